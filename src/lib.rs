@@ -1 +1,4 @@
-mod openbook;
\ No newline at end of file
+pub mod openbook;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,76 @@
+use solana_sdk::pubkey::Pubkey;
+
+use super::error::DecodeError;
+use super::market::{
+    check_account_padding, AccountFlags, MarketLayout, MarketState, ACCOUNT_HEAD_PADDING,
+};
+use super::order::{OpenOrdersLayout, OpenOrdersState};
+use super::queue::{
+    decode_event_queue, decode_request_queue, Event, EventQueueHeader, Request, RequestQueueHeader,
+};
+use super::registry::ProgramRegistry;
+use super::slab::{read_u64, Slab};
+
+const ACCOUNT_FLAGS_OFFSET: usize = ACCOUNT_HEAD_PADDING.len();
+
+/// Any account owned by a DEX program, decoded according to its flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexAccount {
+    Market(Box<MarketState>),
+    OpenOrders(Box<OpenOrdersState>),
+    RequestQueue {
+        header: RequestQueueHeader,
+        requests: Vec<Request>,
+    },
+    EventQueue {
+        header: EventQueueHeader,
+        events: Vec<Event>,
+    },
+    Bids(Slab),
+    Asks(Slab),
+}
+
+/// Decodes `data` owned by `program_id` without knowing its account type up
+/// front. The type comes from the account flags; market and open orders
+/// layouts are picked from the program's layout version and the data length.
+/// Queues decode their pending entries only.
+pub fn decode_any(program_id: &Pubkey, data: &[u8]) -> Result<DexAccount, DecodeError> {
+    check_account_padding(data)?;
+    let min_len = ACCOUNT_FLAGS_OFFSET + 8;
+    if data.len() < min_len {
+        return Err(DecodeError::InvalidLength {
+            expected: min_len,
+            actual: data.len(),
+        });
+    }
+    let account_flags = AccountFlags::from_raw(read_u64(data, ACCOUNT_FLAGS_OFFSET))?;
+    let version = ProgramRegistry::global().layout_version(program_id);
+
+    let account_type = account_flags & AccountFlags::ACCOUNT_TYPES;
+    if account_type == AccountFlags::MARKET {
+        let layout = MarketLayout::for_account(version, data.len());
+        MarketState::decode(data, layout).map(|state| DexAccount::Market(Box::new(state)))
+    } else if account_type == AccountFlags::OPEN_ORDERS {
+        let layout = OpenOrdersLayout::for_version(version);
+        OpenOrdersState::decode(data, layout).map(|state| DexAccount::OpenOrders(Box::new(state)))
+    } else if account_type == AccountFlags::REQUEST_QUEUE {
+        Ok(DexAccount::RequestQueue {
+            header: RequestQueueHeader::decode(data)?,
+            requests: decode_request_queue(data, None)?,
+        })
+    } else if account_type == AccountFlags::EVENT_QUEUE {
+        Ok(DexAccount::EventQueue {
+            header: EventQueueHeader::decode(data)?,
+            events: decode_event_queue(data, None)?,
+        })
+    } else if account_type == AccountFlags::BIDS || account_type == AccountFlags::ASKS {
+        let (account_flags, slab) = Slab::decode_account(data)?;
+        if account_flags.contains(AccountFlags::BIDS) {
+            Ok(DexAccount::Bids(slab))
+        } else {
+            Ok(DexAccount::Asks(slab))
+        }
+    } else {
+        Err(DecodeError::UnknownAccountType(account_flags))
+    }
+}
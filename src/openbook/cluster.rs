@@ -0,0 +1,123 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey, pubkey::Pubkey};
+
+use super::registry::{ProgramInfo, ProgramRegistry};
+
+pub const WRAPPED_SOL_MINT: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+pub const SRM_MINT: Pubkey = pubkey!("SRMuApVNdxXokk5GT7XD5cUUgXMBCoAz2LHeuAoKWRt");
+pub const MSRM_MINT: Pubkey = pubkey!("MSRMcoVyrFxnSgo5uXwone5SKcGhT1KEJMFEkMEWf9L");
+
+/// A market listed for a cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketInfo {
+    pub name: String,
+    pub address: Pubkey,
+    pub program_id: Pubkey,
+    pub deprecated: bool,
+}
+
+/// Everything that changes between environments: where to connect, which
+/// DEX programs are deployed and the mints and markets that exist there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    pub name: String,
+    pub rpc_url: String,
+    pub ws_url: String,
+    /// DEX programs deployed on the cluster. The first one is the default
+    /// for markets that are not listed.
+    pub programs: Vec<ProgramInfo>,
+    pub wrapped_sol_mint: Pubkey,
+    /// SRM and MSRM only exist on mainnet, where holding them lowers fees.
+    pub srm_mint: Option<Pubkey>,
+    pub msrm_mint: Option<Pubkey>,
+    pub markets: Vec<MarketInfo>,
+}
+
+impl Cluster {
+    pub fn mainnet() -> Self {
+        let openbook = pubkey!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
+        Cluster {
+            name: "mainnet-beta".to_string(),
+            rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
+            ws_url: "wss://api.mainnet-beta.solana.com".to_string(),
+            programs: vec![
+                ProgramInfo::new(openbook, 3),
+                ProgramInfo::new(pubkey!("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o"), 2),
+                ProgramInfo::new(pubkey!("BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg"), 1),
+                ProgramInfo::new(pubkey!("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn"), 1),
+            ],
+            wrapped_sol_mint: WRAPPED_SOL_MINT,
+            srm_mint: Some(SRM_MINT),
+            msrm_mint: Some(MSRM_MINT),
+            markets: vec![MarketInfo {
+                name: "SOL/USDC".to_string(),
+                address: pubkey!("8BnEgHoWFysVcuFFX7QztDmzuH8r5ZFvyP3sYwn1XTh6"),
+                program_id: openbook,
+                deprecated: false,
+            }],
+        }
+    }
+
+    pub fn devnet() -> Self {
+        Cluster {
+            name: "devnet".to_string(),
+            rpc_url: "https://api.devnet.solana.com".to_string(),
+            ws_url: "wss://api.devnet.solana.com".to_string(),
+            programs: vec![ProgramInfo::new(
+                pubkey!("EoTcMgcDRTJVZDMZWBoU6rhYHZfkNTVEAfz3uUJRcYGj"),
+                3,
+            )],
+            wrapped_sol_mint: WRAPPED_SOL_MINT,
+            srm_mint: None,
+            msrm_mint: None,
+            markets: Vec::new(),
+        }
+    }
+
+    /// A local validator with the DEX deployed at `program_id`.
+    pub fn localnet(program_id: Pubkey) -> Self {
+        Cluster {
+            name: "localnet".to_string(),
+            rpc_url: "http://127.0.0.1:8899".to_string(),
+            ws_url: "ws://127.0.0.1:8900".to_string(),
+            programs: vec![ProgramInfo::new(program_id, 3)],
+            wrapped_sol_mint: WRAPPED_SOL_MINT,
+            srm_mint: None,
+            msrm_mint: None,
+            markets: Vec::new(),
+        }
+    }
+
+    pub fn rpc_client(&self) -> RpcClient {
+        RpcClient::new(self.rpc_url.clone())
+    }
+
+    /// The default DEX program of the cluster.
+    pub fn program_id(&self) -> Pubkey {
+        self.programs[0].program_id
+    }
+
+    /// The program of a listed market, or the default program.
+    pub fn program_id_for_market(&self, address: &Pubkey) -> Pubkey {
+        self.market_by_address(address)
+            .map_or_else(|| self.program_id(), |market| market.program_id)
+    }
+
+    pub fn market(&self, name: &str) -> Option<&MarketInfo> {
+        self.markets.iter().find(|market| market.name == name)
+    }
+
+    pub fn market_by_address(&self, address: &Pubkey) -> Option<&MarketInfo> {
+        self.markets
+            .iter()
+            .find(|market| market.address == *address)
+    }
+
+    /// Adds the cluster's programs to `registry`, so their accounts and
+    /// instructions are recognized.
+    pub fn register_programs(&self, registry: &ProgramRegistry) {
+        for program in &self.programs {
+            registry.register(*program);
+        }
+    }
+}
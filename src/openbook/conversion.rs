@@ -0,0 +1,250 @@
+use rust_decimal::Decimal;
+
+use super::order::Side;
+use super::units::{BaseLots, NativeBase, NativeQuote, PriceLots, QuoteLots};
+
+/// Largest scale a `Decimal` can hold.
+const MAX_SCALE: u32 = 28;
+
+/// Largest mantissa a `Decimal` can hold, 2^96 - 1.
+const MAX_MANTISSA: u128 = (1 << 96) - 1;
+
+/// How a conversion treats a value that falls between two units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards zero.
+    Floor,
+    /// Away from zero.
+    Ceil,
+    /// To the nearest unit, halves away from zero.
+    Nearest,
+}
+
+impl Rounding {
+    /// The rounding that keeps a limit price on the user's side of their
+    /// limit: bids round down and asks round up, so neither crosses further
+    /// into the book than asked for.
+    pub fn for_limit_price(side: Side) -> Self {
+        match side {
+            Side::Buy => Rounding::Floor,
+            Side::Sell => Rounding::Ceil,
+        }
+    }
+
+    /// `numerator / denominator` rounded with `self`, or `None` for a zero
+    /// denominator.
+    pub fn div(self, numerator: u128, denominator: u128) -> Option<u128> {
+        if denominator == 0 {
+            return None;
+        }
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        let round_up = match self {
+            Rounding::Floor => false,
+            Rounding::Ceil => remainder > 0,
+            Rounding::Nearest => remainder >= denominator - remainder,
+        };
+        Some(quotient + round_up as u128)
+    }
+}
+
+/// Exact conversions between the UI values of a market (tokens, and quote
+/// tokens per base token), native token amounts and lots. This is the only
+/// place the unit types are converted into one another.
+///
+/// All arithmetic is done on integers. Values that do not fall on a unit are
+/// rounded as asked, and conversions return `None` instead of overflowing or
+/// for negative inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LotConversion {
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+impl LotConversion {
+    pub fn new(
+        base_lot_size: u64,
+        quote_lot_size: u64,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Self {
+        LotConversion {
+            base_lot_size,
+            quote_lot_size,
+            base_decimals,
+            quote_decimals,
+        }
+    }
+
+    /// A price lot is `quote_lot_size` native quote per `base_lot_size`
+    /// native base.
+    pub fn price_number_to_lots(&self, price: Decimal, rounding: Rounding) -> Option<PriceLots> {
+        let (mantissa, scale) = unsigned_parts(price)?;
+        let numerator = mantissa
+            .checked_mul(pow10(self.quote_decimals.into())?)?
+            .checked_mul(self.base_lot_size.into())?;
+        let denominator = pow10(scale + u32::from(self.base_decimals))?
+            .checked_mul(self.quote_lot_size.into())?;
+        to_u64(rounding.div(numerator, denominator)?).map(PriceLots::new)
+    }
+
+    pub fn price_lots_to_number(&self, price: PriceLots, rounding: Rounding) -> Option<Decimal> {
+        let numerator = u128::from(price.get())
+            .checked_mul(self.quote_lot_size.into())?
+            .checked_mul(pow10(self.base_decimals.into())?)?;
+        let denominator =
+            u128::from(self.base_lot_size).checked_mul(pow10(self.quote_decimals.into())?)?;
+        ratio_to_decimal(numerator, denominator, rounding)
+    }
+
+    pub fn base_size_number_to_lots(&self, size: Decimal, rounding: Rounding) -> Option<BaseLots> {
+        let lots = number_to_units(size, self.base_decimals, self.base_lot_size, rounding)?;
+        to_u64(lots).map(BaseLots::new)
+    }
+
+    pub fn base_size_lots_to_number(&self, size: BaseLots, rounding: Rounding) -> Option<Decimal> {
+        let native = self.base_lots_to_native(size)?;
+        self.base_spl_size_to_number(native, rounding)
+    }
+
+    pub fn quote_size_number_to_lots(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<QuoteLots> {
+        let lots = number_to_units(size, self.quote_decimals, self.quote_lot_size, rounding)?;
+        to_u64(lots).map(QuoteLots::new)
+    }
+
+    pub fn quote_size_lots_to_number(
+        &self,
+        size: QuoteLots,
+        rounding: Rounding,
+    ) -> Option<Decimal> {
+        let native = self.quote_lots_to_native(size)?;
+        self.quote_spl_size_to_number(native, rounding)
+    }
+
+    pub fn base_size_number_to_spl_size(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<NativeBase> {
+        to_u64(number_to_units(size, self.base_decimals, 1, rounding)?).map(NativeBase::new)
+    }
+
+    pub fn base_spl_size_to_number(&self, size: NativeBase, rounding: Rounding) -> Option<Decimal> {
+        ratio_to_decimal(
+            size.get().into(),
+            pow10(self.base_decimals.into())?,
+            rounding,
+        )
+    }
+
+    pub fn quote_size_number_to_spl_size(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<NativeQuote> {
+        to_u64(number_to_units(size, self.quote_decimals, 1, rounding)?).map(NativeQuote::new)
+    }
+
+    pub fn quote_spl_size_to_number(
+        &self,
+        size: NativeQuote,
+        rounding: Rounding,
+    ) -> Option<Decimal> {
+        ratio_to_decimal(
+            size.get().into(),
+            pow10(self.quote_decimals.into())?,
+            rounding,
+        )
+    }
+
+    /// The price of a fill, in quote tokens per base token, from the native
+    /// amounts that changed hands.
+    pub fn native_price_to_number(
+        &self,
+        native_quote: NativeQuote,
+        native_base: NativeBase,
+        rounding: Rounding,
+    ) -> Option<Decimal> {
+        let numerator =
+            u128::from(native_quote.get()).checked_mul(pow10(self.base_decimals.into())?)?;
+        let denominator =
+            u128::from(native_base.get()).checked_mul(pow10(self.quote_decimals.into())?)?;
+        ratio_to_decimal(numerator, denominator, rounding)
+    }
+
+    pub fn base_lots_to_native(&self, size: BaseLots) -> Option<NativeBase> {
+        size.get()
+            .checked_mul(self.base_lot_size)
+            .map(NativeBase::new)
+    }
+
+    pub fn native_base_to_lots(&self, size: NativeBase, rounding: Rounding) -> Option<BaseLots> {
+        to_u64(rounding.div(size.get().into(), self.base_lot_size.into())?).map(BaseLots::new)
+    }
+
+    pub fn quote_lots_to_native(&self, size: QuoteLots) -> Option<NativeQuote> {
+        size.get()
+            .checked_mul(self.quote_lot_size)
+            .map(NativeQuote::new)
+    }
+
+    pub fn native_quote_to_lots(&self, size: NativeQuote, rounding: Rounding) -> Option<QuoteLots> {
+        to_u64(rounding.div(size.get().into(), self.quote_lot_size.into())?).map(QuoteLots::new)
+    }
+
+    /// Quote lots paid for `size` at `price`, before fees. A price lot is one
+    /// quote lot per base lot.
+    pub fn quote_lots_for(&self, price: PriceLots, size: BaseLots) -> Option<QuoteLots> {
+        price.get().checked_mul(size.get()).map(QuoteLots::new)
+    }
+
+    /// Native quote paid for `size` at `price`, before fees.
+    pub fn native_quote_for(&self, price: PriceLots, size: BaseLots) -> Option<NativeQuote> {
+        self.quote_lots_to_native(self.quote_lots_for(price, size)?)
+    }
+}
+
+fn pow10(exponent: u32) -> Option<u128> {
+    10u128.checked_pow(exponent)
+}
+
+fn to_u64(value: u128) -> Option<u64> {
+    u64::try_from(value).ok()
+}
+
+fn unsigned_parts(value: Decimal) -> Option<(u128, u32)> {
+    if value.is_sign_negative() && !value.is_zero() {
+        return None;
+    }
+    Some((value.mantissa().unsigned_abs(), value.scale()))
+}
+
+/// `value` tokens of a mint with `decimals` in units of `unit` native tokens.
+fn number_to_units(value: Decimal, decimals: u8, unit: u64, rounding: Rounding) -> Option<u128> {
+    let (mantissa, scale) = unsigned_parts(value)?;
+    let numerator = mantissa.checked_mul(pow10(decimals.into())?)?;
+    rounding.div(numerator, pow10(scale)?.checked_mul(unit.into())?)
+}
+
+/// `numerator / denominator` at the largest scale that fits, so ratios with
+/// a terminating decimal expansion of up to 28 digits come out exact and the
+/// others are rounded in the last digit.
+fn ratio_to_decimal(numerator: u128, denominator: u128, rounding: Rounding) -> Option<Decimal> {
+    for scale in (0..=MAX_SCALE).rev() {
+        let scaled = match numerator.checked_mul(pow10(scale)?) {
+            Some(scaled) => scaled,
+            None => continue,
+        };
+        let mantissa = rounding.div(scaled, denominator)?;
+        if mantissa <= MAX_MANTISSA {
+            return Some(Decimal::from_i128_with_scale(mantissa as i128, scale).normalize());
+        }
+    }
+    None
+}
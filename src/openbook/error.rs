@@ -1,40 +1,32 @@
 use num_enum::TryFromPrimitive;
-use std::collections::HashMap;
-use num_enum::{FromPrimitive, IntoPrimitive};
 use thiserror::Error;
-use std::collections::HashMap;
-use std::io::Error;
 
-use solana_sdk::{
-    transaction::Transaction,
+use num_traits::FromPrimitive;
+use solana_client::{
+    client_error::{reqwest::StatusCode, ClientError, ClientErrorKind},
+    rpc_custom_error::{
+        JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    },
+    rpc_request::{RpcError, RpcResponseErrorData},
 };
-use solana_program::{
+use solana_sdk::{
+    instruction::InstructionError,
     program_error::ProgramError,
+    pubkey::{ParsePubkeyError, Pubkey, PubkeyError},
+    signer::SignerError,
+    system_instruction::SystemError,
+    system_program,
+    transaction::{TransactionError, VersionedTransaction},
 };
+use spl_token::error::TokenError;
 
+use super::instruction::TOKEN_PROGRAM_ID;
+use super::logs::{parse_logs, DexLogError, InstructionLogs};
+use super::market::AccountFlags;
+use super::registry::ProgramRegistry;
 
-lazy_static::lazy_static! {
-    static ref PROGRAM_LAYOUT_VERSIONS: HashMap<String, u8> = {
-        let mut map = HashMap::new();
-        map.insert("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn".to_string(), 1);
-        map.insert("BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg".to_string(), 1);
-        map.insert("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o".to_string(), 2);
-        map.insert("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX".to_string(), 3);
-        map
-    };
-    static ref KNOWN_PROGRAMS: HashMap<&'static str, &'static str> = {
-        let mut map = HashMap::new();
-        map.insert(TOKEN_PROGRAM_ID, "Token program");
-        map.insert(SYSTEM_PROGRAM_ID, "System program");
-        map
-    };
-}
-
-fn get_layout_version(program_id: &Pubkey) -> u8 {
-    PROGRAM_LAYOUT_VERSIONS.get(&program_id.to_string()).cloned().unwrap_or(3)
-}
-
-#[derive(Debug, PartialEq, Eq, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u32)]
 pub enum DexError {
     InvalidMarketFlags = 0,
@@ -113,60 +105,461 @@ pub enum DexError {
     OrderNotYours,
 
     WouldSelfTrade,
+    InvalidOpenOrdersAuthority,
+    OrderMaxTimestampExceeded,
 
     Unknown = 1000,
+    /// A failed `check_assert!` in the program, located by `file_id` and
+    /// `line` on the `DexErrorCode`.
+    AssertionError,
 }
 
-const TOKEN_PROGRAM_ID: &str = "Token program";
-const SYSTEM_PROGRAM_ID: &str = "System program";
+impl DexError {
+    pub fn class(self) -> ErrorClass {
+        use DexError::*;
 
-#[derive(Debug)]
-struct CustomError {
-    custom: u32,
+        match self {
+            RequestQueueFull | EventQueueFull => ErrorClass::RetryAfterCrank,
+            InvalidMarketFlags
+            | InvalidAskFlags
+            | InvalidBidFlags
+            | OwnerAccountNotProvided
+            | WrongCoinVault
+            | WrongPcVault
+            | WrongCoinMint
+            | WrongPcMint
+            | WrongFeeDiscountAccountOwner
+            | WrongFeeDiscountMint
+            | WrongOrdersAccount
+            | WrongBidsAccount
+            | WrongAsksAccount
+            | WrongRequestQueueAccount
+            | WrongEventQueueAccount
+            | MarketIsDisabled
+            | WrongSigner
+            | ClientOrderIdIsZero
+            | WrongRentSysvarAccount
+            | RentNotProvided
+            | OrderNotYours
+            | InvalidOpenOrdersAuthority
+            | OrderMaxTimestampExceeded => ErrorClass::Fatal,
+            _ => ErrorClass::Unclassified,
+        }
+    }
+}
+
+/// How a caller should react to an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Transient, so sending the same request again may succeed.
+    Retryable,
+    /// The market's request or event queue is full. Retrying helps once the
+    /// queue has been cranked.
+    RetryAfterCrank,
+    /// Sending the same request again fails the same way.
+    Fatal,
+    /// The transaction was sent but not seen confirmed, so it may still
+    /// land. Check its signature status before sending it again, or the
+    /// request may be executed twice.
+    CheckStatus,
+    /// Depends on state the caller controls, such as balances or open
+    /// orders.
+    Unclassified,
+}
+
+/// A DEX custom error code. The program returns the error in the low 16
+/// bits and the source line that raised it in the upper 16. Failed
+/// assertions instead put the id of the source file in the top byte and the
+/// line in the low 16 bits, leaving the byte between them zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DexErrorCode {
+    pub code: u32,
+    /// `DexError::Unknown` for codes this crate does not know.
+    pub error: DexError,
+    pub line: Option<u16>,
+    /// The program source file of a `DexError::AssertionError`.
+    pub file_id: Option<u8>,
+}
+
+impl DexErrorCode {
+    pub fn from_custom(code: u32) -> Self {
+        let file_id = (code >> 24) as u8;
+        if file_id != 0 && (code >> 16) & 0xff == 0 {
+            return DexErrorCode {
+                code,
+                error: DexError::AssertionError,
+                line: Some(code as u16),
+                file_id: Some(file_id),
+            };
+        }
+        let line = (code >> 16) as u16;
+        DexErrorCode {
+            code,
+            error: DexError::try_from(code & 0xffff).unwrap_or(DexError::Unknown),
+            line: if line == 0 { None } else { Some(line) },
+            file_id: None,
+        }
+    }
 }
 
-#[derive(Debug)]
-enum InstructionError {
+/// Why an instruction failed, decoded for the program that raised it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramFailure {
+    Dex(DexErrorCode),
+    Token(TokenError),
+    System(SystemError),
+    /// Custom error of a program this crate does not know, or a code its
+    /// program does not define.
     Custom(u32),
+    /// Failures raised by the runtime rather than the program, such as a
+    /// missing signature.
+    Instruction(InstructionError),
 }
 
-fn parse_instruction_error_response(
-    transaction: &Transaction,
-    error_response: &InstructionError,
-) -> (usize, String, String) {
-    let failed_instruction_index: usize = match *error_response {
-        InstructionError::Custom(custom_error) => custom_error as usize,
+impl ProgramFailure {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            ProgramFailure::Dex(code) => code.error.class(),
+            ProgramFailure::Instruction(InstructionError::MissingRequiredSignature) => {
+                ErrorClass::Fatal
+            }
+            _ => ErrorClass::Unclassified,
+        }
+    }
+}
+
+/// The instruction a transaction failed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionFailure {
+    pub index: usize,
+    pub program_id: Pubkey,
+    pub failure: ProgramFailure,
+    /// Panics and error lines the DEX logged while running the instruction.
+    /// A panic fails the instruction without a custom code, so these are
+    /// often the only detail available.
+    pub log_errors: Vec<DexLogError>,
+}
+
+impl InstructionFailure {
+    /// Attaches the DEX errors logged by the failing instruction, from the
+    /// transaction's logs as split by `parse_logs`.
+    pub fn with_logs(mut self, logs: &[InstructionLogs]) -> Self {
+        if let Some(logs) = logs.iter().find(|logs| logs.index == self.index) {
+            self.log_errors = logs.dex_errors();
+        }
+        self
+    }
+}
+
+/// Finds the failing instruction of `transaction` from the error returned by
+/// `sendTransaction` or a simulation. Returns `None` for errors that are not
+/// tied to an instruction of the transaction. The logged details are added
+/// with `InstructionFailure::with_logs`.
+pub fn parse_instruction_error(
+    transaction: &VersionedTransaction,
+    error: &TransactionError,
+) -> Option<InstructionFailure> {
+    let (index, error) = match error {
+        TransactionError::InstructionError(index, error) => (*index as usize, error),
+        _ => return None,
     };
+    let message = &transaction.message;
+    let instruction = message.instructions().get(index)?;
+    let program_id = *message
+        .static_account_keys()
+        .get(instruction.program_id_index as usize)?;
 
-    let failed_instruction = transaction.instructions.get(failed_instruction_index);
-    let parsed_error: String;
-    let failed_program: String;
-
-    match failed_instruction {
-        Some(instruction) => {
-            let program_id = instruction.program_id.to_string();
-            if PROGRAM_LAYOUT_VERSIONS.contains_key(&program_id) {
-                parsed_error = format!(
-                    "{}",
-                    PROGRAM_LAYOUT_VERSIONS[&program_id]
-                );
-            } else if KNOWN_PROGRAMS.contains_key(&program_id) {
-                let program = KNOWN_PROGRAMS[&program_id];
-                parsed_error = format!("{} error {}", program, failed_instruction_index);
-            } else {
-                parsed_error = format!(
-                    "Unknown program {} custom error: {}",
-                    program_id,
-                    failed_instruction_index
-                );
+    let failure = match *error {
+        InstructionError::Custom(code) if ProgramRegistry::global().is_known(&program_id) => {
+            ProgramFailure::Dex(DexErrorCode::from_custom(code))
+        }
+        InstructionError::Custom(code) if program_id == TOKEN_PROGRAM_ID => {
+            TokenError::from_u32(code).map_or(ProgramFailure::Custom(code), ProgramFailure::Token)
+        }
+        InstructionError::Custom(code) if program_id == system_program::id() => {
+            SystemError::from_u32(code).map_or(ProgramFailure::Custom(code), ProgramFailure::System)
+        }
+        InstructionError::Custom(code) => ProgramFailure::Custom(code),
+        ref error => ProgramFailure::Instruction(error.clone()),
+    };
+    Some(InstructionFailure {
+        index,
+        program_id,
+        failure,
+        log_errors: Vec::new(),
+    })
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("invalid account length: expected {expected} bytes, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("account head padding is not \"serum\"")]
+    InvalidHeadPadding,
+    #[error("account tail padding is not \"padding\"")]
+    InvalidTailPadding,
+    #[error("unknown layout field: {0}")]
+    UnknownField(String),
+    #[error("slab bump index {bump_index} exceeds node capacity {capacity}")]
+    SlabOverflow { bump_index: u32, capacity: usize },
+    #[error("invalid slab node tag {tag} at offset {offset}")]
+    InvalidNodeTag { tag: u32, offset: usize },
+    #[error("queue head {head} and count {count} do not fit in {capacity} slots")]
+    QueueOverflow {
+        head: u32,
+        count: u32,
+        capacity: usize,
+    },
+    #[error("request flags {0:#04x} are neither a new order nor a cancel")]
+    InvalidRequestFlags(u8),
+    #[error("account flags {0:#x} contain undefined bits")]
+    UnknownAccountFlags(u64),
+    #[error("expected an initialized {expected:?} account, found flags {actual:?}")]
+    WrongAccountType {
+        expected: AccountFlags,
+        actual: AccountFlags,
+    },
+    #[error("account flags {0:?} do not name a DEX account type")]
+    UnknownAccountType(AccountFlags),
+    #[error("unsupported instruction version {0}")]
+    UnsupportedInstructionVersion(u8),
+    #[error("unknown instruction tag {0}")]
+    UnknownInstructionTag(u32),
+    #[error("instruction tag {tag} has an invalid payload length of {actual} bytes")]
+    InvalidInstructionLength { tag: u32, actual: usize },
+    #[error("invalid {field} value {value} in instruction data")]
+    InvalidInstructionField { field: &'static str, value: u32 },
+    #[error("account index {index} is out of range for {len} transaction accounts")]
+    AccountIndexOutOfRange { index: u8, len: usize },
+    #[error("address lookups name {expected} accounts but {actual} loaded addresses were given")]
+    LoadedAddressesMismatch { expected: usize, actual: usize },
+}
+
+impl DecodeError {
+    /// Shifts the offset carried by the error by `base`, for errors raised
+    /// while decoding a sub-slice of an account.
+    pub fn at_offset(self, base: usize) -> Self {
+        match self {
+            DecodeError::InvalidNodeTag { tag, offset } => DecodeError::InvalidNodeTag {
+                tag,
+                offset: base + offset,
+            },
+            err => err,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EncodeError {
+    #[error("duplicate order key {0:#x}")]
+    DuplicateKey(u128),
+    #[error("{required} nodes do not fit in a capacity of {capacity}")]
+    CapacityExceeded { required: usize, capacity: usize },
+    #[error("{count} client ids given, at most {max} can be cancelled at once")]
+    TooManyClientIds { count: usize, max: usize },
+}
+
+/// Errors returned by the SDK. Decode errors name the account they were
+/// raised for; offsets within it are carried by the `DecodeError`.
+#[derive(Debug, Error)]
+pub enum SdkError {
+    #[error("RPC request failed: {0}")]
+    Rpc(Box<ClientError>),
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+    #[error("account {account} is owned by {owner}, expected {expected}")]
+    InvalidAccountOwner {
+        account: Pubkey,
+        owner: Pubkey,
+        expected: Pubkey,
+    },
+    #[error("failed to decode account {account}: {source}")]
+    Decode {
+        account: Pubkey,
+        #[source]
+        source: DecodeError,
+    },
+    #[error("invalid account {account}: {reason}")]
+    InvalidAccount {
+        account: Pubkey,
+        reason: &'static str,
+    },
+    #[error("invalid account layout: {0}")]
+    Layout(DecodeError),
+    #[error("program {program_id} does not support {feature}")]
+    Unsupported {
+        program_id: Pubkey,
+        feature: &'static str,
+    },
+    #[error("instruction {} of program {} failed: {:?}", .0.index, .0.program_id, .0.failure)]
+    InstructionFailed(Box<InstructionFailure>),
+    #[error("transaction failed: {0}")]
+    Transaction(TransactionError),
+    /// The transaction with this signature was sent but not confirmed in
+    /// time. It may still land; see `ErrorClass::CheckStatus`.
+    #[error("transaction {0} was not confirmed")]
+    Unconfirmed(String),
+    #[error("invalid order: {0}")]
+    InvalidOrder(&'static str),
+    #[error("failed to encode instruction: {0}")]
+    Encode(#[from] EncodeError),
+    #[error("invalid market list: {0}")]
+    MarketList(#[from] serde_json::Error),
+    #[error(transparent)]
+    InvalidPubkey(#[from] ParsePubkeyError),
+    #[error(transparent)]
+    Pubkey(#[from] PubkeyError),
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+    #[error("failed to sign transaction: {0}")]
+    Signer(#[from] SignerError),
+}
+
+impl SdkError {
+    pub fn decode(account: Pubkey, source: DecodeError) -> Self {
+        SdkError::Decode { account, source }
+    }
+
+    /// Wraps the error of a sent or simulated transaction, decoding the
+    /// failing instruction when the error names one.
+    pub fn from_transaction_error(
+        transaction: &VersionedTransaction,
+        error: TransactionError,
+    ) -> Self {
+        match parse_instruction_error(transaction, &error) {
+            Some(failure) => SdkError::InstructionFailed(Box::new(failure)),
+            None => SdkError::Transaction(error),
+        }
+    }
+
+    /// Wraps an error from sending `transaction`. Preflight failures are
+    /// decoded like `from_transaction_error`, with the simulation logs
+    /// attached.
+    pub fn from_send_error(transaction: &VersionedTransaction, error: ClientError) -> Self {
+        if let ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) = error.kind()
+        {
+            let failure = result
+                .err
+                .as_ref()
+                .and_then(|err| parse_instruction_error(transaction, err));
+            if let Some(failure) = failure {
+                let logs = parse_logs(result.logs.as_deref().unwrap_or_default());
+                return SdkError::InstructionFailed(Box::new(failure.with_logs(&logs)));
             }
-            failed_program = program_id;
         }
-        None => {
-            parsed_error = "Failed instruction not found".to_string();
-            failed_program = String::new();
+        SdkError::from(error)
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            SdkError::Rpc(error) => client_error_class(error),
+            SdkError::InstructionFailed(failure) => failure.failure.class(),
+            SdkError::Transaction(error) => transaction_error_class(error),
+            SdkError::Unconfirmed(_) => ErrorClass::CheckStatus,
+            SdkError::AccountNotFound(_) => ErrorClass::Unclassified,
+            SdkError::InvalidAccountOwner { .. }
+            | SdkError::Decode { .. }
+            | SdkError::InvalidAccount { .. }
+            | SdkError::Layout(_)
+            | SdkError::Unsupported { .. }
+            | SdkError::InvalidOrder(_)
+            | SdkError::Encode(_)
+            | SdkError::MarketList(_)
+            | SdkError::InvalidPubkey(_)
+            | SdkError::Pubkey(_)
+            | SdkError::Program(_)
+            | SdkError::Signer(_) => ErrorClass::Fatal,
+        }
+    }
+
+    /// Whether sending the same request again may succeed right away.
+    /// Errors that need the market cranked first are not retryable; see
+    /// `needs_crank`.
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    pub fn needs_crank(&self) -> bool {
+        self.class() == ErrorClass::RetryAfterCrank
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.class() == ErrorClass::Fatal
+    }
+
+    /// Whether the transaction may have landed despite the error, so its
+    /// status has to be checked before it is sent again.
+    pub fn needs_status_check(&self) -> bool {
+        self.class() == ErrorClass::CheckStatus
+    }
+
+    /// The DEX error behind a failed instruction, if the DEX raised one.
+    pub fn dex_error(&self) -> Option<DexError> {
+        match self {
+            SdkError::InstructionFailed(failure) => match failure.failure {
+                ProgramFailure::Dex(code) => Some(code.error),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<ClientError> for SdkError {
+    fn from(error: ClientError) -> Self {
+        SdkError::Rpc(Box::new(error))
+    }
+}
+
+/// Preflight failures without a transaction to decode them against are
+/// classified by their transaction error alone.
+fn client_error_class(error: &ClientError) -> ErrorClass {
+    match error.kind() {
+        ClientErrorKind::Io(_) => ErrorClass::Retryable,
+        ClientErrorKind::Reqwest(error) => {
+            let busy = error.status().is_some_and(|status| {
+                status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            });
+            if busy || error.is_timeout() || error.is_connect() {
+                ErrorClass::Retryable
+            } else {
+                ErrorClass::Unclassified
+            }
         }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, data, .. }) => match data {
+            RpcResponseErrorData::SendTransactionPreflightFailure(result) => result
+                .err
+                .as_ref()
+                .map_or(ErrorClass::Unclassified, transaction_error_class),
+            RpcResponseErrorData::NodeUnhealthy { .. } => ErrorClass::Retryable,
+            RpcResponseErrorData::Empty => match *code {
+                JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+                | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED => ErrorClass::Retryable,
+                _ => ErrorClass::Unclassified,
+            },
+        },
+        ClientErrorKind::TransactionError(error) => transaction_error_class(error),
+        _ => ErrorClass::Unclassified,
     }
+}
 
-    (failed_instruction_index, parsed_error, failed_program)
+fn transaction_error_class(error: &TransactionError) -> ErrorClass {
+    match error {
+        TransactionError::BlockhashNotFound
+        | TransactionError::AccountInUse
+        | TransactionError::ClusterMaintenance
+        | TransactionError::WouldExceedMaxBlockCostLimit
+        | TransactionError::WouldExceedMaxAccountCostLimit
+        | TransactionError::WouldExceedAccountDataBlockLimit => ErrorClass::Retryable,
+        TransactionError::SignatureFailure
+        | TransactionError::MissingSignatureForFee
+        | TransactionError::InvalidAccountForFee
+        | TransactionError::AlreadyProcessed
+        | TransactionError::UnsupportedVersion => ErrorClass::Fatal,
+        _ => ErrorClass::Unclassified,
+    }
 }
@@ -0,0 +1,194 @@
+use rust_decimal::Decimal;
+
+use super::conversion::Rounding;
+use super::instruction::{Instruction, NewOrderV3};
+use super::market::Market;
+use super::order::Side;
+use super::order_id::OrderId;
+use super::transaction::{AccountRole, DecodedInstruction};
+use super::units::{BaseLots, NativeQuote, PriceLots};
+
+/// Describes DEX instructions in the units of one market, for logs and
+/// review tools, e.g.
+/// `NewOrderV3 BUY 12.5 SOL @ 23.41 USDC, PostOnly, client_id=42, STP=CancelProvide`.
+pub struct Explainer<'a> {
+    market: &'a Market,
+    base_symbol: &'a str,
+    quote_symbol: &'a str,
+}
+
+impl<'a> Explainer<'a> {
+    pub fn new(market: &'a Market, base_symbol: &'a str, quote_symbol: &'a str) -> Self {
+        Explainer {
+            market,
+            base_symbol,
+            quote_symbol,
+        }
+    }
+
+    pub fn explain(&self, instruction: &Instruction) -> String {
+        let name = instruction.name();
+        match instruction {
+            Instruction::InitializeMarket(params) => format!(
+                "{} base_lot_size={}, quote_lot_size={}, fee_rate_bps={}, quote_dust_threshold={}",
+                name,
+                params.base_lot_size,
+                params.quote_lot_size,
+                params.fee_rate_bps,
+                params.quote_dust_threshold
+            ),
+            Instruction::NewOrder(order) => format!(
+                "{} {}, {:?}, client_id={}",
+                name,
+                self.order(order.side, order.max_quantity, order.limit_price),
+                order.order_type,
+                order.client_id
+            ),
+            Instruction::NewOrderV3(order) => format!("{} {}", name, self.new_order_v3(order)),
+            Instruction::ReplaceOrderByClientId(replace) => {
+                format!("{} {}", name, self.new_order_v3(&replace.order))
+            }
+            Instruction::ReplaceOrdersByClientIds(orders) => format!(
+                "{} [{}]",
+                name,
+                orders
+                    .orders
+                    .iter()
+                    .map(|order| self.new_order_v3(order))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+            Instruction::SendTake(take) => {
+                format!(
+                    "{} {}, max_quote={} {}, min_base={} {}, min_quote={} {}, limit={}",
+                    name,
+                    self.order(take.side, take.max_base_quantity, take.limit_price),
+                    number(self.market.quote_spl_size_to_number(
+                        NativeQuote::new(take.max_quote_quantity),
+                        Rounding::Nearest
+                    )),
+                    self.quote_symbol,
+                    number(self.market.base_size_lots_to_number(
+                        BaseLots::new(take.min_base_quantity),
+                        Rounding::Nearest
+                    )),
+                    self.base_symbol,
+                    number(self.market.quote_spl_size_to_number(
+                        NativeQuote::new(take.min_quote_quantity),
+                        Rounding::Nearest
+                    )),
+                    self.quote_symbol,
+                    take.limit
+                )
+            }
+            Instruction::CancelOrder(cancel) => {
+                format!(
+                    "{} {}",
+                    name,
+                    self.cancel(cancel.side, OrderId(cancel.order_id))
+                )
+            }
+            Instruction::CancelOrderV2(cancel) => {
+                format!(
+                    "{} {}",
+                    name,
+                    self.cancel(cancel.side, OrderId(cancel.order_id))
+                )
+            }
+            Instruction::CancelOrderByClientId(cancel) => {
+                format!("{} client_id={}", name, cancel.client_id)
+            }
+            Instruction::CancelOrderByClientIdV2(cancel) => {
+                format!("{} client_id={}", name, cancel.client_id)
+            }
+            // Unused slots of the fixed size array are zero.
+            Instruction::CancelOrdersByClientIds(cancel) => format!(
+                "{} client_ids={:?}",
+                name,
+                cancel
+                    .client_ids
+                    .iter()
+                    .filter(|&&client_id| client_id != 0)
+                    .collect::<Vec<_>>()
+            ),
+            Instruction::MatchOrders(params) => format!("{} limit={}", name, params.limit),
+            Instruction::ConsumeEvents(params) => format!("{} limit={}", name, params.limit),
+            Instruction::ConsumeEventsPermissioned(params) => {
+                format!("{} limit={}", name, params.limit)
+            }
+            Instruction::Prune(params) => format!("{} limit={}", name, params.limit),
+            Instruction::SettleFunds(_)
+            | Instruction::CloseOpenOrders(_)
+            | Instruction::InitOpenOrders(_) => name.to_string(),
+        }
+    }
+
+    /// Explains the instructions of a decoded transaction that act on this
+    /// market, skipping the ones for other markets.
+    pub fn explain_transaction(&self, instructions: &[DecodedInstruction]) -> Vec<String> {
+        let address = self.market.address();
+        instructions
+            .iter()
+            .filter(|decoded| decoded.account(AccountRole::Market) == Some(&address))
+            .filter_map(|decoded| decoded.instruction.known())
+            .map(|instruction| self.explain(instruction))
+            .collect()
+    }
+
+    fn new_order_v3(&self, order: &NewOrderV3) -> String {
+        let mut text = format!(
+            "{}, {:?}, client_id={}, STP={:?}",
+            self.order(order.side, order.max_base_quantity, order.limit_price),
+            order.order_type,
+            order.client_order_id,
+            order.self_trade_behavior
+        );
+        if order.max_ts != i64::MAX {
+            text.push_str(&format!(", max_ts={}", order.max_ts));
+        }
+        text
+    }
+
+    fn order(&self, side: Side, base_lots: u64, price_lots: u64) -> String {
+        format!(
+            "{} {} {} @ {} {}",
+            side_name(side),
+            number(
+                self.market
+                    .base_size_lots_to_number(BaseLots::new(base_lots), Rounding::Nearest)
+            ),
+            self.base_symbol,
+            number(
+                self.market
+                    .price_lots_to_number(PriceLots::new(price_lots), Rounding::Nearest)
+            ),
+            self.quote_symbol
+        )
+    }
+
+    fn cancel(&self, side: Side, order_id: OrderId) -> String {
+        format!(
+            "{} @ {} {}, order_id={:#x}",
+            side_name(side),
+            number(
+                self.market
+                    .price_lots_to_number(order_id.price_lots(), Rounding::Nearest)
+            ),
+            self.quote_symbol,
+            order_id
+        )
+    }
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "BUY",
+        Side::Sell => "SELL",
+    }
+}
+
+/// Values too large to convert are shown as `?` rather than hiding the rest
+/// of the instruction.
+fn number(value: Option<Decimal>) -> String {
+    value.map_or_else(|| "?".to_string(), |value| value.to_string())
+}
@@ -1,51 +1,34 @@
-use crate::error::
-
-use solana_client::{
-    client_error::ClientError, 
-    rpc_client::RpcClient, 
-    rpc_request::RpcRequest,
-    rpc_config::RpcProgramAccountsConfig,
-};
 use solana_sdk::{
-    address_lookup_table::program,
-    account::Account,
-    commitment_config::CommitmentConfig,
+    instruction::{AccountMeta, Instruction as SolanaInstruction},
     pubkey::Pubkey,
-    connection::Connection,
-    transaction::Transaction,
-    program_pack::{Pack, IsInitialized},
-    system_instruction,
-    sysvar::{rent, Sysvar},
-    instruction::{Instruction, SystemProgram},
+    sysvar::rent,
 };
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use std::convert::TryInto;
 
+use super::error::{DecodeError, EncodeError};
+use super::order::{OrderType, SelfTradeBehavior, Side};
+use super::order_id::OrderId;
+use super::units::{BaseLots, NativeQuote, PriceLots};
 
-const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
-    4, 203, 124, 179, 243, 185, 36, 150, 209, 73, 91, 131, 15, 200, 87, 250, 50, 35, 220, 
-    90, 139, 198, 193, 19, 236, 115, 82, 71, 244, 60, 187, 249,
+pub const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172, 28, 180, 133, 237,
+    95, 91, 55, 145, 58, 140, 245, 133, 126, 255, 0, 169,
 ]);
 
-const SETTLE_FUNDS_BASE_WALLET_INDEX: u8 = 5;
-const SETTLE_FUNDS_QUOTE_WALLET_INDEX: u8 = 6;
-
-const NEW_ORDER_OPEN_ORDERS_INDEX: u8 = 1;
-const NEW_ORDER_OWNER_INDEX: u8 = 4;
+pub const SETTLE_FUNDS_BASE_WALLET_INDEX: u8 = 5;
+pub const SETTLE_FUNDS_QUOTE_WALLET_INDEX: u8 = 6;
 
-const NEW_ORDER_V3_OPEN_ORDERS_INDEX: u8 = 1;
-const NEW_ORDER_V3_OWNER_INDEX: u8 = 7;
+pub const NEW_ORDER_OPEN_ORDERS_INDEX: u8 = 1;
+pub const NEW_ORDER_OWNER_INDEX: u8 = 4;
 
-pub struct InstructionLayout {
-    pub instruction: u32,
-}
+pub const NEW_ORDER_V3_OPEN_ORDERS_INDEX: u8 = 1;
+pub const NEW_ORDER_V3_OWNER_INDEX: u8 = 7;
 
-impl InstructionLayout {
-    pub fn new(instruction: u32) -> Self {
-        Self { instruction }
-    }
-}
+/// Limit used for `NewOrderV3` when the caller does not cap matching.
+pub const DEFAULT_MATCH_LIMIT: u16 = u16::MAX;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     InitializeMarket(InitializeMarket),
     NewOrder(NewOrder),
@@ -67,154 +50,1242 @@ pub enum Instruction {
     ReplaceOrdersByClientIds(ReplaceOrdersByClientIds),
 }
 
-impl Pack for Instruction {
-    const LEN: usize = std::mem::size_of::<u32>();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitializeMarket {
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub fee_rate_bps: u16,
+    pub vault_signer_nonce: u64,
+    pub quote_dust_threshold: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewOrder {
+    pub side: Side,
+    pub limit_price: u64,
+    pub max_quantity: u64,
+    pub order_type: OrderType,
+    pub client_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchOrders {
+    pub limit: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumeEvents {
+    pub limit: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOrder {
+    pub side: Side,
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub owner_slot: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettleFunds;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOrderByClientId {
+    pub client_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewOrderV3 {
+    pub side: Side,
+    pub limit_price: u64,
+    pub max_base_quantity: u64,
+    pub max_quote_quantity: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub order_type: OrderType,
+    pub client_order_id: u64,
+    pub limit: u16,
+    pub max_ts: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOrderV2 {
+    pub side: Side,
+    pub order_id: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOrderByClientIdV2 {
+    pub client_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendTake {
+    pub side: Side,
+    pub limit_price: u64,
+    pub max_base_quantity: u64,
+    pub max_quote_quantity: u64,
+    pub min_base_quantity: u64,
+    pub min_quote_quantity: u64,
+    pub limit: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseOpenOrders;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitOpenOrders;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prune {
+    pub limit: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumeEventsPermissioned {
+    pub limit: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelOrdersByClientIds {
+    pub client_ids: [u64; 8],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceOrderByClientId {
+    pub order: NewOrderV3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceOrdersByClientIds {
+    pub orders: Vec<NewOrderV3>,
+}
+
+impl Instruction {
+    /// Tag of the variant in the deployed program. The program also knows
+    /// `DisableMarket` (7), `SweepFees` (8) and `NewOrderV2` (9), which this
+    /// enum leaves out, so the tags are not the variant positions.
+    pub fn tag(&self) -> u32 {
+        match self {
+            Instruction::InitializeMarket(_) => 0,
+            Instruction::NewOrder(_) => 1,
+            Instruction::MatchOrders(_) => 2,
+            Instruction::ConsumeEvents(_) => 3,
+            Instruction::CancelOrder(_) => 4,
+            Instruction::SettleFunds(_) => 5,
+            Instruction::CancelOrderByClientId(_) => 6,
+            Instruction::NewOrderV3(_) => 10,
+            Instruction::CancelOrderV2(_) => 11,
+            Instruction::CancelOrderByClientIdV2(_) => 12,
+            Instruction::SendTake(_) => 13,
+            Instruction::CloseOpenOrders(_) => 14,
+            Instruction::InitOpenOrders(_) => 15,
+            Instruction::Prune(_) => 16,
+            Instruction::ConsumeEventsPermissioned(_) => 17,
+            Instruction::CancelOrdersByClientIds(_) => 18,
+            Instruction::ReplaceOrderByClientId(_) => 19,
+            Instruction::ReplaceOrdersByClientIds(_) => 20,
+        }
+    }
+
+    /// Variant name, as used in explanations and logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::InitializeMarket(_) => "InitializeMarket",
+            Instruction::NewOrder(_) => "NewOrder",
+            Instruction::MatchOrders(_) => "MatchOrders",
+            Instruction::ConsumeEvents(_) => "ConsumeEvents",
+            Instruction::CancelOrder(_) => "CancelOrder",
+            Instruction::SettleFunds(_) => "SettleFunds",
+            Instruction::CancelOrderByClientId(_) => "CancelOrderByClientId",
+            Instruction::NewOrderV3(_) => "NewOrderV3",
+            Instruction::CancelOrderV2(_) => "CancelOrderV2",
+            Instruction::CancelOrderByClientIdV2(_) => "CancelOrderByClientIdV2",
+            Instruction::SendTake(_) => "SendTake",
+            Instruction::CloseOpenOrders(_) => "CloseOpenOrders",
+            Instruction::InitOpenOrders(_) => "InitOpenOrders",
+            Instruction::Prune(_) => "Prune",
+            Instruction::ConsumeEventsPermissioned(_) => "ConsumeEventsPermissioned",
+            Instruction::CancelOrdersByClientIds(_) => "CancelOrdersByClientIds",
+            Instruction::ReplaceOrderByClientId(_) => "ReplaceOrderByClientId",
+            Instruction::ReplaceOrdersByClientIds(_) => "ReplaceOrdersByClientIds",
+        }
+    }
 
-    fn pack_into_slice(&self, dst: &mut [u8]) {
-        let mut writer = std::io::Cursor::new(dst);
+    /// Instruction data as the program reads it: a zero version byte, the
+    /// little-endian `u32` tag and the packed little-endian fields.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8];
+        data.extend_from_slice(&self.tag().to_le_bytes());
         match self {
             Instruction::InitializeMarket(inner) => {
-                writer.write_all(&[0]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&inner.base_lot_size.to_le_bytes());
+                data.extend_from_slice(&inner.quote_lot_size.to_le_bytes());
+                data.extend_from_slice(&inner.fee_rate_bps.to_le_bytes());
+                data.extend_from_slice(&inner.vault_signer_nonce.to_le_bytes());
+                data.extend_from_slice(&inner.quote_dust_threshold.to_le_bytes());
             }
             Instruction::NewOrder(inner) => {
-                writer.write_all(&[1]).unwrap();
-                inner.serialize(&mut writer).unwrap();
-            }
-            Instruction::MatchOrders(inner) => {
-                writer.write_all(&[2]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&side_code(inner.side).to_le_bytes());
+                data.extend_from_slice(&inner.limit_price.to_le_bytes());
+                data.extend_from_slice(&inner.max_quantity.to_le_bytes());
+                data.extend_from_slice(&order_type_code(inner.order_type).to_le_bytes());
+                data.extend_from_slice(&inner.client_id.to_le_bytes());
             }
-            Instruction::ConsumeEvents(inner) => {
-                writer.write_all(&[3]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+            Instruction::MatchOrders(MatchOrders { limit })
+            | Instruction::ConsumeEvents(ConsumeEvents { limit })
+            | Instruction::Prune(Prune { limit })
+            | Instruction::ConsumeEventsPermissioned(ConsumeEventsPermissioned { limit }) => {
+                data.extend_from_slice(&limit.to_le_bytes());
             }
             Instruction::CancelOrder(inner) => {
-                writer.write_all(&[4]).unwrap();
-                inner.serialize(&mut writer).unwrap();
-            }
-            Instruction::SettleFunds(inner) => {
-                writer.write_all(&[5]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&side_code(inner.side).to_le_bytes());
+                data.extend_from_slice(&inner.order_id.to_le_bytes());
+                data.extend_from_slice(inner.owner.as_ref());
+                data.push(inner.owner_slot);
             }
-            Instruction::CancelOrderByClientId(inner) => {
-                writer.write_all(&[6]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+            Instruction::CancelOrderByClientId(CancelOrderByClientId { client_id })
+            | Instruction::CancelOrderByClientIdV2(CancelOrderByClientIdV2 { client_id }) => {
+                data.extend_from_slice(&client_id.to_le_bytes());
             }
-            Instruction::NewOrderV3(inner) => {
-                writer.write_all(&[7]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+            Instruction::NewOrderV3(inner)
+            | Instruction::ReplaceOrderByClientId(ReplaceOrderByClientId { order: inner }) => {
+                pack_new_order_v3(inner, &mut data);
             }
             Instruction::CancelOrderV2(inner) => {
-                writer.write_all(&[8]).unwrap();
-                inner.serialize(&mut writer).unwrap();
-            }
-            Instruction::CancelOrderByClientIdV2(inner) => {
-                writer.write_all(&[9]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&side_code(inner.side).to_le_bytes());
+                data.extend_from_slice(&inner.order_id.to_le_bytes());
             }
             Instruction::SendTake(inner) => {
-                writer.write_all(&[10]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&side_code(inner.side).to_le_bytes());
+                data.extend_from_slice(&inner.limit_price.to_le_bytes());
+                data.extend_from_slice(&inner.max_base_quantity.to_le_bytes());
+                data.extend_from_slice(&inner.max_quote_quantity.to_le_bytes());
+                data.extend_from_slice(&inner.min_base_quantity.to_le_bytes());
+                data.extend_from_slice(&inner.min_quote_quantity.to_le_bytes());
+                data.extend_from_slice(&inner.limit.to_le_bytes());
             }
+            Instruction::SettleFunds(_)
+            | Instruction::CloseOpenOrders(_)
+            | Instruction::InitOpenOrders(_) => {}
             Instruction::CancelOrdersByClientIds(inner) => {
-                writer.write_all(&[11]).unwrap();
-                inner.serialize(&mut writer).unwrap();
-            }
-            Instruction::ReplaceOrderByClientId(inner) => {
-                writer.write_all(&[12]).unwrap();
-                inner.serialize(&mut writer).unwrap();
-            }
-            Instruction::ReplaceOrdersByClientIds(inner) => {
-                writer.write_all(&[13]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                for client_id in inner.client_ids {
+                    data.extend_from_slice(&client_id.to_le_bytes());
+                }
             }
             Instruction::ReplaceOrdersByClientIds(inner) => {
-                writer.write_all(&[14]).unwrap();
-                inner.serialize(&mut writer).unwrap();
+                data.extend_from_slice(&(inner.orders.len() as u64).to_le_bytes());
+                for order in &inner.orders {
+                    pack_new_order_v3(order, &mut data);
+                }
             }
         }
+        data
+    }
+}
+
+fn pack_new_order_v3(order: &NewOrderV3, data: &mut Vec<u8>) {
+    data.extend_from_slice(&side_code(order.side).to_le_bytes());
+    data.extend_from_slice(&order.limit_price.to_le_bytes());
+    data.extend_from_slice(&order.max_base_quantity.to_le_bytes());
+    data.extend_from_slice(&order.max_quote_quantity.to_le_bytes());
+    data.extend_from_slice(&self_trade_behavior_code(order.self_trade_behavior).to_le_bytes());
+    data.extend_from_slice(&order_type_code(order.order_type).to_le_bytes());
+    data.extend_from_slice(&order.client_order_id.to_le_bytes());
+    data.extend_from_slice(&order.limit.to_le_bytes());
+    data.extend_from_slice(&order.max_ts.to_le_bytes());
+}
+
+fn side_code(side: Side) -> u32 {
+    match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    }
+}
+
+fn order_type_code(order_type: OrderType) -> u32 {
+    match order_type {
+        OrderType::Limit => 0,
+        OrderType::Ioc => 1,
+        OrderType::PostOnly => 2,
     }
+}
+
+fn self_trade_behavior_code(self_trade_behavior: SelfTradeBehavior) -> u32 {
+    match self_trade_behavior {
+        SelfTradeBehavior::DecrementTake => 0,
+        SelfTradeBehavior::CancelProvide => 1,
+        SelfTradeBehavior::AbortTransaction => 2,
+    }
+}
+
+/// Length of a packed `NewOrderV3` payload, and of the older form that
+/// predates `max_ts`.
+const NEW_ORDER_V3_LEN: usize = 54;
+const NEW_ORDER_V3_LEN_WITHOUT_MAX_TS: usize = 46;
+
+impl Instruction {
+    /// Decodes instruction data produced by `pack` or by any other client of
+    /// the deployed program. Trailing or missing bytes are rejected, as the
+    /// program itself does.
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, DecodeError> {
+        if src.len() < 5 {
+            return Err(DecodeError::InvalidLength {
+                expected: 5,
+                actual: src.len(),
+            });
+        }
+        if src[0] != 0 {
+            return Err(DecodeError::UnsupportedInstructionVersion(src[0]));
+        }
+        let tag = u32::from_le_bytes(src[1..5].try_into().unwrap());
+        let payload = &src[5..];
+        let invalid_length = || DecodeError::InvalidInstructionLength {
+            tag,
+            actual: payload.len(),
+        };
+        let expect_len = |len: usize| {
+            if payload.len() == len {
+                Ok(InstructionReader::new(payload))
+            } else {
+                Err(invalid_length())
+            }
+        };
 
-    fn unpack_from_slice(src: &[u8]) -> Result<Self, borsh::Error> {
-        let mut reader = std::io::Cursor::new(src);
-        let variant: u32 = borsh::BorshDeserialize::deserialize(&mut reader)?;
-        match variant {
+        let instruction = match tag {
             0 => {
-                let inner: InitializeMarket = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::InitializeMarket(inner))
+                let mut reader = expect_len(34)?;
+                Instruction::InitializeMarket(InitializeMarket {
+                    base_lot_size: reader.u64(),
+                    quote_lot_size: reader.u64(),
+                    fee_rate_bps: reader.u16(),
+                    vault_signer_nonce: reader.u64(),
+                    quote_dust_threshold: reader.u64(),
+                })
             }
             1 => {
-                let inner: NewOrder = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::NewOrder(inner))
-            }
-            2 => {
-                let inner: MatchOrders = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::MatchOrders(inner))
-            }
-            3 => {
-                let inner: ConsumeEvents = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::ConsumeEvents(inner))
+                let mut reader = expect_len(32)?;
+                Instruction::NewOrder(NewOrder {
+                    side: reader.side()?,
+                    limit_price: reader.u64(),
+                    max_quantity: reader.u64(),
+                    order_type: reader.order_type()?,
+                    client_id: reader.u64(),
+                })
             }
+            2 => Instruction::MatchOrders(MatchOrders {
+                limit: expect_len(2)?.u16(),
+            }),
+            3 => Instruction::ConsumeEvents(ConsumeEvents {
+                limit: expect_len(2)?.u16(),
+            }),
             4 => {
-                let inner: CancelOrder = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::CancelOrder(inner))
-            }
-            5 => Ok(Instruction::SettleFunds),
-            6 => {
-                let inner: CancelOrderByClientId = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::CancelOrderByClientId(inner))
+                let mut reader = expect_len(53)?;
+                Instruction::CancelOrder(CancelOrder {
+                    side: reader.side()?,
+                    order_id: reader.u128(),
+                    owner: Pubkey::new_from_array(reader.take()),
+                    owner_slot: reader.u8(),
+                })
             }
-            7 => {
-                let inner: NewOrderV3 = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::NewOrderV3(inner))
-            }
-            8 => {
-                let inner: CancelOrderV2 = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::CancelOrderV2(inner))
-            }
-            9 => {
-                let inner: CancelOrderByClientIdV2 = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::CancelOrderByClientIdV2(inner))
-            }
-            10 => {
-                let inner: SendTake = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::SendTake(inner))
+            5 => {
+                expect_len(0)?;
+                Instruction::SettleFunds(SettleFunds)
             }
+            6 => Instruction::CancelOrderByClientId(CancelOrderByClientId {
+                client_id: expect_len(8)?.u64(),
+            }),
+            10 => Instruction::NewOrderV3(unpack_new_order_v3(tag, payload)?),
             11 => {
-                let inner: CloseOpenOrders = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::CloseOpenOrders(inner))
-            }
-            12 => {
-                let inner: InitOpenOrders = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::InitOpenOrders(inner))
+                let mut reader = expect_len(20)?;
+                Instruction::CancelOrderV2(CancelOrderV2 {
+                    side: reader.side()?,
+                    order_id: reader.u128(),
+                })
             }
+            12 => Instruction::CancelOrderByClientIdV2(CancelOrderByClientIdV2 {
+                client_id: expect_len(8)?.u64(),
+            }),
             13 => {
-                let inner: Prune = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::Prune(inner))
+                let mut reader = expect_len(46)?;
+                Instruction::SendTake(SendTake {
+                    side: reader.side()?,
+                    limit_price: reader.u64(),
+                    max_base_quantity: reader.u64(),
+                    max_quote_quantity: reader.u64(),
+                    min_base_quantity: reader.u64(),
+                    min_quote_quantity: reader.u64(),
+                    limit: reader.u16(),
+                })
             }
             14 => {
-                let inner: ConsumeEventsPermissioned = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::ConsumeEventsPermissioned(inner))
+                expect_len(0)?;
+                Instruction::CloseOpenOrders(CloseOpenOrders)
             }
             15 => {
-                let inner: ReplaceOrderByClientId = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::ReplaceOrderByClientId(inner))
+                expect_len(0)?;
+                Instruction::InitOpenOrders(InitOpenOrders)
+            }
+            16 => Instruction::Prune(Prune {
+                limit: expect_len(2)?.u16(),
+            }),
+            17 => Instruction::ConsumeEventsPermissioned(ConsumeEventsPermissioned {
+                limit: expect_len(2)?.u16(),
+            }),
+            18 => {
+                let mut reader = expect_len(64)?;
+                let mut client_ids = [0u64; 8];
+                for client_id in client_ids.iter_mut() {
+                    *client_id = reader.u64();
+                }
+                Instruction::CancelOrdersByClientIds(CancelOrdersByClientIds { client_ids })
             }
-            16 => {
-                let inner: ReplaceOrdersByClientIds = borsh::BorshDeserialize::deserialize(&mut reader)?;
-                Ok(Instruction::ReplaceOrdersByClientIds(inner))
+            19 => Instruction::ReplaceOrderByClientId(ReplaceOrderByClientId {
+                order: unpack_new_order_v3(tag, payload)?,
+            }),
+            20 => {
+                if payload.len() < 8 {
+                    return Err(invalid_length());
+                }
+                let count = u64::from_le_bytes(payload[..8].try_into().unwrap());
+                let orders = &payload[8..];
+                if count > 8 || orders.len() as u64 != count * NEW_ORDER_V3_LEN as u64 {
+                    return Err(invalid_length());
+                }
+                let orders = orders
+                    .chunks_exact(NEW_ORDER_V3_LEN)
+                    .map(|order| unpack_new_order_v3(tag, order))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Instruction::ReplaceOrdersByClientIds(ReplaceOrdersByClientIds { orders })
             }
-            // Add other variants as needed
-            _ => Err(borsh::Error::InvalidData),
+            _ => return Err(DecodeError::UnknownInstructionTag(tag)),
+        };
+        Ok(instruction)
+    }
+}
+
+/// Orders sent without `max_ts` never expire.
+fn unpack_new_order_v3(tag: u32, payload: &[u8]) -> Result<NewOrderV3, DecodeError> {
+    if payload.len() != NEW_ORDER_V3_LEN && payload.len() != NEW_ORDER_V3_LEN_WITHOUT_MAX_TS {
+        return Err(DecodeError::InvalidInstructionLength {
+            tag,
+            actual: payload.len(),
+        });
+    }
+    let mut reader = InstructionReader::new(payload);
+    Ok(NewOrderV3 {
+        side: reader.side()?,
+        limit_price: reader.u64(),
+        max_base_quantity: reader.u64(),
+        max_quote_quantity: reader.u64(),
+        self_trade_behavior: reader.self_trade_behavior()?,
+        order_type: reader.order_type()?,
+        client_order_id: reader.u64(),
+        limit: reader.u16(),
+        max_ts: if payload.len() == NEW_ORDER_V3_LEN {
+            reader.i64()
+        } else {
+            i64::MAX
+        },
+    })
+}
+
+/// Little-endian reads over a payload whose length was checked up front.
+struct InstructionReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> InstructionReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        InstructionReader { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        bytes.try_into().unwrap()
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    fn i64(&mut self) -> i64 {
+        i64::from_le_bytes(self.take())
+    }
+
+    fn u128(&mut self) -> u128 {
+        u128::from_le_bytes(self.take())
+    }
+
+    fn side(&mut self) -> Result<Side, DecodeError> {
+        match self.u32() {
+            0 => Ok(Side::Buy),
+            1 => Ok(Side::Sell),
+            value => Err(DecodeError::InvalidInstructionField {
+                field: "side",
+                value,
+            }),
+        }
+    }
+
+    fn order_type(&mut self) -> Result<OrderType, DecodeError> {
+        match self.u32() {
+            0 => Ok(OrderType::Limit),
+            1 => Ok(OrderType::Ioc),
+            2 => Ok(OrderType::PostOnly),
+            value => Err(DecodeError::InvalidInstructionField {
+                field: "order_type",
+                value,
+            }),
+        }
+    }
+
+    fn self_trade_behavior(&mut self) -> Result<SelfTradeBehavior, DecodeError> {
+        match self.u32() {
+            0 => Ok(SelfTradeBehavior::DecrementTake),
+            1 => Ok(SelfTradeBehavior::CancelProvide),
+            2 => Ok(SelfTradeBehavior::AbortTransaction),
+            value => Err(DecodeError::InvalidInstructionField {
+                field: "self_trade_behavior",
+                value,
+            }),
+        }
+    }
+}
+
+pub struct InitializeMarketParams {
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub fee_rate_bps: u16,
+    pub vault_signer_nonce: u64,
+    pub quote_dust_threshold: u64,
+    /// Permissioned markets only: the open orders, prune and crank authorities.
+    pub authority: Option<Pubkey>,
+    pub prune_authority: Option<Pubkey>,
+    pub crank_authority: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct NewOrderParams {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub request_queue: Pubkey,
+    pub payer: Pubkey,
+    pub owner: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub side: Side,
+    pub limit_price: PriceLots,
+    pub max_quantity: BaseLots,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub fee_discount_pubkey: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct MatchOrdersParams {
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub coin_fee_receivable: Pubkey,
+    pub pc_fee_receivable: Pubkey,
+    pub limit: u16,
+    pub program_id: Pubkey,
+}
+
+pub struct ConsumeEventsParams {
+    pub market: Pubkey,
+    pub event_queue: Pubkey,
+    pub coin_fee: Pubkey,
+    pub pc_fee: Pubkey,
+    pub open_orders_accounts: Vec<Pubkey>,
+    pub limit: u16,
+    pub program_id: Pubkey,
+}
+
+pub struct CancelOrderParams {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub request_queue: Pubkey,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub order_id: OrderId,
+    pub open_orders_slot: u8,
+    pub program_id: Pubkey,
+}
+
+pub struct SettleFundsParams {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub base_wallet: Pubkey,
+    pub quote_wallet: Pubkey,
+    pub vault_signer: Pubkey,
+    pub referrer_quote_wallet: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct CancelOrderByClientIdParams {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub request_queue: Pubkey,
+    pub owner: Pubkey,
+    pub client_id: u64,
+    pub program_id: Pubkey,
+}
+
+pub struct NewOrderV3Params {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub payer: Pubkey,
+    pub owner: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub side: Side,
+    pub limit_price: PriceLots,
+    pub max_base_quantity: BaseLots,
+    /// Native quote the order may lock up, including the taker fee.
+    pub max_quote_quantity: NativeQuote,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub limit: u16,
+    pub max_ts: i64,
+    /// Sends `ReplaceOrderByClientId` instead, cancelling any resting order
+    /// with the same client id first.
+    pub replace_if_exists: bool,
+    pub fee_discount_pubkey: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct CancelOrderV2Params {
+    pub market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub event_queue: Pubkey,
+    pub side: Side,
+    pub order_id: OrderId,
+    pub program_id: Pubkey,
+}
+
+pub struct CancelOrderByClientIdV2Params {
+    pub market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub event_queue: Pubkey,
+    pub client_id: u64,
+    pub program_id: Pubkey,
+}
+
+pub struct SendTakeInstructionParams {
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub base_wallet: Pubkey,
+    pub quote_wallet: Pubkey,
+    pub owner: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub vault_signer: Pubkey,
+    pub side: Side,
+    pub limit_price: PriceLots,
+    pub max_base_quantity: BaseLots,
+    /// Native quote, including the taker fee.
+    pub max_quote_quantity: NativeQuote,
+    pub min_base_quantity: BaseLots,
+    pub min_quote_quantity: NativeQuote,
+    pub limit: u16,
+    pub fee_discount_pubkey: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct CloseOpenOrdersParams {
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub destination: Pubkey,
+    pub market: Pubkey,
+    pub program_id: Pubkey,
+}
+
+pub struct InitOpenOrdersParams {
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    /// Required by permissioned markets, which must co-sign.
+    pub market_authority: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+pub struct PruneParams {
+    pub market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub prune_authority: Pubkey,
+    pub open_orders: Pubkey,
+    pub open_orders_owner: Pubkey,
+    pub event_queue: Pubkey,
+    pub limit: u16,
+    pub program_id: Pubkey,
+}
+
+pub struct ConsumeEventsPermissionedParams {
+    pub market: Pubkey,
+    pub event_queue: Pubkey,
+    pub crank_authority: Pubkey,
+    pub open_orders_accounts: Vec<Pubkey>,
+    pub limit: u16,
+    pub program_id: Pubkey,
+}
+
+pub struct CancelOrdersByClientIdsParams {
+    pub market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub open_orders: Pubkey,
+    pub owner: Pubkey,
+    pub event_queue: Pubkey,
+    /// At most eight ids; unused slots are sent as zero.
+    pub client_ids: Vec<u64>,
+    pub program_id: Pubkey,
+}
+
+pub struct ReplaceOrdersByClientIdsParams {
+    pub market: Pubkey,
+    pub open_orders: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub payer: Pubkey,
+    pub owner: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub orders: Vec<NewOrderV3>,
+    pub fee_discount_pubkey: Option<Pubkey>,
+    pub program_id: Pubkey,
+}
+
+/// Builders for every DEX instruction, with accounts in the order the
+/// program expects them.
+pub struct DexInstructions;
+
+impl DexInstructions {
+    pub fn initialize_market(params: InitializeMarketParams) -> SolanaInstruction {
+        let mut accounts = vec![
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.request_queue, false),
+            AccountMeta::new(params.event_queue, false),
+            AccountMeta::new(params.bids, false),
+            AccountMeta::new(params.asks, false),
+            AccountMeta::new(params.base_vault, false),
+            AccountMeta::new(params.quote_vault, false),
+            AccountMeta::new_readonly(params.base_mint, false),
+            AccountMeta::new_readonly(params.quote_mint, false),
+            AccountMeta::new_readonly(rent::id(), false),
+        ];
+        accounts.extend(
+            [
+                params.authority,
+                params.prune_authority,
+                params.crank_authority,
+            ]
+            .into_iter()
+            .flatten()
+            .map(|authority| AccountMeta::new_readonly(authority, false)),
+        );
+        let data = Instruction::InitializeMarket(InitializeMarket {
+            base_lot_size: params.base_lot_size,
+            quote_lot_size: params.quote_lot_size,
+            fee_rate_bps: params.fee_rate_bps,
+            vault_signer_nonce: params.vault_signer_nonce,
+            quote_dust_threshold: params.quote_dust_threshold,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn new_order(params: NewOrderParams) -> SolanaInstruction {
+        let mut accounts = keyed_accounts(
+            [
+                (
+                    NEW_ORDER_OPEN_ORDERS_INDEX,
+                    AccountMeta::new(params.open_orders, false),
+                ),
+                (
+                    NEW_ORDER_OWNER_INDEX,
+                    AccountMeta::new_readonly(params.owner, true),
+                ),
+            ],
+            [
+                AccountMeta::new(params.market, false),
+                AccountMeta::new(params.request_queue, false),
+                AccountMeta::new(params.payer, false),
+                AccountMeta::new(params.base_vault, false),
+                AccountMeta::new(params.quote_vault, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(rent::id(), false),
+            ],
+        );
+        if let Some(fee_discount_pubkey) = params.fee_discount_pubkey {
+            accounts.push(AccountMeta::new_readonly(fee_discount_pubkey, false));
+        }
+        let data = Instruction::NewOrder(NewOrder {
+            side: params.side,
+            limit_price: params.limit_price.get(),
+            max_quantity: params.max_quantity.get(),
+            order_type: params.order_type,
+            client_id: params.client_id,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn match_orders(params: MatchOrdersParams) -> SolanaInstruction {
+        let accounts = vec![
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.request_queue, false),
+            AccountMeta::new(params.event_queue, false),
+            AccountMeta::new(params.bids, false),
+            AccountMeta::new(params.asks, false),
+            AccountMeta::new(params.coin_fee_receivable, false),
+            AccountMeta::new(params.pc_fee_receivable, false),
+        ];
+        let data = Instruction::MatchOrders(MatchOrders {
+            limit: params.limit,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn consume_events(params: ConsumeEventsParams) -> SolanaInstruction {
+        let mut accounts: Vec<AccountMeta> = params
+            .open_orders_accounts
+            .iter()
+            .map(|open_orders| AccountMeta::new(*open_orders, false))
+            .collect();
+        accounts.extend([
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.event_queue, false),
+            AccountMeta::new(params.coin_fee, false),
+            AccountMeta::new(params.pc_fee, false),
+        ]);
+        let data = Instruction::ConsumeEvents(ConsumeEvents {
+            limit: params.limit,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn cancel_order(params: CancelOrderParams) -> SolanaInstruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(params.market, false),
+            AccountMeta::new(params.open_orders, false),
+            AccountMeta::new(params.request_queue, false),
+            AccountMeta::new_readonly(params.owner, true),
+        ];
+        let data = Instruction::CancelOrder(CancelOrder {
+            side: params.side,
+            order_id: params.order_id.get(),
+            owner: params.owner,
+            owner_slot: params.open_orders_slot,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn settle_funds(params: SettleFundsParams) -> SolanaInstruction {
+        let mut accounts = keyed_accounts(
+            [
+                (
+                    SETTLE_FUNDS_BASE_WALLET_INDEX,
+                    AccountMeta::new(params.base_wallet, false),
+                ),
+                (
+                    SETTLE_FUNDS_QUOTE_WALLET_INDEX,
+                    AccountMeta::new(params.quote_wallet, false),
+                ),
+            ],
+            [
+                AccountMeta::new(params.market, false),
+                AccountMeta::new(params.open_orders, false),
+                AccountMeta::new_readonly(params.owner, true),
+                AccountMeta::new(params.base_vault, false),
+                AccountMeta::new(params.quote_vault, false),
+                AccountMeta::new_readonly(params.vault_signer, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            ],
+        );
+        if let Some(referrer_quote_wallet) = params.referrer_quote_wallet {
+            accounts.push(AccountMeta::new(referrer_quote_wallet, false));
         }
+        let data = Instruction::SettleFunds(SettleFunds).pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
     }
 
+    pub fn cancel_order_by_client_id(params: CancelOrderByClientIdParams) -> SolanaInstruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(params.market, false),
+            AccountMeta::new(params.open_orders, false),
+            AccountMeta::new(params.request_queue, false),
+            AccountMeta::new_readonly(params.owner, true),
+        ];
+        let data = Instruction::CancelOrderByClientId(CancelOrderByClientId {
+            client_id: params.client_id,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    /// Builds `NewOrderV3`, or `ReplaceOrderByClientId` when
+    /// `replace_if_exists` is set; both take the same accounts.
+    pub fn new_order_v3(params: NewOrderV3Params) -> SolanaInstruction {
+        let order = NewOrderV3 {
+            side: params.side,
+            limit_price: params.limit_price.get(),
+            max_base_quantity: params.max_base_quantity.get(),
+            max_quote_quantity: params.max_quote_quantity.get(),
+            self_trade_behavior: params.self_trade_behavior,
+            order_type: params.order_type,
+            client_order_id: params.client_id,
+            limit: params.limit,
+            max_ts: params.max_ts,
+        };
+        let instruction = if params.replace_if_exists {
+            Instruction::ReplaceOrderByClientId(ReplaceOrderByClientId { order })
+        } else {
+            Instruction::NewOrderV3(order)
+        };
+        let accounts = new_order_v3_accounts(
+            &NewOrderV3Accounts {
+                market: params.market,
+                open_orders: params.open_orders,
+                request_queue: params.request_queue,
+                event_queue: params.event_queue,
+                bids: params.bids,
+                asks: params.asks,
+                payer: params.payer,
+                owner: params.owner,
+                base_vault: params.base_vault,
+                quote_vault: params.quote_vault,
+            },
+            params.fee_discount_pubkey,
+        );
+        SolanaInstruction::new_with_bytes(params.program_id, &instruction.pack(), accounts)
+    }
 
+    pub fn cancel_order_v2(params: CancelOrderV2Params) -> SolanaInstruction {
+        let accounts = orderbook_accounts(
+            params.market,
+            params.bids,
+            params.asks,
+            params.open_orders,
+            params.owner,
+            params.event_queue,
+        );
+        let data = Instruction::CancelOrderV2(CancelOrderV2 {
+            side: params.side,
+            order_id: params.order_id.get(),
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
 
+    pub fn cancel_order_by_client_id_v2(
+        params: CancelOrderByClientIdV2Params,
+    ) -> SolanaInstruction {
+        let accounts = orderbook_accounts(
+            params.market,
+            params.bids,
+            params.asks,
+            params.open_orders,
+            params.owner,
+            params.event_queue,
+        );
+        let data = Instruction::CancelOrderByClientIdV2(CancelOrderByClientIdV2 {
+            client_id: params.client_id,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
 
-            
+    pub fn send_take(params: SendTakeInstructionParams) -> SolanaInstruction {
+        let mut accounts = vec![
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.request_queue, false),
+            AccountMeta::new(params.event_queue, false),
+            AccountMeta::new(params.bids, false),
+            AccountMeta::new(params.asks, false),
+            AccountMeta::new(params.base_wallet, false),
+            AccountMeta::new(params.quote_wallet, false),
+            AccountMeta::new_readonly(params.owner, true),
+            AccountMeta::new(params.base_vault, false),
+            AccountMeta::new(params.quote_vault, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(params.vault_signer, false),
+        ];
+        if let Some(fee_discount_pubkey) = params.fee_discount_pubkey {
+            accounts.push(AccountMeta::new_readonly(fee_discount_pubkey, false));
+        }
+        let data = Instruction::SendTake(SendTake {
+            side: params.side,
+            limit_price: params.limit_price.get(),
+            max_base_quantity: params.max_base_quantity.get(),
+            max_quote_quantity: params.max_quote_quantity.get(),
+            min_base_quantity: params.min_base_quantity.get(),
+            min_quote_quantity: params.min_quote_quantity.get(),
+            limit: params.limit,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
 
+    pub fn close_open_orders(params: CloseOpenOrdersParams) -> SolanaInstruction {
+        let accounts = vec![
+            AccountMeta::new(params.open_orders, false),
+            AccountMeta::new_readonly(params.owner, true),
+            AccountMeta::new(params.destination, false),
+            AccountMeta::new_readonly(params.market, false),
+        ];
+        let data = Instruction::CloseOpenOrders(CloseOpenOrders).pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
 
+    pub fn init_open_orders(params: InitOpenOrdersParams) -> SolanaInstruction {
+        let mut accounts = vec![
+            AccountMeta::new(params.open_orders, false),
+            AccountMeta::new_readonly(params.owner, true),
+            AccountMeta::new_readonly(params.market, false),
+            AccountMeta::new_readonly(rent::id(), false),
+        ];
+        if let Some(market_authority) = params.market_authority {
+            accounts.push(AccountMeta::new_readonly(market_authority, true));
+        }
+        let data = Instruction::InitOpenOrders(InitOpenOrders).pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn prune(params: PruneParams) -> SolanaInstruction {
+        let accounts = vec![
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.bids, false),
+            AccountMeta::new(params.asks, false),
+            AccountMeta::new_readonly(params.prune_authority, true),
+            AccountMeta::new(params.open_orders, false),
+            AccountMeta::new_readonly(params.open_orders_owner, false),
+            AccountMeta::new(params.event_queue, false),
+        ];
+        let data = Instruction::Prune(Prune {
+            limit: params.limit,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    pub fn consume_events_permissioned(
+        params: ConsumeEventsPermissionedParams,
+    ) -> SolanaInstruction {
+        let mut accounts: Vec<AccountMeta> = params
+            .open_orders_accounts
+            .iter()
+            .map(|open_orders| AccountMeta::new(*open_orders, false))
+            .collect();
+        accounts.extend([
+            AccountMeta::new(params.market, false),
+            AccountMeta::new(params.event_queue, false),
+            AccountMeta::new_readonly(params.crank_authority, true),
+        ]);
+        let data = Instruction::ConsumeEventsPermissioned(ConsumeEventsPermissioned {
+            limit: params.limit,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+
+    /// Fails if more than eight client ids are given, the most the program
+    /// accepts in one instruction.
+    pub fn cancel_orders_by_client_ids(
+        params: CancelOrdersByClientIdsParams,
+    ) -> Result<SolanaInstruction, EncodeError> {
+        let mut client_ids = [0u64; 8];
+        if params.client_ids.len() > client_ids.len() {
+            return Err(EncodeError::TooManyClientIds {
+                count: params.client_ids.len(),
+                max: client_ids.len(),
+            });
+        }
+        client_ids[..params.client_ids.len()].copy_from_slice(&params.client_ids);
+
+        let accounts = orderbook_accounts(
+            params.market,
+            params.bids,
+            params.asks,
+            params.open_orders,
+            params.owner,
+            params.event_queue,
+        );
+        let data =
+            Instruction::CancelOrdersByClientIds(CancelOrdersByClientIds { client_ids }).pack();
+        Ok(SolanaInstruction::new_with_bytes(
+            params.program_id,
+            &data,
+            accounts,
+        ))
+    }
+
+    pub fn replace_order_by_client_id(params: NewOrderV3Params) -> SolanaInstruction {
+        DexInstructions::new_order_v3(NewOrderV3Params {
+            replace_if_exists: true,
+            ..params
+        })
+    }
+
+    pub fn replace_orders_by_client_ids(
+        params: ReplaceOrdersByClientIdsParams,
+    ) -> SolanaInstruction {
+        let accounts = new_order_v3_accounts(
+            &NewOrderV3Accounts {
+                market: params.market,
+                open_orders: params.open_orders,
+                request_queue: params.request_queue,
+                event_queue: params.event_queue,
+                bids: params.bids,
+                asks: params.asks,
+                payer: params.payer,
+                owner: params.owner,
+                base_vault: params.base_vault,
+                quote_vault: params.quote_vault,
+            },
+            params.fee_discount_pubkey,
+        );
+        let data = Instruction::ReplaceOrdersByClientIds(ReplaceOrdersByClientIds {
+            orders: params.orders,
+        })
+        .pack();
+        SolanaInstruction::new_with_bytes(params.program_id, &data, accounts)
+    }
+}
+
+struct NewOrderV3Accounts {
+    market: Pubkey,
+    open_orders: Pubkey,
+    request_queue: Pubkey,
+    event_queue: Pubkey,
+    bids: Pubkey,
+    asks: Pubkey,
+    payer: Pubkey,
+    owner: Pubkey,
+    base_vault: Pubkey,
+    quote_vault: Pubkey,
+}
+
+/// Accounts of `NewOrderV3` and both replace instructions.
+fn new_order_v3_accounts(
+    keys: &NewOrderV3Accounts,
+    fee_discount_pubkey: Option<Pubkey>,
+) -> Vec<AccountMeta> {
+    let mut accounts = keyed_accounts(
+        [
+            (
+                NEW_ORDER_V3_OPEN_ORDERS_INDEX,
+                AccountMeta::new(keys.open_orders, false),
+            ),
+            (
+                NEW_ORDER_V3_OWNER_INDEX,
+                AccountMeta::new_readonly(keys.owner, true),
+            ),
+        ],
+        [
+            AccountMeta::new(keys.market, false),
+            AccountMeta::new(keys.request_queue, false),
+            AccountMeta::new(keys.event_queue, false),
+            AccountMeta::new(keys.bids, false),
+            AccountMeta::new(keys.asks, false),
+            AccountMeta::new(keys.payer, false),
+            AccountMeta::new(keys.base_vault, false),
+            AccountMeta::new(keys.quote_vault, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(rent::id(), false),
+        ],
+    );
+    if let Some(fee_discount_pubkey) = fee_discount_pubkey {
+        accounts.push(AccountMeta::new_readonly(fee_discount_pubkey, false));
+    }
+    accounts
 }
 
+/// Places each keyed account at its `*_INDEX` position and fills the slots
+/// around them with `others` in order.
+fn keyed_accounts<const N: usize>(
+    mut keyed: [(u8, AccountMeta); N],
+    others: impl IntoIterator<Item = AccountMeta>,
+) -> Vec<AccountMeta> {
+    keyed.sort_by_key(|(index, _)| *index);
+    let mut others = others.into_iter();
+    let mut accounts = Vec::new();
+    for (index, account) in keyed {
+        while accounts.len() < index as usize {
+            accounts.push(
+                others
+                    .next()
+                    .expect("account index past the end of the list"),
+            );
+        }
+        accounts.push(account);
+    }
+    accounts.extend(others);
+    accounts
+}
+
+/// Accounts of the cancel instructions that settle against the book directly.
+fn orderbook_accounts(
+    market: Pubkey,
+    bids: Pubkey,
+    asks: Pubkey,
+    open_orders: Pubkey,
+    owner: Pubkey,
+    event_queue: Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(market, false),
+        AccountMeta::new(bids, false),
+        AccountMeta::new(asks, false),
+        AccountMeta::new(open_orders, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new(event_queue, false),
+    ]
+}
@@ -0,0 +1,111 @@
+use rust_decimal::Decimal;
+
+use super::conversion::{LotConversion, Rounding};
+use super::order::Side;
+use super::units::{BaseLots, PriceLots};
+
+/// One price level of one side of the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L2Level {
+    /// Quote tokens per base token.
+    pub price: Decimal,
+    /// Base tokens.
+    pub size: Decimal,
+    pub price_lots: PriceLots,
+    pub size_lots: BaseLots,
+    pub order_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct L2Options {
+    /// Number of levels to return, after grouping. All of them when `None`.
+    pub depth: Option<usize>,
+    /// Width of the price buckets levels are merged into. Bids are grouped
+    /// down to the bucket below and asks up to the one above, so a grouped
+    /// level never looks better than the orders in it.
+    pub group: Option<PriceLots>,
+    /// Each level also counts the size and orders of all better levels, as
+    /// depth charts show them.
+    pub cumulative: bool,
+}
+
+impl L2Options {
+    /// Groups into buckets `width` quote tokens per base token wide, e.g.
+    /// 0.1 USDC. Widths that are not a whole number of ticks are rounded up
+    /// to one; `None` for widths that are not positive or do not fit.
+    pub fn group_by(self, conversion: &LotConversion, width: Decimal) -> Option<Self> {
+        let group = conversion.price_number_to_lots(width, Rounding::Ceil)?;
+        if group.is_zero() {
+            return None;
+        }
+        Some(L2Options {
+            group: Some(group),
+            ..self
+        })
+    }
+}
+
+/// Merges the orders of one side of the book into price levels. `orders`
+/// are prices and sizes in book order, best price first, as the slab yields
+/// them.
+pub fn aggregate_l2(
+    conversion: &LotConversion,
+    side: Side,
+    orders: impl IntoIterator<Item = (PriceLots, BaseLots)>,
+    options: &L2Options,
+) -> Vec<L2Level> {
+    let depth = options.depth.unwrap_or(usize::MAX);
+    let mut levels: Vec<(PriceLots, BaseLots, usize)> = Vec::new();
+    for (price, size) in orders {
+        let price = match options.group {
+            Some(group) if !group.is_zero() => bucket(side, price, group),
+            _ => price,
+        };
+        if let Some(last) = levels.last_mut() {
+            if last.0 == price {
+                last.1 = BaseLots::new(last.1.get().saturating_add(size.get()));
+                last.2 += 1;
+                continue;
+            }
+        }
+        if levels.len() == depth {
+            break;
+        }
+        levels.push((price, size, 1));
+    }
+
+    if options.cumulative {
+        for i in 1..levels.len() {
+            let (_, size, order_count) = levels[i - 1];
+            levels[i].1 = BaseLots::new(levels[i].1.get().saturating_add(size.get()));
+            levels[i].2 += order_count;
+        }
+    }
+
+    levels
+        .into_iter()
+        .map(|(price_lots, size_lots, order_count)| L2Level {
+            price: conversion
+                .price_lots_to_number(price_lots, Rounding::Nearest)
+                .unwrap_or_default(),
+            size: conversion
+                .base_size_lots_to_number(size_lots, Rounding::Nearest)
+                .unwrap_or_default(),
+            price_lots,
+            size_lots,
+            order_count,
+        })
+        .collect()
+}
+
+/// The bucket `price` falls into, for a non-zero `group`. Asks within one
+/// bucket of `u64::MAX` are clamped to it.
+fn bucket(side: Side, price: PriceLots, group: PriceLots) -> PriceLots {
+    let rounding = match side {
+        Side::Buy => Rounding::Floor,
+        Side::Sell => Rounding::Ceil,
+    };
+    let group = u128::from(group.get());
+    let buckets = rounding.div(price.get().into(), group).unwrap_or_default();
+    PriceLots::new(u64::try_from(buckets * group).unwrap_or(u64::MAX))
+}
@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+
+use super::registry::ProgramRegistry;
+
+/// A `Program log:` message and the program that wrote it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramLog {
+    /// Invoke depth of the program, 1 for the top-level instruction.
+    pub depth: usize,
+    pub program_id: Pubkey,
+    pub message: String,
+}
+
+/// The logs of one top-level instruction, including the programs it invoked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionLogs {
+    /// Position among the transaction's top-level instructions.
+    pub index: usize,
+    pub program_id: Pubkey,
+    pub messages: Vec<ProgramLog>,
+    /// What the runtime logged after `failed:` when the instruction failed,
+    /// e.g. `custom program error: 0x22`.
+    pub failure: Option<String>,
+}
+
+/// Where the DEX panicked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Error details the DEX writes to the program log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexLogError {
+    Panic {
+        message: String,
+        location: Option<SourceLocation>,
+    },
+    /// A `Program log: Error: ...` line, without the `Error: ` prefix.
+    Error(String),
+}
+
+impl InstructionLogs {
+    /// The custom error code from the failure line, which is all a preflight
+    /// failure reports when the transaction error itself is not available.
+    pub fn custom_error_code(&self) -> Option<u32> {
+        let code = self
+            .failure
+            .as_deref()?
+            .strip_prefix("custom program error: 0x")?;
+        u32::from_str_radix(code, 16).ok()
+    }
+
+    /// Panics and error lines logged by DEX programs, at any invoke depth.
+    pub fn dex_errors(&self) -> Vec<DexLogError> {
+        self.messages
+            .iter()
+            .filter(|log| ProgramRegistry::global().is_known(&log.program_id))
+            .filter_map(|log| parse_dex_error(&log.message))
+            .collect()
+    }
+}
+
+/// Splits the log messages of a transaction, as returned by a simulation or
+/// in the transaction status meta, by top-level instruction. Lines that do
+/// not follow the runtime's format are skipped, and logs cut off by the
+/// runtime's size limit yield the instructions seen so far.
+pub fn parse_logs<S: AsRef<str>>(logs: &[S]) -> Vec<InstructionLogs> {
+    let mut instructions: Vec<InstructionLogs> = Vec::new();
+    let mut stack: Vec<Pubkey> = Vec::new();
+
+    for line in logs.iter().map(AsRef::as_ref) {
+        if line == "Log truncated" {
+            break;
+        }
+        if let Some(message) = line.strip_prefix("Program log: ") {
+            if let (Some(program_id), Some(instruction)) = (stack.last(), instructions.last_mut()) {
+                instruction.messages.push(ProgramLog {
+                    depth: stack.len(),
+                    program_id: *program_id,
+                    message: message.to_string(),
+                });
+            }
+            continue;
+        }
+        let rest = match line.strip_prefix("Program ") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let (program_id, event) = match rest.split_once(' ') {
+            Some((program_id, event)) => match Pubkey::from_str(program_id) {
+                Ok(program_id) => (program_id, event),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+
+        if event.starts_with("invoke [") {
+            if stack.is_empty() {
+                instructions.push(InstructionLogs {
+                    index: instructions.len(),
+                    program_id,
+                    messages: Vec::new(),
+                    failure: None,
+                });
+            }
+            stack.push(program_id);
+        } else if event == "success" {
+            stack.pop();
+        } else if let Some(failure) = event.strip_prefix("failed: ") {
+            stack.pop();
+            if stack.is_empty() {
+                if let Some(instruction) = instructions.last_mut() {
+                    instruction.failure = Some(failure.to_string());
+                }
+            }
+        }
+    }
+    instructions
+}
+
+fn parse_dex_error(message: &str) -> Option<DexLogError> {
+    if let Some(error) = message.strip_prefix("Error: ") {
+        return Some(DexLogError::Error(error.to_string()));
+    }
+    let panic = message.strip_prefix("panicked at ")?;
+    // Older toolchains log `panicked at 'message', file:line:column`, newer
+    // ones `panicked at file:line:column:` with the message on the next line.
+    let (message, location) = match panic.strip_prefix('\'') {
+        Some(quoted) => match quoted.rsplit_once("', ") {
+            Some((message, location)) => (message, location),
+            None => (quoted, ""),
+        },
+        None => match panic.split_once('\n') {
+            Some((location, message)) => (message, location.trim_end_matches(':')),
+            None => ("", panic.trim_end_matches(':')),
+        },
+    };
+    Some(DexLogError::Panic {
+        message: message.to_string(),
+        location: parse_location(location),
+    })
+}
+
+fn parse_location(location: &str) -> Option<SourceLocation> {
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+    Some(SourceLocation {
+        file: file.to_string(),
+        line,
+        column,
+    })
+}
@@ -1,166 +1,235 @@
-use std::collections::HashMap;
-use connection::Connection;
-use std::error::Error;
-use tokio;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
-use num_bigint::{BigUint, ToBigUint};
-use serde;
-use std::str::FromStr;
-
-// use solana_client::nonblocking::rpc_client::RpcClient;
+use std::collections::{HashMap, HashSet};
+use std::mem::{offset_of, size_of};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bitflags::bitflags;
+use bytemuck::{Pod, Zeroable};
+use rust_decimal::Decimal;
+use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
 use solana_client::{
-    client_error::ClientError, 
-    rpc_client::RpcClient, 
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSendTransactionConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
     rpc_request::RpcRequest,
-    rpc_config::RpcProgramAccountsConfig,
+    rpc_response::{Response, RpcKeyedAccount},
 };
 use solana_sdk::{
-    address_lookup_table::program,
     account::Account,
     commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    program_pack::Pack,
     pubkey::Pubkey,
-    connection::Connection,
-    transaction::Transaction,
-    program_pack::{Pack, IsInitialized},
+    signature::{Keypair, Signature, Signer},
     system_instruction,
-    sysvar::{rent, Sysvar},
-    instruction::{Instruction, SystemProgram},
+    transaction::Transaction,
 };
 
+use super::cluster::{Cluster, MSRM_MINT, SRM_MINT, WRAPPED_SOL_MINT};
+use super::conversion::{LotConversion, Rounding};
+use super::error::{DecodeError, SdkError};
+use super::fees::get_fee_tier;
+use super::instruction::{
+    CancelOrderByClientIdParams, CancelOrderByClientIdV2Params, CancelOrderParams,
+    CancelOrderV2Params, CancelOrdersByClientIdsParams, ConsumeEventsParams,
+    ConsumeEventsPermissionedParams, DexInstructions, MatchOrdersParams, NewOrderParams,
+    NewOrderV3, NewOrderV3Params, ReplaceOrdersByClientIdsParams, SendTakeInstructionParams,
+    SettleFundsParams, DEFAULT_MATCH_LIMIT, TOKEN_PROGRAM_ID,
+};
+use super::market_list::MarketList;
+use super::order::{
+    OpenOrders, Order, OrderParams, OrderParamsAccounts, OrderParamsBase, OrderType, Orderbook,
+    SelfTradeBehavior, SendTakeParams, Side,
+};
+use super::queue::{decode_event_queue, decode_request_queue, Event, EventFlags, Request};
+use super::registry::ProgramRegistry;
+use super::slab::read_u64;
+use super::units::{BaseLots, NativeBase, NativeQuote, PriceLots, QuoteLots};
+use super::validation::OrderValidator;
 
-use super::order::OpenOrders;
-use anyhow::format_err;
-use serde::{Value, Error};
+const MSRM_DECIMALS: u8 = 0;
+const SRM_DECIMALS: u8 = 6;
 
-use crate::order::Order;
-use crete::queue;
-use crate::slab::{Slab, SLAB_LAYOUT};
+/// How long `Market::send_transaction` waits between signature status
+/// checks.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-pub struct AccountInfo {
-    balance: u64,
-    mint: Pubkey,
-    pubkey: Pubkey,
-    fee_tier: u64,
+#[derive(Debug, Clone, Default)]
+pub struct MarketOptions {
+    pub skip_preflight: Option<bool>,
+    /// Used for reads, preflight and confirmation. Defaults to
+    /// `CommitmentConfig::confirmed()`.
+    pub commitment: Option<CommitmentConfig>,
 }
 
-pub struct FeeDiscountKeyCache {
-    accounts: Vec<AccountInfo>,
-    ts: u64,
+/// An SRM or MSRM token account of an owner, which lowers the fees of the
+/// orders it is passed with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeDiscountKey {
+    pub pubkey: Pubkey,
+    pub mint: Pubkey,
+    /// In tokens.
+    pub balance: f64,
+    pub fee_tier: u8,
 }
 
-#[derive(Debug)]
-pub struct MarketOptions {
-    pub skip_preflight: Option<bool>,
-    pub commitment: Option<String>,
+/// A fill from the event queue, in quote tokens and base tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FillEvent {
+    pub event: Event,
+    pub side: Side,
+    pub price: Decimal,
+    pub size: Decimal,
+    /// Negative for maker rebates.
+    pub fee_cost: Decimal,
 }
 
+/// An unsigned transaction and the keypairs it creates accounts with. The
+/// keypairs sign alongside the owner.
+pub struct PreparedTransaction {
+    pub transaction: Transaction,
+    pub signers: Vec<Keypair>,
+}
+
+type Cache<T> = Mutex<HashMap<Pubkey, (Instant, Vec<T>)>>;
+
 pub struct Market {
-    decoded: serde::Value,
+    decoded: MarketState,
     base_mint_decimals: u8,
     quote_mint_decimals: u8,
-    base_spl_token_decimals: u32,
-    quote_spl_token_decimals: u32,
     skip_preflight: bool,
     commitment: CommitmentConfig,
     program_id: Pubkey,
-    open_orders_accounts_cache: HashMap<Pubkey, OpenOrders>,
-    layout_override: Option<()>, 
-    fee_discount_keys_cache: HashMap<String, FeeDiscountKeyCache>,
-    
+    open_orders_accounts_cache: Cache<OpenOrders>,
+    fee_discount_keys_cache: Cache<FeeDiscountKey>,
 }
 
 impl Market {
-
     pub fn new(
-        decoded: serde::Value,
+        decoded: MarketState,
         base_mint_decimals: u8,
         quote_mint_decimals: u8,
         options: MarketOptions,
         program_id: Pubkey,
-        layout_override: Option<LayoutOverride>,
-    ) -> Result<Market, &'static str> {
+    ) -> Result<Market, SdkError> {
         let MarketOptions {
             skip_preflight,
             commitment,
         } = options;
 
-        if !decoded.account_flags.initialized || !decoded.account_flags.market {
-            return Err("Invalid market state");
-        }
+        decoded
+            .account_flags
+            .expect_market()
+            .map_err(|err| SdkError::decode(decoded.own_address, err))?;
 
         Ok(Market {
             decoded,
             base_mint_decimals,
             quote_mint_decimals,
             skip_preflight: skip_preflight.unwrap_or(false),
-            commitment: commitment.unwrap_or_else(|| "recent".to_string()),
+            commitment: commitment.unwrap_or_else(CommitmentConfig::confirmed),
             program_id,
-            open_orders_accounts_cache: HashMap::new(),
-            fee_discount_keys_cache: HashMap::new(),
-            layout_override,
+            open_orders_accounts_cache: Mutex::new(HashMap::new()),
+            fee_discount_keys_cache: Mutex::new(HashMap::new()),
         })
     }
 
-    async fn load(
+    pub async fn load(
         rpc_client: &RpcClient,
         address: Pubkey,
         options: MarketOptions,
         program_id: Pubkey,
-        layout_override: Option<&str>,
-    ) -> Result<Market, Box<dyn Error>> {
-        let account_info = rpc_client.get_account_info(&address)?;
-
-        let account = account_info
-            .ok_or_else(|| "Market not found")?;
+        layout_override: Option<MarketLayout>,
+    ) -> Result<Market, SdkError> {
+        let commitment = options
+            .commitment
+            .unwrap_or_else(CommitmentConfig::confirmed);
+        let account = get_account(rpc_client, &address, commitment).await?;
 
         if account.owner != program_id {
-            return Err(format!("Address not owned by program: {}", account.owner).into());
+            return Err(SdkError::InvalidAccountOwner {
+                account: address,
+                owner: account.owner,
+                expected: program_id,
+            });
         }
 
-        let layout = layout_override.unwrap_or_else(|| Self::get_layout(program_id));
+        let layout = layout_override.unwrap_or_else(|| {
+            MarketLayout::for_account(
+                ProgramRegistry::global().layout_version(&program_id),
+                account.data.len(),
+            )
+        });
+
+        let decoded = MarketState::decode(&account.data, layout)
+            .map_err(|err| SdkError::decode(address, err))?;
 
-        let decoded = layout.decode(&account.data);
-        
-        if !decoded.account_flags.initialized || !decoded.account_flags.market || !decoded.own_address != address {
-            return Err("Invalid market".into());
+        if decoded.own_address != address {
+            return Err(SdkError::InvalidAccount {
+                account: address,
+                reason: "market state belongs to another address",
+            });
         }
 
         let (base_mint_decimals, quote_mint_decimals) = tokio::try_join!(
-            get_mint_decimals(rpc_client, decoded.base_mint),
-            get_mint_decimals(rpc_client, decoded.quote_mint)
+            get_mint_decimals(rpc_client, &decoded.base_mint, commitment),
+            get_mint_decimals(rpc_client, &decoded.quote_mint, commitment)
         )?;
 
-        Ok(Market {
+        Market::new(
             decoded,
             base_mint_decimals,
             quote_mint_decimals,
+            options,
             program_id,
-            // layout_override,
-        }) 
-    }
-
-    pub async fn get_mint_decimals(rpc_client: &RpcClient, mint: Pubkey) -> Result<(u8, u8), Box<dyn Error>> {
-        
-        let account_info = rpc_client.get_account_info(&mint)?;
-        let account = account_info.ok_or("Mint not found")?;
-        
-        let decimals = {
-            let data = account.data.to_vec();
-            let decoded = mint_layout.decode(&data).map_err(|e| format!("Failed to decode mint data: {:?}", e))?;
-            decoded.decimals
-        };
-    
-        Ok(decimals)
+        )
+    }
+
+    /// Loads a market of `cluster` with the program it is listed under, or
+    /// the cluster's default program. The cluster's programs are registered
+    /// first so local and devnet deployments decode like mainnet ones.
+    pub async fn load_from_cluster(
+        rpc_client: &RpcClient,
+        address: Pubkey,
+        cluster: &Cluster,
+        options: MarketOptions,
+    ) -> Result<Market, SdkError> {
+        cluster.register_programs(ProgramRegistry::global());
+        let program_id = cluster.program_id_for_market(&address);
+        Self::load(rpc_client, address, options, program_id, None).await
+    }
+
+    /// Loads every market of `list` with the layout of its program. Results
+    /// are in list order, so a market that fails to load does not hide the
+    /// others.
+    pub async fn load_list(
+        rpc_client: &RpcClient,
+        list: &MarketList,
+        options: &MarketOptions,
+    ) -> Vec<Result<Market, SdkError>> {
+        let mut markets = Vec::with_capacity(list.len());
+        for market in list.markets() {
+            markets.push(
+                Self::load(
+                    rpc_client,
+                    market.address,
+                    options.clone(),
+                    market.program_id,
+                    None,
+                )
+                .await,
+            );
+        }
+        markets
     }
 
     pub fn program_id(&self) -> Pubkey {
-        self._program_id
+        self.program_id
     }
 
     pub fn address(&self) -> Pubkey {
-        self._decoded.own_address
+        self.decoded.own_address
     }
 
     pub fn public_key(&self) -> Pubkey {
@@ -168,958 +237,979 @@ impl Market {
     }
 
     pub fn base_mint_address(&self) -> Pubkey {
-        self._decoded.base_mint
+        self.decoded.base_mint
     }
 
     pub fn quote_mint_address(&self) -> Pubkey {
-        self._decoded.quote_mint
+        self.decoded.quote_mint
     }
 
     pub fn bids_address(&self) -> Pubkey {
-        self._decoded.bids
+        self.decoded.bids
     }
 
     pub fn asks_address(&self) -> Pubkey {
-        self._decoded.asks
+        self.decoded.asks
     }
 
-    pub fn decoded(&self) -> &YourDecodedStruct {
-        &self._decoded
+    pub fn decoded(&self) -> &MarketState {
+        &self.decoded
     }
 
-    fn get_layout(program_id: &Pubkey) -> &'static [u8] {
-        if get_layout_version(program_id) == 1 {
-            &_MARKET_STAT_LAYOUT_V1
-        } else {
-            &MARKET_STATE_LAYOUT_V2
-        }
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
     }
 
-    async fn find_accounts_by_mints(
-        connection: &Connection,
+    /// Markets of `program_id` trading `base_mint_address` against
+    /// `quote_mint_address`.
+    pub async fn find_accounts_by_mints(
+        connection: &RpcClient,
         base_mint_address: &Pubkey,
         quote_mint_address: &Pubkey,
         program_id: &Pubkey,
-    ) -> Result<Vec<AccountInfo>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+        let layout = Self::get_layout(program_id);
         let filters = vec![
-            Memcmp {
-                offset: Self::get_layout(program_id).offset_of("baseMint") as u64,
-                bytes: base_mint_address.to_string().into_bytes(),
-            },
-            Memcmp {
-                offset: Self::get_layout(program_id).offset_of("quoteMint") as u64,
-                bytes: quote_mint_address.to_string().into_bytes(),
-            },
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                layout.offset_of("base_mint").map_err(SdkError::Layout)?,
+                base_mint_address.to_bytes().to_vec(),
+            )),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                layout.offset_of("quote_mint").map_err(SdkError::Layout)?,
+                quote_mint_address.to_bytes().to_vec(),
+            )),
         ];
-        get_filtered_program_accounts(connection, program_id, &filters).await
+        get_filtered_program_accounts(connection, program_id, filters).await
     }
 
-    async fn load_bids(&self, connection: &RpcClient) -> Result<Orderbook, Box<dyn std::error::Error>> {
-        let bids_account = connection.get_account(&self.decoded.bids).await?.value;
-        
-        let data = bids_account.data.ok_or("Account data not found")?;
-        
-        Ok(Orderbook::decode(&self, &data))
+    pub async fn load_bids(&self, connection: &RpcClient) -> Result<Orderbook, SdkError> {
+        let account = get_account(connection, &self.decoded.bids, self.commitment).await?;
+        Orderbook::decode(self, &account.data, Side::Buy)
     }
 
-    async fn load_asks(&self, connection: &RpcClient) -> Result<Orderbook, Box<dyn std::error::Error>> {
-        let asks_account = connection.get_account(&self.decoded.asks).await?.value;
-        
-        let data = asks_account.data.ok_or("Account data not found")?;
-        
-        Ok(Orderbook::decode(&self, &data))
+    pub async fn load_asks(&self, connection: &RpcClient) -> Result<Orderbook, SdkError> {
+        let account = get_account(connection, &self.decoded.asks, self.commitment).await?;
+        Orderbook::decode(self, &account.data, Side::Sell)
     }
 
-    async fn load_orders_for_owner(
+    /// The orders of every open orders account of `owner_address` on this
+    /// market, bids first.
+    pub async fn load_orders_for_owner(
         &self,
         connection: &RpcClient,
-        owner_address: Pubkey,
-        cache_duration_ms: u64,
-    ) -> Result<Vec<Order>, Box<dyn std::error::Error>> {
+        owner_address: &Pubkey,
+        cache_duration: Duration,
+    ) -> Result<Vec<Order>, SdkError> {
         let (bids, asks, open_orders_accounts) = tokio::try_join!(
             self.load_bids(connection),
             self.load_asks(connection),
-            self.find_open_orders_accounts_for_owner(connection, owner_address, cache_duration_ms)
+            self.find_open_orders_accounts_for_owner(connection, owner_address, cache_duration)
         )?;
-        
-        let filtered_orders = self.filter_for_open_orders(&bids, &asks, &open_orders_accounts);
-        
-        Ok(filtered_orders)
-    }
-
-    pub fn filter_for_open_orders(bids: &Orderbook, asks: &Orderbook, open_orders_accounts: &[OpenOrders]) -> Vec<Order> {
-        let mut orders_map: HashMap<Pubkey, ()> = HashMap::new();
-    
-        for order in bids.iter().chain(asks.iter()) {
-            for open_orders in open_orders_accounts.iter() {
-                if order.open_orders_address == open_orders.address {
-                    orders_map.insert(order.id, ());
-                }
-            }
-        }
-    
-        let filtered_orders: Vec<Order> = bids
+        Ok(Self::filter_for_open_orders(
+            &bids,
+            &asks,
+            &open_orders_accounts,
+        ))
+    }
+
+    pub fn filter_for_open_orders(
+        bids: &Orderbook,
+        asks: &Orderbook,
+        open_orders_accounts: &[OpenOrders],
+    ) -> Vec<Order> {
+        let addresses: HashSet<Pubkey> = open_orders_accounts
             .iter()
-            .cloned()
-            .chain(asks.iter().cloned())
-            .filter(|order| orders_map.contains_key(&order.id))
+            .map(|open_orders| open_orders.address)
             .collect();
-    
-        filtered_orders
+        let mut orders = bids.orders_for(&addresses);
+        orders.extend(asks.orders_for(&addresses));
+        orders
     }
 
-    async fn find_base_token_accounts_for_owner(
-        connection: Arc<RpcClient>,
-        owner_address: Pubkey,
-        include_unwrapped_sol: bool,
-    ) -> Result<Vec<(Pubkey, Account)>, Box<dyn std::error::Error>> {
-        if base_mint_address == WRAPPED_SOL_MINT && include_unwrapped_sol {
-            // let (wrapped, unwrapped) = tokio::try_join!(
-            //     find_base_token_accounts_for_owner(connection.clone(), owner_address, false),
-            //     connection.get_account(&owner_address),
-            // )?;
-            
-            if let Some(unwrapped_account) = unwrapped {
-                let mut accounts = vec![(owner_address, unwrapped_account)];
-                accounts.extend(wrapped);
-                return Ok(accounts);
-            }
-            
-            return Ok(wrapped);
-        }
-        
-        let token_accounts = get_token_accounts_by_owner_for_mint(connection.clone(), owner_address, base_mint_address).await?;
-        Ok(token_accounts)
-    }  
-    
-    async fn get_token_accounts_by_owner_for_mint(
-        connection: &solana_client::rpc_client::RpcClient,
-        owner_address: Pubkey,
-        mint_address: Pubkey,
-    ) -> Result<Vec<(Pubkey, Account)>, Box<dyn std::error::Error>> {
-        let token_accounts = connection
-            .get_token_accounts_by_owner(&owner_address, Some(solana_client::rpc_config::RpcTokenAccountsFilter::Mint(mint_address)))
-            .await?;
-        
-        Ok(token_accounts.value.into_iter().map(|(pubkey, account)| (pubkey, account)).collect())
+    pub async fn load_request_queue(
+        &self,
+        connection: &RpcClient,
+    ) -> Result<Vec<Request>, SdkError> {
+        let address = self.decoded.request_queue;
+        let account = get_account(connection, &address, self.commitment).await?;
+        decode_request_queue(&account.data, None).map_err(|err| SdkError::decode(address, err))
     }
 
-    async fn find_quote_token_accounts_for_owner(
+    pub async fn load_event_queue(&self, connection: &RpcClient) -> Result<Vec<Event>, SdkError> {
+        let address = self.decoded.event_queue;
+        let account = get_account(connection, &address, self.commitment).await?;
+        decode_event_queue(&account.data, None).map_err(|err| SdkError::decode(address, err))
+    }
+
+    /// The most recent fills of the event queue, at most `limit` events
+    /// deep.
+    pub async fn load_fills(
+        &self,
         connection: &RpcClient,
-        owner_address: &Pubkey,
-        include_unwrapped_sol: bool,
-    ) -> Vec<(Pubkey, Account)> {
-        if this.quote_mint_address == WRAPPED_SOL_MINT && include_unwrapped_sol {
-            let (wrapped, unwrapped) = futures::join!(
-                find_quote_token_accounts_for_owner(connection, owner_address, false),
-                connection.get_account(owner_address)
-            );
-            
-            if let Some(unwrapped_account) = unwrapped {
-                return vec![(owner_address.clone(), unwrapped_account), wrapped];
-            }
-            
-            return wrapped;
+        limit: usize,
+    ) -> Result<Vec<FillEvent>, SdkError> {
+        let address = self.decoded.event_queue;
+        let account = get_account(connection, &address, self.commitment).await?;
+        let events = decode_event_queue(&account.data, Some(limit))
+            .map_err(|err| SdkError::decode(address, err))?;
+
+        Ok(events
+            .into_iter()
+            .filter(|event| {
+                event.event_flags.contains(EventFlags::FILL) && event.native_quantity_paid > 0
+            })
+            .map(|event| self.parse_fill_event(event))
+            .collect())
+    }
+
+    fn parse_fill_event(&self, event: Event) -> FillEvent {
+        let conversion = self.lot_conversion();
+        let is_maker = event.event_flags.contains(EventFlags::MAKER);
+
+        // Fees are paid in quote. Takers pay them on top of what they buy
+        // with and out of what they sell for, makers get rebates the other
+        // way round.
+        let (side, native_quote, native_base) = if event.event_flags.contains(EventFlags::BID) {
+            let quote = if is_maker {
+                event.native_quantity_paid + event.native_fee_or_rebate
+            } else {
+                event.native_quantity_paid - event.native_fee_or_rebate
+            };
+            (
+                Side::Buy,
+                NativeQuote::new(quote),
+                NativeBase::new(event.native_quantity_released),
+            )
+        } else {
+            let quote = if is_maker {
+                event.native_quantity_released - event.native_fee_or_rebate
+            } else {
+                event.native_quantity_released + event.native_fee_or_rebate
+            };
+            (
+                Side::Sell,
+                NativeQuote::new(quote),
+                NativeBase::new(event.native_quantity_paid),
+            )
+        };
+
+        let price = conversion
+            .native_price_to_number(native_quote, native_base, Rounding::Nearest)
+            .unwrap_or_default();
+        let size = conversion
+            .base_spl_size_to_number(native_base, Rounding::Nearest)
+            .unwrap_or_default();
+        let fee = conversion
+            .quote_spl_size_to_number(
+                NativeQuote::new(event.native_fee_or_rebate),
+                Rounding::Nearest,
+            )
+            .unwrap_or_default();
+
+        FillEvent {
+            event,
+            side,
+            price,
+            fee_cost: if is_maker { -fee } else { fee },
+            size,
         }
-    
-        return get_token_accounts_by_owner_for_mint(connection, owner_address, this.quote_mint_address);
     }
 
-    struct OpenOrdersCache {
-        accounts: Vec<OpenOrders>,
-        ts: u128,
+    pub fn supports_srm_fee_discounts(&self) -> bool {
+        ProgramRegistry::global().supports_srm_fee_discounts(&self.program_id)
     }
 
-    pub async fn find_open_orders_accounts_for_owner(
+    pub fn supports_referral_fees(&self) -> bool {
+        ProgramRegistry::global().supports_referral_fees(&self.program_id)
+    }
+
+    pub fn uses_request_queue(&self) -> bool {
+        ProgramRegistry::global().uses_request_queue(&self.program_id)
+    }
+
+    /// Token accounts of `owner_address` for the base mint. For wrapped SOL
+    /// markets the owner's own account comes first when
+    /// `include_unwrapped_sol` is set.
+    pub async fn find_base_token_accounts_for_owner(
+        &self,
         connection: &RpcClient,
         owner_address: &Pubkey,
-        cache_duration_ms: u128,
-        force_seed_account: bool,
-        open_orders_accounts_cache: &mut HashMap<String, OpenOrdersCache>,
-    ) -> Vec<OpenOrders> {
-        let str_owner = owner_address.to_string();
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis();
-        
-        if let Some(cache) = open_orders_accounts_cache.get(&str_owner) {
-            if now - cache.ts < cache_duration_ms {
-                return cache.accounts.clone();
-            }
-        }
-    
-        let open_orders_accounts_for_owner = OpenOrders::find_for_market_and_owner(
+        include_unwrapped_sol: bool,
+    ) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+        self.find_token_accounts_for_owner(
             connection,
-            &self.address,
             owner_address,
-            &self.program_id,
-            force_seed_account,
-        ).await;
-    
-        open_orders_accounts_cache.insert(str_owner.clone(), OpenOrdersCache {
-            accounts: open_orders_accounts_for_owner.clone(),
-            ts: now
-        });
-    
-        open_orders_accounts_for_owner
+            &self.decoded.base_mint,
+            include_unwrapped_sol,
+        )
+        .await
     }
 
-    #[derive(Default)]
-    pub struct OrderParamsAccounts<T = Account> {
-        pub owner: T,
-        pub payer: Pubkey,
-        pub open_orders_address_key: Option<Pubkey>,
-        pub open_orders_account: Option<T>,
-        pub fee_discount_pubkey: Option<Pubkey>,
-        pub program_id: Option<Pubkey>,
+    /// Token accounts of `owner_address` for the quote mint, as
+    /// `find_base_token_accounts_for_owner`.
+    pub async fn find_quote_token_accounts_for_owner(
+        &self,
+        connection: &RpcClient,
+        owner_address: &Pubkey,
+        include_unwrapped_sol: bool,
+    ) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+        self.find_token_accounts_for_owner(
+            connection,
+            owner_address,
+            &self.decoded.quote_mint,
+            include_unwrapped_sol,
+        )
+        .await
     }
 
-    async fn replace_orders(
+    async fn find_token_accounts_for_owner(
+        &self,
         connection: &RpcClient,
-        accounts: &mut OrderParamsAccounts,
-        orders: Vec<OrderParamsBase>,
-        cache_duration_ms: u128,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        if accounts.open_orders_account.is_none() && accounts.open_orders_address_key.is_none() {
-            let owner_address: Pubkey = accounts.owner;
-            let open_orders_accounts = find_open_orders_accounts_for_owner(connection, &owner_address, cache_duration_ms).await;
-            
-            if let Some(first_open_orders) = open_orders_accounts.get(0) {
-                accounts.open_orders_address_key = Some(first_open_orders.address);
-            }
+        owner_address: &Pubkey,
+        mint_address: &Pubkey,
+        include_unwrapped_sol: bool,
+    ) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+        let wrapped =
+            self.get_token_accounts_by_owner_for_mint(connection, owner_address, mint_address);
+        if *mint_address != WRAPPED_SOL_MINT || !include_unwrapped_sol {
+            return wrapped.await;
         }
-    
-        let mut transaction = Transaction::new();
-        let replace_orders_instruction = make_replace_orders_by_client_ids_instruction(accounts, orders);
-        transaction.add(replace_orders_instruction);
-    
-        let owner = vec![accounts.owner];
-        let result = send_transaction(connection, &transaction, &owner).await;
-        Ok(result)
+
+        let unwrapped = async {
+            Ok::<_, SdkError>(
+                connection
+                    .get_account_with_commitment(owner_address, self.commitment)
+                    .await?
+                    .value,
+            )
+        };
+        let (wrapped, unwrapped) = tokio::try_join!(wrapped, unwrapped)?;
+        Ok(unwrapped
+            .map(|account| (*owner_address, account))
+            .into_iter()
+            .chain(wrapped)
+            .collect())
     }
 
-    async fn place_order(
-        connection: &RpcClient,
-        order_params: OrderParams,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let OrderParams {
-            owner,
-            payer,
-            side,
-            price,
-            size,
-            order_type,
-            client_id,
-            open_orders_address_key,
-            open_orders_account,
-            fee_discount_pubkey,
-            max_ts,
-            replace_if_exists,
-        } = order_params;
-    
-        let place_order_transaction = make_place_order_transaction::<Account>(connection, PlaceOrderTransactionParams {
-            owner,
-            payer,
-            side,
-            price,
-            size,
-            order_type,
-            client_id,
-            open_orders_address_key,
-            open_orders_account,
-            fee_discount_pubkey,
-            max_ts,
-            replace_if_exists,
-        }).await?;
-    
-        let mut signers = place_order_transaction.signers.clone();
-        signers.insert(0, owner);
-    
-        let transaction = place_order_transaction.transaction;
-        let result = send_transaction(connection, &transaction, &signers).await;
-        Ok(result)
-    }
-
-    async fn send_take(
+    /// Requested with base64 encoding, as `get_token_accounts_by_owner`
+    /// parses the accounts into JSON instead.
+    async fn get_token_accounts_by_owner_for_mint(
+        &self,
         connection: &RpcClient,
-        send_take_params: SendTakeParams,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let SendTakeParams {
-            owner,
-            base_wallet,
-            quote_wallet,
-            side,
-            price,
-            max_base_size,
-            max_quote_size,
-            min_base_size,
-            min_quote_size,
-            limit,
-            program_id,
-            fee_discount_pubkey,
-        } = send_take_params;
-    
-        let send_take_transaction = make_send_take_transaction::<Account>(connection, SendTakeTransactionParams {
-            owner,
-            base_wallet,
-            quote_wallet,
-            side,
-            price,
-            max_base_size,
-            max_quote_size,
-            min_base_size,
-            min_quote_size,
-            limit,
-            program_id.unwrap_or_default(),
-            fee_discount_pubkey.unwrap_or_default(),
-        }).await?;
-    
-        let mut signers = send_take_transaction.signers.clone();
-        signers.insert(0, owner); 
-    
-        let transaction = send_take_transaction.transaction;
-        let result = send_transaction(connection, &transaction, &signers).await;
-        Ok(result)
+        owner_address: &Pubkey,
+        mint_address: &Pubkey,
+    ) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment),
+            ..RpcAccountInfoConfig::default()
+        };
+        let response: Response<Vec<RpcKeyedAccount>> = connection
+            .send(
+                RpcRequest::GetTokenAccountsByOwner,
+                json!([
+                    owner_address.to_string(),
+                    { "mint": mint_address.to_string() },
+                    config,
+                ]),
+            )
+            .await?;
+        response
+            .value
+            .into_iter()
+            .map(|keyed| {
+                let pubkey = keyed.pubkey.parse::<Pubkey>()?;
+                let account = keyed.account.decode().ok_or(SdkError::InvalidAccount {
+                    account: pubkey,
+                    reason: "token account data is not base64",
+                })?;
+                Ok((pubkey, account))
+            })
+            .collect()
     }
 
-    fn get_spl_token_balance_from_account_info(account_info: &AccountInfo<>, decimals: u32) -> f64 {
-        let data_slice = &account_info.data[64..72];
-        let balance_big_uint = BigUint::from_bytes_le(data_slice);
-        let scale_factor = 10u32.to_biguint().unwrap().pow(decimals);
-        
-        let balance = balance_big_uint / scale_factor;
-        
-        balance.to_f64().unwrap()
+    /// The open orders accounts of `owner_address` on this market, reused
+    /// for `cache_duration` after they were fetched.
+    pub async fn find_open_orders_accounts_for_owner(
+        &self,
+        connection: &RpcClient,
+        owner_address: &Pubkey,
+        cache_duration: Duration,
+    ) -> Result<Vec<OpenOrders>, SdkError> {
+        if let Some(accounts) = get_cached(
+            &self.open_orders_accounts_cache,
+            owner_address,
+            cache_duration,
+        ) {
+            return Ok(accounts);
+        }
+        let accounts = OpenOrders::find_for_market_and_owner(
+            connection,
+            &self.address(),
+            owner_address,
+            &self.program_id,
+            false,
+        )
+        .await?;
+        set_cached(&self.open_orders_accounts_cache, owner_address, &accounts);
+        Ok(accounts)
     }
 
-    fn supports_srm_fee_discounts(program_id: &Pubkey) -> bool {
-        supports_srm_fee_discounts(this._programId)
+    /// Places an order, creating the owner's open orders account on their
+    /// first order and wrapping SOL when the owner pays with it.
+    pub async fn place_order(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        params: &OrderParams,
+    ) -> Result<Signature, SdkError> {
+        let prepared = self
+            .make_place_order_transaction(connection, params, Duration::ZERO, Duration::ZERO)
+            .await?;
+        self.send_prepared_transaction(connection, prepared, owner)
+            .await
     }
-    
-    fn supports_referral_fees(program_id: &Pubkey) -> bool {
-        get_layout_version(program_id) > 1
+
+    /// Replaces orders of the owner by client id, in one transaction.
+    pub async fn replace_orders(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        accounts: &OrderParamsAccounts,
+        orders: &[OrderParamsBase],
+        cache_duration: Duration,
+    ) -> Result<Signature, SdkError> {
+        let mut accounts = *accounts;
+        if accounts.open_orders_address_key.is_none() {
+            let open_orders_accounts = self
+                .find_open_orders_accounts_for_owner(connection, &accounts.owner, cache_duration)
+                .await?;
+            accounts.open_orders_address_key = open_orders_accounts
+                .first()
+                .map(|open_orders| open_orders.address);
+        }
+        let instruction = self.make_replace_orders_by_client_ids_instruction(&accounts, orders)?;
+        let transaction = Transaction::new_with_payer(&[instruction], Some(&accounts.owner));
+        self.send_transaction(connection, transaction, &[owner])
+            .await
     }
-    
-    fn uses_request_queue(program_id: &Pubkey) -> bool {
-        get_layout_version(program_id) <= 2
+
+    pub async fn send_take(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        params: &SendTakeParams,
+    ) -> Result<Signature, SdkError> {
+        let prepared = self
+            .make_send_take_transaction(connection, params, Duration::ZERO)
+            .await?;
+        self.send_prepared_transaction(connection, prepared, owner)
+            .await
     }
 
-    async fn find_fee_discount_keys(
+    /// The SRM and MSRM accounts of `owner_address`, best fee tier and then
+    /// largest balance first. Empty for programs without SRM discounts.
+    pub async fn find_fee_discount_keys(
+        &self,
         connection: &RpcClient,
         owner_address: &Pubkey,
-        cache_duration_ms: u128,
-    ) -> Vec<FeeDiscountKey> {
-        let mut sorted_accounts: Vec<FeeDiscountKey> = Vec::new();
-        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-        let str_owner = owner_address.to_string();
-
-        if let Some(cache) = fee_discount_keys_cache.get(&str_owner) {
-            if now - cache.ts < cache_duration_ms {
-                return cache.accounts.clone();
-            }
+        cache_duration: Duration,
+    ) -> Result<Vec<FeeDiscountKey>, SdkError> {
+        if !self.supports_srm_fee_discounts() {
+            return Ok(Vec::new());
         }
-
-        if supports_srm_fee_discounts(&program_id) {
-            let msrm_accounts = get_token_accounts_by_owner_for_mint(connection, owner_address, MSRM_MINT).await
-                .into_iter()
-                .map(|(pubkey, account)| {
-                    let balance = get_spl_token_balance_from_account_info(&account, MSRM_DECIMALS);
-                    FeeDiscountKey {
-                        pubkey: pubkey,
-                        mint: MSRM_MINT,
-                        balance: balance,
-                        fee_tier: get_fee_tier(balance, 0),
-                    }
-                })
-                .collect::<Vec<FeeDiscountKey>>();
-
-            let srm_accounts = get_token_accounts_by_owner_for_mint(connection, owner_address, SRM_MINT).await
-                .into_iter()
-                .map(|(pubkey, account)| {
-                    let balance = get_spl_token_balance_from_account_info(&account, SRM_DECIMALS);
-                    FeeDiscountKey {
-                        pubkey: pubkey,
-                        mint: SRM_MINT,
-                        balance: balance,
-                        fee_tier: get_fee_tier(0, balance),
-                    }
-                })
-                .collect::<Vec<FeeDiscountKey>>();
-
-            sorted_accounts = msrm_accounts.iter().chain(srm_accounts.iter()).cloned().collect();
-            sorted_accounts.sort_by(|a, b| {
-                if a.fee_tier > b.fee_tier {
-                    return std::cmp::Ordering::Less;
-                } else if a.fee_tier < b.fee_tier {
-                    return std::cmp::Ordering::Greater;
-                } else {
-                    if a.balance > b.balance {
-                        return std::cmp::Ordering::Less;
-                    } else if a.balance < b.balance {
-                        return std::cmp::Ordering::Greater;
-                    } else {
-                        return std::cmp::Ordering::Equal;
-                    }
-                }
-            });
+        if let Some(keys) = get_cached(&self.fee_discount_keys_cache, owner_address, cache_duration)
+        {
+            return Ok(keys);
         }
 
-        fee_discount_keys_cache.insert(str_owner.clone(), FeeDiscountKeysCache {
-            accounts: sorted_accounts.clone(),
-            ts: now,
+        let (msrm_accounts, srm_accounts) = tokio::try_join!(
+            self.get_token_accounts_by_owner_for_mint(connection, owner_address, &MSRM_MINT),
+            self.get_token_accounts_by_owner_for_mint(connection, owner_address, &SRM_MINT)
+        )?;
+        let msrm_keys = msrm_accounts.iter().map(|(pubkey, account)| {
+            let balance = get_spl_token_balance(account, MSRM_DECIMALS);
+            FeeDiscountKey {
+                pubkey: *pubkey,
+                mint: MSRM_MINT,
+                balance,
+                fee_tier: get_fee_tier(balance, 0.0),
+            }
         });
+        let srm_keys = srm_accounts.iter().map(|(pubkey, account)| {
+            let balance = get_spl_token_balance(account, SRM_DECIMALS);
+            FeeDiscountKey {
+                pubkey: *pubkey,
+                mint: SRM_MINT,
+                balance,
+                fee_tier: get_fee_tier(0.0, balance),
+            }
+        });
+        let mut keys: Vec<FeeDiscountKey> = msrm_keys.chain(srm_keys).collect();
+        keys.sort_by(|a, b| {
+            b.fee_tier
+                .cmp(&a.fee_tier)
+                .then(b.balance.total_cmp(&a.balance))
+        });
+
+        set_cached(&self.fee_discount_keys_cache, owner_address, &keys);
+        Ok(keys)
+    }
 
-        sorted_accounts
+    /// The key with the best fee tier, `None` when the owner holds no SRM
+    /// or MSRM.
+    pub async fn find_best_fee_discount_key(
+        &self,
+        connection: &RpcClient,
+        owner_address: &Pubkey,
+        cache_duration: Duration,
+    ) -> Result<Option<FeeDiscountKey>, SdkError> {
+        let keys = self
+            .find_fee_discount_keys(connection, owner_address, cache_duration)
+            .await?;
+        Ok(keys.first().copied())
     }
 
-    async fn find_best_fee_discount_key(
+    /// `fee_discount_pubkey`, or the owner's best fee discount key when it
+    /// is not given and the program supports them.
+    async fn resolve_fee_discount_pubkey(
+        &self,
         connection: &RpcClient,
         owner_address: &Pubkey,
-        cache_duration_ms: u128,
-    ) -> FeeDiscountKey {
-        let accounts = find_fee_discount_keys(connection, owner_address, cache_duration_ms).await;
-        
-        if let Some(best_account) = accounts.iter().min_by(|a, b| a.fee_tier.cmp(&b.fee_tier)) {
-            return FeeDiscountKey {
-                pubkey: best_account.pubkey,
-                fee_tier: best_account.fee_tier,
-            };
-        }
-    
-        FeeDiscountKey {
-            pubkey: Pubkey::default(), 
-            fee_tier: 0,
+        fee_discount_pubkey: Option<Pubkey>,
+        cache_duration: Duration,
+    ) -> Result<Option<Pubkey>, SdkError> {
+        if fee_discount_pubkey.is_some() || !self.supports_srm_fee_discounts() {
+            return Ok(fee_discount_pubkey);
         }
+        let key = self
+            .find_best_fee_discount_key(connection, owner_address, cache_duration)
+            .await?;
+        Ok(key.map(|key| key.pubkey))
     }
 
-    async fn make_place_order_transaction<T: Pack>(
+    pub async fn make_place_order_transaction(
+        &self,
         connection: &RpcClient,
-        order_params: OrderParams<T>,
-        cache_duration_ms: u64,
-        fee_discount_pubkey_cache_duration_ms: u64,
-    ) -> Result<(Transaction, Vec<Account>, Pubkey), Box<dyn std::error::Error> {
-        let owner_address: Pubkey = order_params.owner.public_key.unwrap_or(order_params.owner);
-        let open_orders_accounts = find_open_orders_accounts_for_owner(connection, &owner_address, cache_duration_ms).await?;
-        let mut transaction = Transaction::new();
-        let mut signers: Vec<Account> = Vec::new();
-    
-        let use_fee_discount_pubkey: Option<Pubkey>;
-        if let Some(fee_discount_pubkey) = order_params.fee_discount_pubkey {
-            use_fee_discount_pubkey = Some(fee_discount_pubkey);
-        } else if fee_discount_pubkey == None && supports_srm_fee_discounts {
-            use_fee_discount_pubkey = Some(
-                find_best_fee_discount_key(connection, &owner_address, fee_discount_pubkey_cache_duration_ms).await?.pubkey
-            );
+        params: &OrderParams,
+        cache_duration: Duration,
+        fee_discount_pubkey_cache_duration: Duration,
+    ) -> Result<PreparedTransaction, SdkError> {
+        // Orders below one tick or lot fail here, before any RPC call. The
+        // owner's fee tier is not known yet, and tier 0 has the highest fee.
+        let order = self
+            .order_validator(0)
+            .validate(params.side, params.price, params.size)?;
+        let owner_address = params.owner;
+        let open_orders_accounts = self
+            .find_open_orders_accounts_for_owner(connection, &owner_address, cache_duration)
+            .await?;
+        let mut instructions = Vec::new();
+        let mut signers = Vec::new();
+
+        let fee_discount_pubkey = self
+            .resolve_fee_discount_pubkey(
+                connection,
+                &owner_address,
+                params.fee_discount_pubkey,
+                fee_discount_pubkey_cache_duration,
+            )
+            .await?;
+
+        let open_orders_address = if let Some(open_orders) = open_orders_accounts.first() {
+            params
+                .open_orders_address_key
+                .unwrap_or(open_orders.address)
         } else {
-            use_fee_discount_pubkey = None;
-        }
-    
-        let open_orders_address: Pubkey;
-        if open_orders_accounts.is_empty() {
-            let account: Account;
-            if let Some(open_orders_account) = order_params.open_orders_account {
-                account = open_orders_account;
-            } else {
-                account = OpenOrders::get_derived_oo_account_pubkey(&owner_address, &address, &program_id).await?;
-            }
-            transaction.add(
-                OpenOrders::make_create_account_transaction(connection, &address, &owner_address, &account.public_key, &program_id, &account.seed)
-                .await?
+            let (address, seed) = OpenOrders::get_derived_oo_account_pubkey(
+                &owner_address,
+                &self.address(),
+                &self.program_id,
+            )?;
+            instructions.push(
+                OpenOrders::make_create_account_instruction(
+                    connection,
+                    &owner_address,
+                    &address,
+                    &self.program_id,
+                    &seed,
+                )
+                .await?,
             );
-            open_orders_address = account.public_key;
-            open_orders_accounts_cache.get_mut(&owner_address.to_string()).unwrap().ts = 0;
-        } else if let Some(open_orders_account) = order_params.open_orders_account {
-            open_orders_address = open_orders_account.public_key;
-        } else if let Some(open_orders_address_key) = order_params.open_orders_address_key {
-            open_orders_address = open_orders_address_key;
-        } else {
-            open_orders_address = open_orders_accounts[0].address;
-        }
-    
-        let mut wrapped_sol_account: Option<Account> = None;
-        if payer == owner_address {
-            if (side == "buy" && quote_mint_address == wrapped_sol_mint) || (side == "sell" && base_mint_address == wrapped_sol_mint) {
-                wrapped_sol_account = Some(Account::new());
-                let mut lamports;
-                if side == "buy" {
-                    lamports = ((price * size * 1.01) * LAMPORTS_PER_SOL) as u64;
-                    if !open_orders_accounts.is_empty() {
-                        lamports -= open_orders_accounts[0].quote_token_free as u64;
-                    }
-                } else {
-                    lamports = (size * LAMPORTS_PER_SOL) as u64;
-                    if !open_orders_accounts.is_empty() {
-                        lamports -= open_orders_accounts[0].base_token_free as u64;
-                    }
-                }
-                lamports = lamports.max(0) + 10_000_000;
-                transaction.add(system_instruction::create_account(
+            // The next lookup has to find the new account.
+            self.open_orders_accounts_cache
+                .lock()
+                .unwrap()
+                .remove(&owner_address);
+            address
+        };
+
+        let mut payer = params.payer;
+        let mut wrapped_sol_account = None;
+        if params.payer == owner_address {
+            let pays_in_sol = match params.side {
+                Side::Buy => self.decoded.quote_mint == WRAPPED_SOL_MINT,
+                Side::Sell => self.decoded.base_mint == WRAPPED_SOL_MINT,
+            };
+            if !pays_in_sol {
+                return Err(SdkError::InvalidAccount {
+                    account: params.payer,
+                    reason: "payer must be a token account unless it is the owner paying in SOL",
+                });
+            }
+            let open_orders = open_orders_accounts.first();
+            let lamports = match params.side {
+                Side::Buy => order.max_quote_quantity.get().saturating_sub(
+                    open_orders.map_or(0, |open_orders| open_orders.quote_token_free),
+                ),
+                Side::Sell => self
+                    .lot_conversion()
+                    .base_lots_to_native(order.max_base_quantity)
+                    .map_or(0, NativeBase::get)
+                    .saturating_sub(
+                        open_orders.map_or(0, |open_orders| open_orders.base_token_free),
+                    ),
+            };
+            let account = Keypair::new();
+            instructions.extend(
+                self.make_wrapped_sol_account_instructions(
+                    connection,
+                    &account.pubkey(),
                     &owner_address,
-                    &wrapped_sol_account.public_key,
                     lamports,
-                    165,
-                    &token_program_id,
-                ));
-                transaction.add(initialize_account(&wrapped_sol_account.public_key, &wrapped_sol_mint, &owner_address));
-                signers.push(wrapped_sol_account);
-            } else {
-                return Err(Box::from("Invalid payer account"));
-            }
-        }
-    
-        let place_order_instruction = make_place_order_instruction(connection, PlaceOrderInstructionParams {
-            owner,
-            payer: wrapped_sol_account.map_or(payer, |acc| acc.public_key),
-            side,
-            price,
-            size,
-            order_type,
-            client_id,
-            open_orders_address_key: open_orders_address,
-            fee_discount_pubkey: use_fee_discount_pubkey,
-            self_trade_behavior,
-            max_ts,
-            replace_if_exists,
-        });
-        transaction.add(place_order_instruction);
-    
-        if let Some(wrapped_sol_account) = wrapped_sol_account {
-            transaction.add(close_account(&wrapped_sol_account.public_key, &owner_address));
+                )
+                .await?,
+            );
+            payer = account.pubkey();
+            wrapped_sol_account = Some(account);
         }
-    
-        Ok((transaction, signers, owner))
-    }
-
-    struct OrderParams {
-        owner: Pubkey,
-        payer: Pubkey,
-        side: u8,
-        price: u64,
-        size: u64,
-        order_type: String,
-        client_id: Option<u64>,
-        open_orders_address_key: Pubkey,
-        open_orders_account: Option<Pubkey>,
-        fee_discount_pubkey: Option<Pubkey>,
-        self_trade_behavior: String,
-        program_id: Pubkey,
-        max_ts: Option<u64>,
-        replace_if_exists: bool,
-    }
-    
-    fn make_place_order_instruction<T: Pubkey + Account>(
-        connection: Connection,
-        params: OrderParams<T>,
-    ) -> TransactionInstruction {
-        let OrderParams {
-            owner,
+
+        instructions.push(self.make_place_order_instruction(&OrderParams {
             payer,
-            side,
-            price,
-            size,
-            order_type,
-            client_id,
-            open_orders_address_key,
-            open_orders_account,
+            open_orders_address_key: Some(open_orders_address),
             fee_discount_pubkey,
-        } = params;
-    
-        let owner_address: Pubkey = owner.public_key.unwrap_or(owner);
-    
-        if base_size_number_to_lots(size) <= 0 {
-            panic!("size too small");
-        }
-        if price_number_to_lots(price) <= 0 {
-            panic!("invalid price");
-        }
-    
-        if uses_request_queue {
-            DexInstructions::new_order(NewOrderParams {
-                market: self.address,
-                request_queue: self.decoded.request_queue,
-                base_vault: self.decoded.base_vault,
-                quote_vault: self.decoded.quote_vault,
-                open_orders: open_orders_account.map_or(open_orders_address_key, |account| account.public_key),
-                owner: owner_address,
-                payer,
-                side,
-                limit_price: price_number_to_lots(price),
-                max_quantity: base_size_number_to_lots(size),
-                order_type,
-                client_id,
-                program_id: self.program_id,
-                // @ts-ignore
-                fee_discount_pubkey: if supports_srm_fee_discounts {
-                    fee_discount_pubkey
-                } else {
-                    None
-                },
-            });
-        } else {
-            self.make_new_order_v3_instruction(params);
+            ..*params
+        })?);
+
+        if let Some(account) = wrapped_sol_account {
+            instructions.push(spl_token::instruction::close_account(
+                &TOKEN_PROGRAM_ID,
+                &account.pubkey(),
+                &owner_address,
+                &owner_address,
+                &[],
+            )?);
+            signers.push(account);
         }
+
+        Ok(PreparedTransaction {
+            transaction: Transaction::new_with_payer(&instructions, Some(&owner_address)),
+            signers,
+        })
     }
 
-    fn make_new_order_v3_instruction<T: Pubkey + Account>(
-        params: OrderParams<T>,
-    ) -> TransactionInstruction {
-        let OrderParams {
-            owner,
-            payer,
-            side,
-            price,
-            size,
-            order_type,
-            client_id,
-            open_orders_address_key,
-            open_orders_account,
-            fee_discount_pubkey,
-            self_trade_behavior,
-            program_id,
-            max_ts,
-            replace_if_exists,
-        } = params;
-    
-        let owner_address: Pubkey = owner.public_key.unwrap_or(owner);
-    
-        DexInstructions::new_order_v3(NewOrderV3Params {
-            market: self.address,
-            bids: self.decoded.bids,
-            asks: self.decoded.asks,
+    /// Creates `account` as a wrapped SOL token account of `owner_address`
+    /// holding `lamports` on top of its rent.
+    async fn make_wrapped_sol_account_instructions(
+        &self,
+        connection: &RpcClient,
+        account: &Pubkey,
+        owner_address: &Pubkey,
+        lamports: u64,
+    ) -> Result<Vec<Instruction>, SdkError> {
+        let span = spl_token::state::Account::LEN;
+        let rent = connection
+            .get_minimum_balance_for_rent_exemption(span)
+            .await?;
+        Ok(vec![
+            system_instruction::create_account(
+                owner_address,
+                account,
+                lamports.saturating_add(rent),
+                span as u64,
+                &TOKEN_PROGRAM_ID,
+            ),
+            spl_token::instruction::initialize_account(
+                &TOKEN_PROGRAM_ID,
+                account,
+                &WRAPPED_SOL_MINT,
+                owner_address,
+            )?,
+        ])
+    }
+
+    /// `NewOrder` on programs with a request queue, `NewOrderV3` otherwise.
+    /// `params.open_orders_address_key` is required.
+    pub fn make_place_order_instruction(
+        &self,
+        params: &OrderParams,
+    ) -> Result<Instruction, SdkError> {
+        if !self.uses_request_queue() {
+            return self.make_new_order_v3_instruction(params);
+        }
+
+        let order = self
+            .order_validator(0)
+            .validate(params.side, params.price, params.size)?;
+        Ok(DexInstructions::new_order(NewOrderParams {
+            market: self.address(),
+            open_orders: open_orders_address(params.open_orders_address_key)?,
             request_queue: self.decoded.request_queue,
-            event_queue: self.decoded.event_queue,
+            payer: params.payer,
+            owner: params.owner,
             base_vault: self.decoded.base_vault,
             quote_vault: self.decoded.quote_vault,
-            open_orders: open_orders_account.map_or(open_orders_address_key, |account| account.public_key),
-            owner: owner_address,
-            payer,
-            side,
-            limit_price: price_number_to_lots(price),
-            max_base_quantity: base_size_number_to_lots(size),
-            max_quote_quantity: self.decoded.quote_lot_size.into()
-                * (base_size_number_to_lots(size) * price_number_to_lots(price)),
-            order_type,
-            client_id,
-            program_id: program_id.unwrap_or_else(|| self.program_id),
-            self_trade_behavior,
-            fee_discount_pubkey: if supports_srm_fee_discounts {
-                fee_discount_pubkey
-            } else {
-                None
-            },
-            max_ts,
-            replace_if_exists,
-        });
+            side: params.side,
+            limit_price: order.limit_price,
+            max_quantity: order.max_base_quantity,
+            order_type: params.order_type.unwrap_or(OrderType::Limit),
+            client_id: params.client_id.unwrap_or(0),
+            fee_discount_pubkey: self.fee_discount_pubkey(params.fee_discount_pubkey),
+            program_id: params.program_id.unwrap_or(self.program_id),
+        }))
     }
 
-    async fn make_send_take_transaction<'a, T: Pubkey + Account>(
-        connection: Connection,
-        params: SendTakeParams<T>,
-        fee_discount_pubkey_cache_duration_ms: u64,
-    ) -> Result<{ transaction: Transaction, signers: Vec<Account>, payer: Pubkey }, Box<dyn Error>> {
-        let owner_address: Pubkey = owner.public_key.unwrap_or(owner);
-        let mut transaction = Transaction::new();
-        let mut signers: Vec<Account> = vec![];
-    
-        let vault_signer = Pubkey::create_program_address(
-            &[
-                self.address.to_bytes(),
-                self.decoded.vault_signer_nonce.to_le_bytes(),
-            ],
-            &self.program_id,
-        )?;
-    
-        let mut use_fee_discount_pubkey: Option<Pubkey> = None;
-        if let Some(fee_discount_key) = fee_discount_pubkey {
-            use_fee_discount_pubkey = Some(fee_discount_key);
-        } else if fee_discount_pubkey.is_none() && self.supports_srm_fee_discounts {
-            let best_fee_discount_key = self.find_best_fee_discount_key(
-                &connection,
-                &owner_address,
-                fee_discount_pubkey_cache_duration_ms,
-            ).await?;
-            use_fee_discount_pubkey = Some(best_fee_discount_key.pubkey);
-        }
-    
-        let send_take_instruction = self.make_send_take_instruction(SendTakeInstructionParams {
-            owner,
-            base_wallet,
-            quote_wallet,
-            vault_signer,
-            side,
-            price,
-            max_base_size,
-            max_quote_size,
-            min_base_size,
-            min_quote_size,
-            limit,
-            program_id,
-            fee_discount_pubkey: use_fee_discount_pubkey,
-        });
-    
-        transaction.add_instruction(&send_take_instruction);
-    
-        Ok({ transaction, signers, payer: owner })
-    }
-
-    struct SendTakeParams<T> {
-        owner: T,
-        base_wallet: Pubkey,
-        quote_wallet: Pubkey,
-        vault_signer: Pubkey,
-        side: u8,
-        price: u64,
-        max_base_size: u64,
-        max_quote_size: u64,
-        min_base_size: u64,
-        min_quote_size: u64,
-        limit: u16,
-        program_id: Option<Pubkey>,
-        fee_discount_pubkey: Option<Pubkey>,
-    }
-    
-    fn make_send_take_instruction<T: Pubkey + Account>(
-        params: SendTakeParams<T>,
-    ) -> Instruction {
-        let SendTakeParams {
-            owner,
-            base_wallet,
-            quote_wallet,
-            vault_signer,
-            side,
-            price,
-            max_base_size,
-            max_quote_size,
-            min_base_size,
-            min_quote_size,
-            limit,
-            program_id,
-            fee_discount_pubkey,
-        } = params;
-    
-        let owner_address: Pubkey = owner.public_key.unwrap_or(owner);
-    
-        if base_size_number_to_lots(max_base_size) <= 0 {
-            panic!("size too small");
-        }
-        if quote_size_number_to_spl_size(max_quote_size) <= 0 {
-            panic!("size too small");
-        }
-        if price_number_to_lots(price) <= 0 {
-            panic!("invalid price");
-        }
-    
-        DexInstructions::send_take(SendTakeInstructionParams {
-            market: self.address,
+    pub fn make_new_order_v3_instruction(
+        &self,
+        params: &OrderParams,
+    ) -> Result<Instruction, SdkError> {
+        let order = self
+            .order_validator(0)
+            .validate(params.side, params.price, params.size)?;
+        Ok(DexInstructions::new_order_v3(NewOrderV3Params {
+            market: self.address(),
+            open_orders: open_orders_address(params.open_orders_address_key)?,
             request_queue: self.decoded.request_queue,
             event_queue: self.decoded.event_queue,
             bids: self.decoded.bids,
             asks: self.decoded.asks,
-            base_wallet,
-            quote_wallet,
-            owner: owner_address,
+            payer: params.payer,
+            owner: params.owner,
             base_vault: self.decoded.base_vault,
             quote_vault: self.decoded.quote_vault,
-            vault_signer,
-            side,
-            limit_price: price_number_to_lots(price),
-            max_base_quantity: base_size_number_to_lots(max_base_size),
-            max_quote_quantity: quote_size_number_to_spl_size(max_quote_size),
-            min_base_quantity: base_size_number_to_lots(min_base_size),
-            min_quote_quantity: quote_size_number_to_spl_size(min_quote_size),
-            limit,
-            program_id: program_id.unwrap_or_else(|| self.program_id),
-            fee_discount_pubkey: if supports_srm_fee_discounts {
-                fee_discount_pubkey
-            } else {
-                None
-            },
-        })
+            side: params.side,
+            limit_price: order.limit_price,
+            max_base_quantity: order.max_base_quantity,
+            max_quote_quantity: order.max_quote_quantity,
+            self_trade_behavior: params
+                .self_trade_behavior
+                .unwrap_or(SelfTradeBehavior::DecrementTake),
+            order_type: params.order_type.unwrap_or(OrderType::Limit),
+            client_id: params.client_id.unwrap_or(0),
+            limit: DEFAULT_MATCH_LIMIT,
+            max_ts: params.max_ts.unwrap_or(i64::MAX),
+            replace_if_exists: params.replace_if_exists.unwrap_or(false),
+            fee_discount_pubkey: self.fee_discount_pubkey(params.fee_discount_pubkey),
+            program_id: params.program_id.unwrap_or(self.program_id),
+        }))
     }
 
-    struct OrderParamsAccounts<T> {
-        owner: T,
-        open_orders_account: Option<Pubkey>,
-        open_orders_address_key: Pubkey,
-        payer: Pubkey,
-        program_id: Option<Pubkey>,
-        fee_discount_pubkey: Option<Pubkey>,
+    pub async fn make_send_take_transaction(
+        &self,
+        connection: &RpcClient,
+        params: &SendTakeParams,
+        fee_discount_pubkey_cache_duration: Duration,
+    ) -> Result<PreparedTransaction, SdkError> {
+        let fee_discount_pubkey = self
+            .resolve_fee_discount_pubkey(
+                connection,
+                &params.owner,
+                params.fee_discount_pubkey,
+                fee_discount_pubkey_cache_duration,
+            )
+            .await?;
+        let instruction = self.make_send_take_instruction(&SendTakeParams {
+            fee_discount_pubkey,
+            ..*params
+        })?;
+        Ok(PreparedTransaction {
+            transaction: Transaction::new_with_payer(&[instruction], Some(&params.owner)),
+            signers: Vec::new(),
+        })
     }
-    
-    #[derive(Debug)]
-    struct OrderParamsBase<T> {
-        side: u8,
-        price: u64,
-        size: u64,
-        order_type: String,
-        client_id: Option<u64>,
-        self_trade_behavior: String,
-        max_ts: Option<u64>,
-    }
-    
-    fn make_replace_orders_by_client_ids_instruction<T: Pubkey + Account>(
-        accounts: OrderParamsAccounts<T>,
-        orders: Vec<OrderParamsBase<T>>,
-    ) -> Instruction {
-        let owner_address: Pubkey = accounts.owner.public_key.unwrap_or(accounts.owner);
-    
-        DexInstructions::replace_orders_by_client_ids(ReplaceOrdersByClientIdsParams {
-            market: self.address,
-            bids: self.decoded.bids,
-            asks: self.decoded.asks,
+
+    pub fn make_send_take_instruction(
+        &self,
+        params: &SendTakeParams,
+    ) -> Result<Instruction, SdkError> {
+        // Maximums round down and minimums up, so the take never trades
+        // more, or accepts less, than asked for.
+        let limit_price = self
+            .price_number_to_lots(params.price, Rounding::for_limit_price(params.side))
+            .filter(|lots| !lots.is_zero())
+            .ok_or(SdkError::InvalidOrder(
+                "price is below one tick or does not fit in u64",
+            ))?;
+        let max_base_quantity = self
+            .base_size_number_to_lots(params.max_base_size, Rounding::Floor)
+            .filter(|lots| !lots.is_zero())
+            .ok_or(SdkError::InvalidOrder(
+                "max base size is below the minimum order size",
+            ))?;
+        let max_quote_quantity = self
+            .quote_size_number_to_spl_size(params.max_quote_size, Rounding::Floor)
+            .filter(|native| !native.is_zero())
+            .ok_or(SdkError::InvalidOrder(
+                "max quote size is below one native unit",
+            ))?;
+        let min_base_quantity = self
+            .base_size_number_to_lots(params.min_base_size, Rounding::Ceil)
+            .ok_or(SdkError::InvalidOrder(
+                "min base size must not be negative and fit in u64",
+            ))?;
+        let min_quote_quantity = self
+            .quote_size_number_to_spl_size(params.min_quote_size, Rounding::Ceil)
+            .ok_or(SdkError::InvalidOrder(
+                "min quote size must not be negative and fit in u64",
+            ))?;
+
+        Ok(DexInstructions::send_take(SendTakeInstructionParams {
+            market: self.address(),
             request_queue: self.decoded.request_queue,
             event_queue: self.decoded.event_queue,
+            bids: self.decoded.bids,
+            asks: self.decoded.asks,
+            base_wallet: params.base_wallet,
+            quote_wallet: params.quote_wallet,
+            owner: params.owner,
             base_vault: self.decoded.base_vault,
             quote_vault: self.decoded.quote_vault,
-            open_orders: accounts.open_orders_account.unwrap_or(accounts.open_orders_address_key),
-            owner: owner_address,
-            payer: accounts.payer,
-            program_id: accounts.program_id.unwrap_or_else(|| self.program_id),
-            fee_discount_pubkey: if supports_srm_fee_discounts {
-                accounts.fee_discount_pubkey
-            } else {
-                None
-            },
-            orders: orders.iter().map(|order| {
-                ReplaceOrder {
-                    side: order.side,
-                    limit_price: price_number_to_lots(order.price),
-                    max_base_quantity: base_size_number_to_lots(order.size),
-                    max_quote_quantity: self.decoded.quote_lot_size.into()
-                        * (base_size_number_to_lots(order.size) * price_number_to_lots(order.price)),
-                    order_type: order.order_type,
-                    client_id: order.client_id,
-                    program_id: accounts.program_id.unwrap_or_else(|| self.program_id),
-                    self_trade_behavior: order.self_trade_behavior,
-                   max_ts: order.max_ts,
-                }
-            }).collect(),
-        });
+            vault_signer: self.vault_signer()?,
+            side: params.side,
+            limit_price,
+            max_base_quantity,
+            max_quote_quantity,
+            min_base_quantity,
+            min_quote_quantity,
+            limit: params.limit.unwrap_or(DEFAULT_MATCH_LIMIT),
+            fee_discount_pubkey: self.fee_discount_pubkey(params.fee_discount_pubkey),
+            program_id: params.program_id.unwrap_or(self.program_id),
+        }))
     }
 
-    async fn _send_transaction(
-        connection: &Connection,
-        transaction: Transaction,
-        signers: Vec<Account>,
-        skip_preflight: bool,
-        commitment: Option<Commitment>,
-    ) -> Result<TransactionSignature, Box<dyn Error>> {
-        let signature = connection.send_transaction(&transaction, &signers, skip_preflight).await?;
-    
-        match connection.confirm_transaction(&signature, commitment).await {
-            Ok(Some(value)) => {
-                if let Some(err) = value.err {
-                    return Err(format!("Error: {:?}", err).into());
-                }
-                Ok(signature)
+    /// `accounts.open_orders_address_key` is required.
+    pub fn make_replace_orders_by_client_ids_instruction(
+        &self,
+        accounts: &OrderParamsAccounts,
+        orders: &[OrderParamsBase],
+    ) -> Result<Instruction, SdkError> {
+        let validator = self.order_validator(0);
+        let orders = orders
+            .iter()
+            .map(|order| {
+                let validated = validator.validate(order.side, order.price, order.size)?;
+                Ok(NewOrderV3 {
+                    side: order.side,
+                    limit_price: validated.limit_price.get(),
+                    max_base_quantity: validated.max_base_quantity.get(),
+                    max_quote_quantity: validated.max_quote_quantity.get(),
+                    self_trade_behavior: order
+                        .self_trade_behavior
+                        .unwrap_or(SelfTradeBehavior::DecrementTake),
+                    order_type: order.order_type.unwrap_or(OrderType::Limit),
+                    client_order_id: order.client_id.unwrap_or(0),
+                    limit: DEFAULT_MATCH_LIMIT,
+                    max_ts: order.max_ts.unwrap_or(i64::MAX),
+                })
+            })
+            .collect::<Result<Vec<_>, SdkError>>()?;
+
+        Ok(DexInstructions::replace_orders_by_client_ids(
+            ReplaceOrdersByClientIdsParams {
+                market: self.address(),
+                open_orders: open_orders_address(accounts.open_orders_address_key)?,
+                request_queue: self.decoded.request_queue,
+                event_queue: self.decoded.event_queue,
+                bids: self.decoded.bids,
+                asks: self.decoded.asks,
+                payer: accounts.payer,
+                owner: accounts.owner,
+                base_vault: self.decoded.base_vault,
+                quote_vault: self.decoded.quote_vault,
+                orders,
+                fee_discount_pubkey: self.fee_discount_pubkey(accounts.fee_discount_pubkey),
+                program_id: accounts.program_id.unwrap_or(self.program_id),
             },
-            _ => Err("Transaction confirmation failed".into()),
+        ))
+    }
+
+    fn fee_discount_pubkey(&self, fee_discount_pubkey: Option<Pubkey>) -> Option<Pubkey> {
+        fee_discount_pubkey.filter(|_| self.supports_srm_fee_discounts())
+    }
+
+    fn vault_signer(&self) -> Result<Pubkey, SdkError> {
+        Ok(Pubkey::create_program_address(
+            &[
+                &self.address().to_bytes(),
+                &self.decoded.vault_signer_nonce.to_le_bytes(),
+            ],
+            &self.program_id,
+        )?)
+    }
+
+    async fn send_prepared_transaction(
+        &self,
+        connection: &RpcClient,
+        prepared: PreparedTransaction,
+        owner: &dyn Signer,
+    ) -> Result<Signature, SdkError> {
+        let mut signers: Vec<&dyn Signer> = vec![owner];
+        signers.extend(prepared.signers.iter().map(|signer| signer as &dyn Signer));
+        self.send_transaction(connection, prepared.transaction, &signers)
+            .await
+    }
+
+    /// Signs `transaction` with a fresh blockhash, sends it and waits until
+    /// it reaches the market's commitment. Once it is sent, failures to
+    /// confirm it are `SdkError::Unconfirmed`, as it may still land.
+    pub async fn send_transaction(
+        &self,
+        connection: &RpcClient,
+        mut transaction: Transaction,
+        signers: &[&dyn Signer],
+    ) -> Result<Signature, SdkError> {
+        let blockhash = connection.get_latest_blockhash().await?;
+        transaction.try_sign(signers, blockhash)?;
+
+        let config = RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight,
+            preflight_commitment: Some(self.commitment.commitment),
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = connection
+            .send_transaction_with_config(&transaction, config)
+            .await
+            .map_err(|err| SdkError::from_send_error(&transaction.clone().into(), err))?;
+
+        let unconfirmed = |_| SdkError::Unconfirmed(signature.to_string());
+        loop {
+            let status = connection
+                .get_signature_status_with_commitment(&signature, self.commitment)
+                .await
+                .map_err(unconfirmed)?;
+            if let Some(result) = status {
+                return result
+                    .map(|()| signature)
+                    .map_err(|err| SdkError::from_transaction_error(&transaction.into(), err));
+            }
+            let blockhash_valid = connection
+                .is_blockhash_valid(&blockhash, self.commitment)
+                .await
+                .map_err(unconfirmed)?;
+            if !blockhash_valid {
+                return Err(SdkError::Unconfirmed(signature.to_string()));
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
         }
     }
 
-    #[allow(dead_code)]
-    async fn cancel_order_by_client_id(
-        connection: &Connection,
-        owner: Account,
-        open_orders: Pubkey,
+    pub async fn cancel_order_by_client_id(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        open_orders: &Pubkey,
         client_id: u64,
-    ) -> Result<TransactionSignature, Box<dyn Error>> {
-        let transaction = make_cancel_order_by_client_id_transaction(connection, owner.public_key, open_orders, client_id).await?;
-        send_transaction(connection, transaction, vec![owner]).await
+    ) -> Result<Signature, SdkError> {
+        let transaction = self.make_cancel_order_by_client_id_transaction(
+            &owner.pubkey(),
+            open_orders,
+            client_id,
+        );
+        self.send_transaction(connection, transaction, &[owner])
+            .await
     }
 
-    #[allow(dead_code)]
-    async fn cancel_orders_by_client_ids(
-        connection: &Connection,
-        owner: Account,
-        open_orders: Pubkey,
+    pub async fn cancel_orders_by_client_ids(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        open_orders: &Pubkey,
         client_ids: Vec<u64>,
-    ) -> Result<TransactionSignature, Box<dyn Error>> {
-        let transaction = make_cancel_orders_by_client_ids_transaction(connection, owner.public_key, open_orders, client_ids).await?;
-        send_transaction(connection, transaction, vec![owner]).await
+    ) -> Result<Signature, SdkError> {
+        let transaction = self.make_cancel_orders_by_client_ids_transaction(
+            &owner.pubkey(),
+            open_orders,
+            client_ids,
+        )?;
+        self.send_transaction(connection, transaction, &[owner])
+            .await
     }
 
-    #[allow(dead_code)]
-    async fn make_cancel_order_by_client_id_transaction(
-        connection: Connection,
-        owner: Pubkey,
-        open_orders: Pubkey,
+    pub fn make_cancel_order_by_client_id_transaction(
+        &self,
+        owner: &Pubkey,
+        open_orders: &Pubkey,
         client_id: u64,
-    ) -> Result<Transaction, Box<dyn Error>> {
-        let mut transaction = Transaction::new();
-
-        if uses_request_queue {
-            let instruction = DexInstructions::cancel_order_by_client_id(CancelOrderByClientIdParams {
-                market: self.address,
-                owner,
-                open_orders,
+    ) -> Transaction {
+        let instruction = if self.uses_request_queue() {
+            DexInstructions::cancel_order_by_client_id(CancelOrderByClientIdParams {
+                market: self.address(),
+                open_orders: *open_orders,
                 request_queue: self.decoded.request_queue,
+                owner: *owner,
                 client_id,
                 program_id: self.program_id,
-            });
-            transaction.add(instruction);
+            })
         } else {
-            let instruction = DexInstructions::cancel_order_by_client_id_v2(CancelOrderByClientIdV2Params {
-                market: self.address,
-                open_orders,
-                owner,
+            DexInstructions::cancel_order_by_client_id_v2(CancelOrderByClientIdV2Params {
+                market: self.address(),
                 bids: self.decoded.bids,
                 asks: self.decoded.asks,
+                open_orders: *open_orders,
+                owner: *owner,
                 event_queue: self.decoded.event_queue,
                 client_id,
                 program_id: self.program_id,
-            });
-            transaction.add(instruction);
-        }
-
-        Ok(transaction)
+            })
+        };
+        Transaction::new_with_payer(&[instruction], Some(owner))
     }
 
-    #[allow(dead_code)]
-    async fn make_cancel_orders_by_client_ids_transaction(
-        connection: Connection,
-        owner: Pubkey,
-        open_orders: Pubkey,
+    /// Fails for more than eight client ids, the most one instruction can
+    /// cancel.
+    pub fn make_cancel_orders_by_client_ids_transaction(
+        &self,
+        owner: &Pubkey,
+        open_orders: &Pubkey,
         client_ids: Vec<u64>,
-    ) -> Result<Transaction, Box<dyn Error>> {
-        let mut transaction = Transaction::new();
-
-        let instruction = DexInstructions::cancel_orders_by_client_ids(CancelOrdersByClientIdsParams {
-            market: self.address,
-            open_orders,
-            owner,
-            bids: self.decoded.bids,
-            asks: self.decoded.asks,
-            event_queue: self.decoded.event_queue,
-            client_ids,
-            program_id: self.program_id,
-        });
-        transaction.add(instruction);
-
-        Ok(transaction)
+    ) -> Result<Transaction, SdkError> {
+        let instruction =
+            DexInstructions::cancel_orders_by_client_ids(CancelOrdersByClientIdsParams {
+                market: self.address(),
+                bids: self.decoded.bids,
+                asks: self.decoded.asks,
+                open_orders: *open_orders,
+                owner: *owner,
+                event_queue: self.decoded.event_queue,
+                client_ids,
+                program_id: self.program_id,
+            })?;
+        Ok(Transaction::new_with_payer(&[instruction], Some(owner)))
     }
 
-    async fn cancel_order(connection: Connection, owner: Account, order: Order) -> Result<(), Box<dyn Error>> {
-        let transaction = make_cancel_order_transaction(connection, owner.key, order).await?;
-        send_transaction(connection, transaction, vec![owner]).await?;
-        Ok(())
+    pub async fn cancel_order(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        order: &Order,
+    ) -> Result<Signature, SdkError> {
+        let transaction = self.make_cancel_order_transaction(&owner.pubkey(), order);
+        self.send_transaction(connection, transaction, &[owner])
+            .await
     }
-    
-    async fn make_cancel_order_transaction(connection: Connection, owner: Pubkey, order: Order) -> Result<Transaction, Box<dyn Error>> {
-        let mut transaction = Transaction::new();
-        let cancel_order_instruction = make_cancel_order_instruction(connection, owner, order);
-        transaction.add_instruction(&cancel_order_instruction);
-        Ok(transaction)
+
+    pub fn make_cancel_order_transaction(&self, owner: &Pubkey, order: &Order) -> Transaction {
+        let instruction = self.make_cancel_order_instruction(owner, order);
+        Transaction::new_with_payer(&[instruction], Some(owner))
     }
 
-    fn make_cancel_order_instruction(connection: Connection, owner: Pubkey, order: Order) -> Instruction {
-        if uses_request_queue {
+    pub fn make_cancel_order_instruction(&self, owner: &Pubkey, order: &Order) -> Instruction {
+        if self.uses_request_queue() {
             DexInstructions::cancel_order(CancelOrderParams {
-                market: self.address,
-                owner,
+                market: self.address(),
                 open_orders: order.open_orders_address,
                 request_queue: self.decoded.request_queue,
+                owner: *owner,
                 side: order.side,
                 order_id: order.order_id,
                 open_orders_slot: order.open_orders_slot,
@@ -1127,23 +1217,28 @@ impl Market {
             })
         } else {
             DexInstructions::cancel_order_v2(CancelOrderV2Params {
-                market: self.address,
-                owner,
-                open_orders: order.open_orders_address,
+                market: self.address(),
                 bids: self.decoded.bids,
                 asks: self.decoded.asks,
+                open_orders: order.open_orders_address,
+                owner: *owner,
                 event_queue: self.decoded.event_queue,
                 side: order.side,
                 order_id: order.order_id,
-                open_orders_slot: order.open_orders_slot,
                 program_id: self.program_id,
             })
         }
     }
 
-    fn make_consume_events_instruction(open_orders_accounts: Vec<Pubkey>, limit: u64) -> Instruction {
+    /// The fee accounts are unused by the program; the event queue stands
+    /// in for them.
+    pub fn make_consume_events_instruction(
+        &self,
+        open_orders_accounts: Vec<Pubkey>,
+        limit: u16,
+    ) -> Instruction {
         DexInstructions::consume_events(ConsumeEventsParams {
-            market: self.address,
+            market: self.address(),
             event_queue: self.decoded.event_queue,
             coin_fee: self.decoded.event_queue,
             pc_fee: self.decoded.event_queue,
@@ -1152,357 +1247,785 @@ impl Market {
             program_id: self.program_id,
         })
     }
-    
-    fn make_consume_events_permissioned_instruction(open_orders_accounts: Vec<Pubkey>, limit: u64) -> Instruction {
-        DexInstructions::consume_events_permissioned(ConsumeEventsPermissionedParams {
-            market: self.address,
-            event_queue: self.decoded.event_queue,
-            crank_authority: self.decoded.consume_events_authority,
-            open_orders_accounts,
-            limit,
-            program_id: self.program_id,
-        })
+
+    /// `None` unless the market requires a crank authority, i.e. carries
+    /// `AccountFlags::CRANK_AUTHORITY_REQUIRED`.
+    pub fn make_consume_events_permissioned_instruction(
+        &self,
+        open_orders_accounts: Vec<Pubkey>,
+        limit: u16,
+    ) -> Option<Instruction> {
+        let crank_authority = self.decoded.consume_events_authority?;
+        Some(DexInstructions::consume_events_permissioned(
+            ConsumeEventsPermissionedParams {
+                market: self.address(),
+                event_queue: self.decoded.event_queue,
+                crank_authority,
+                open_orders_accounts,
+                limit,
+                program_id: self.program_id,
+            },
+        ))
     }
 
-    #[allow(dead_code)]
-    async fn settle_funds(
-        connection: Connection,
-        owner: Account,
-        open_orders: OpenOrders,
-        base_wallet: Pubkey,
-        quote_wallet: Pubkey,
+    /// Settles the free balances of `open_orders` to the wallets. A wallet
+    /// equal to the owner settles SOL through a temporary wrapped SOL
+    /// account.
+    pub async fn settle_funds(
+        &self,
+        connection: &RpcClient,
+        owner: &dyn Signer,
+        open_orders: &OpenOrders,
+        base_wallet: &Pubkey,
+        quote_wallet: &Pubkey,
         referrer_quote_wallet: Option<Pubkey>,
-    ) -> Result<TransactionSignature, Box<dyn Error>> {
-        if open_orders.owner != owner.public_key {
-            return Err("Invalid open orders account".into());
+    ) -> Result<Signature, SdkError> {
+        if open_orders.owner != owner.pubkey() {
+            return Err(SdkError::InvalidAccount {
+                account: open_orders.address,
+                reason: "open orders account belongs to another owner",
+            });
         }
+        let prepared = self
+            .make_settle_funds_transaction(
+                connection,
+                open_orders,
+                base_wallet,
+                quote_wallet,
+                referrer_quote_wallet,
+            )
+            .await?;
+        self.send_prepared_transaction(connection, prepared, owner)
+            .await
+    }
 
-        if let Some(referrer_quote_wallet) = referrer_quote_wallet {
-            if !self.supports_referral_fees {
-                return Err("This program ID does not support referrerQuoteWallet".into());
-            }
+    pub async fn make_settle_funds_transaction(
+        &self,
+        connection: &RpcClient,
+        open_orders: &OpenOrders,
+        base_wallet: &Pubkey,
+        quote_wallet: &Pubkey,
+        referrer_quote_wallet: Option<Pubkey>,
+    ) -> Result<PreparedTransaction, SdkError> {
+        if referrer_quote_wallet.is_some() && !self.supports_referral_fees() {
+            return Err(SdkError::Unsupported {
+                program_id: self.program_id,
+                feature: "referrer quote wallets",
+            });
         }
 
-        let SettleFundsResult { transaction, signers } = make_settle_funds_transaction(
-            connection,
-            open_orders,
-            base_wallet,
-            quote_wallet,
+        let owner = open_orders.owner;
+        let mut instructions = Vec::new();
+        let mut signers = Vec::new();
+
+        let settles_sol = (self.decoded.base_mint == WRAPPED_SOL_MINT && *base_wallet == owner)
+            || (self.decoded.quote_mint == WRAPPED_SOL_MINT && *quote_wallet == owner);
+        let wrapped_sol_account = if settles_sol {
+            let account = Keypair::new();
+            instructions.extend(
+                self.make_wrapped_sol_account_instructions(
+                    connection,
+                    &account.pubkey(),
+                    &owner,
+                    0,
+                )
+                .await?,
+            );
+            Some(account)
+        } else {
+            None
+        };
+        let wallet = |wallet: &Pubkey| match &wrapped_sol_account {
+            Some(account) if *wallet == owner => account.pubkey(),
+            _ => *wallet,
+        };
+
+        instructions.push(DexInstructions::settle_funds(SettleFundsParams {
+            market: self.address(),
+            open_orders: open_orders.address,
+            owner,
+            base_vault: self.decoded.base_vault,
+            quote_vault: self.decoded.quote_vault,
+            base_wallet: wallet(base_wallet),
+            quote_wallet: wallet(quote_wallet),
+            vault_signer: self.vault_signer()?,
             referrer_quote_wallet,
-        ).await?;
+            program_id: self.program_id,
+        }));
 
-        let mut transaction_signers = vec![owner];
-        transaction_signers.extend(signers);
+        if let Some(account) = wrapped_sol_account {
+            instructions.push(spl_token::instruction::close_account(
+                &TOKEN_PROGRAM_ID,
+                &account.pubkey(),
+                &owner,
+                &owner,
+                &[],
+            )?);
+            signers.push(account);
+        }
 
-        send_transaction(connection, transaction, transaction_signers).await
+        Ok(PreparedTransaction {
+            transaction: Transaction::new_with_payer(&instructions, Some(&owner)),
+            signers,
+        })
     }
 
-    #[allow(dead_code)]
-    async fn make_settle_funds_transaction(
-        connection: Connection,
-        open_orders: OpenOrders,
-        base_wallet: Pubkey,
-        quote_wallet: Pubkey,
-        referrer_quote_wallet: Option<Pubkey>,
-    ) -> Result<{ transaction: Transaction, signers: Vec<Account>, payer: Pubkey }, Box<dyn Error>> {
-        let vault_signer = Pubkey::create_program_address(
-            &[
-                self.address.to_bytes(),
-                self.decoded.vault_signer_nonce.to_le_bytes(),
-            ],
-            &self.program_id,
-        )?;
+    pub async fn match_orders(
+        &self,
+        connection: &RpcClient,
+        fee_payer: &dyn Signer,
+        limit: u16,
+    ) -> Result<Signature, SdkError> {
+        let transaction = self.make_match_orders_transaction(&fee_payer.pubkey(), limit);
+        self.send_transaction(connection, transaction, &[fee_payer])
+            .await
+    }
 
-        let mut transaction = Transaction::new();
-        let mut signers: Vec<Account> = vec![];
+    pub fn make_match_orders_transaction(&self, fee_payer: &Pubkey, limit: u16) -> Transaction {
+        let instruction = DexInstructions::match_orders(MatchOrdersParams {
+            market: self.address(),
+            request_queue: self.decoded.request_queue,
+            event_queue: self.decoded.event_queue,
+            bids: self.decoded.bids,
+            asks: self.decoded.asks,
+            coin_fee_receivable: self.decoded.base_vault,
+            pc_fee_receivable: self.decoded.quote_vault,
+            limit,
+            program_id: self.program_id,
+        });
+        Transaction::new_with_payer(&[instruction], Some(fee_payer))
+    }
 
-        let mut wrapped_sol_account: Option<Account> = None;
-        if (self.base_mint_address == WRAPPED_SOL_MINT && base_wallet == open_orders.owner)
-            || (self.quote_mint_address == WRAPPED_SOL_MINT && quote_wallet == open_orders.owner)
-        {
-            wrapped_sol_account = Some(Account::new());
-            transaction.add_instruction(
-                SystemProgram::create_account(
-                    &open_orders.owner,
-                    &wrapped_sol_account.public_key,
-                    connection.get_minimum_balance_for_rent_exemption(165).await?,
-                    165,
-                    &TOKEN_PROGRAM_ID,
-                ),
-            );
+    fn get_layout(program_id: &Pubkey) -> MarketLayout {
+        MarketLayout::for_version(ProgramRegistry::global().layout_version(program_id))
+    }
 
-            transaction.add_instruction(
-                initialize_account(
-                    &wrapped_sol_account.public_key,
-                    &WRAPPED_SOL_MINT,
-                    &open_orders.owner,
-                ),
-            );
+    /// Conversions between the UI values, native amounts and lots of this
+    /// market.
+    pub fn lot_conversion(&self) -> LotConversion {
+        LotConversion::new(
+            self.decoded.base_lot_size,
+            self.decoded.quote_lot_size,
+            self.base_mint_decimals,
+            self.quote_mint_decimals,
+        )
+    }
 
-            signers.push(wrapped_sol_account);
-        }
+    /// Validates orders against this market's ticks and lots, charging the
+    /// taker fee of `fee_tier`.
+    pub fn order_validator(&self, fee_tier: u8) -> OrderValidator {
+        OrderValidator::new(self.lot_conversion(), fee_tier)
+    }
 
-        transaction.add_instruction(
-            DexInstructions::settle_funds(SettleFundsParams {
-                market: self.address,
-                open_orders: open_orders.address,
-                owner: open_orders.owner,
-                base_vault: self.decoded.base_vault,
-                quote_vault: self.decoded.quote_vault,
-                base_wallet: if base_wallet == open_orders.owner && wrapped_sol_account.is_some() {
-                    wrapped_sol_account.public_key
-                } else {
-                    base_wallet
-                },
-                quote_wallet: if quote_wallet == open_orders.owner && wrapped_sol_account.is_some() {
-                    wrapped_sol_account.public_key
-                } else {
-                    quote_wallet
-                },
-                vault_signer,
-                program_id: self.program_id,
-                referrer_quote_wallet,
-            }),
-        );
+    /// Quote tokens per base token for a price in lots.
+    pub fn price_lots_to_number(&self, price: PriceLots, rounding: Rounding) -> Option<Decimal> {
+        self.lot_conversion().price_lots_to_number(price, rounding)
+    }
 
-        if let Some(wrapped_sol_account) = wrapped_sol_account {
-            transaction.add_instruction(
-                close_account(
-                    &wrapped_sol_account.public_key,
-                    &open_orders.owner,
-                    &open_orders.owner,
-                ),
-            );
-        }
+    /// Price lots for a price in quote tokens per base token. Limit prices
+    /// should use `Rounding::for_limit_price` so they never round past the
+    /// user's limit.
+    pub fn price_number_to_lots(&self, price: Decimal, rounding: Rounding) -> Option<PriceLots> {
+        self.lot_conversion().price_number_to_lots(price, rounding)
+    }
 
-        Ok({ transaction, signers, payer: open_orders.owner })
+    /// Base tokens for a native base amount.
+    pub fn base_spl_size_to_number(&self, size: NativeBase, rounding: Rounding) -> Option<Decimal> {
+        self.lot_conversion()
+            .base_spl_size_to_number(size, rounding)
     }
 
+    /// Quote tokens for a native quote amount.
+    pub fn quote_spl_size_to_number(
+        &self,
+        size: NativeQuote,
+        rounding: Rounding,
+    ) -> Option<Decimal> {
+        self.lot_conversion()
+            .quote_spl_size_to_number(size, rounding)
+    }
 
-    #[allow(dead_code)]
-    async fn match_orders(connection: Connection, fee_payer: Account, limit: u64) -> Result<TransactionSignature, Box<dyn Error>> {
-        let tx = make_match_orders_transaction(limit);
-        _send_transaction(connection, tx, vec![fee_payer]).await
+    pub fn base_size_number_to_spl_size(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<NativeBase> {
+        self.lot_conversion()
+            .base_size_number_to_spl_size(size, rounding)
     }
 
-    fn make_match_orders_transaction(limit: u64) -> Transaction {
-        let mut tx = Transaction::new();
-        tx.add(DexInstructions::match_orders(MatchOrdersParams {
-            market: self.address,
-            request_queue: self.decoded.request_queue,
-            event_queue: self.decoded.event_queue,
-            bids: self.decoded.bids,
-            asks: self.decoded.asks,
-            base_vault: self.decoded.base_vault,
-            quote_vault: self.decoded.quote_vault,
-            limit,
-            program_id: self.program_id,
-        }));
-        tx
+    pub fn quote_size_number_to_spl_size(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<NativeQuote> {
+        self.lot_conversion()
+            .quote_size_number_to_spl_size(size, rounding)
     }
 
-    async fn load_request_queue(connection: Connection) -> Result<RequestQueueData, Box<dyn Error>> {
-        let account_info = connection.get_account_info(self.decoded.request_queue).await?;
-        let data = account_info.data.ok_or("Failed to get account data")?;
-        decode_request_queue(data)
+    /// Base tokens for a size in lots.
+    pub fn base_size_lots_to_number(&self, size: BaseLots, rounding: Rounding) -> Option<Decimal> {
+        self.lot_conversion()
+            .base_size_lots_to_number(size, rounding)
     }
 
-    async fn load_event_queue(connection: Connection) -> Result<EventQueueData, Box<dyn Error>> {
-        let account_info = connection.get_account_info(self.decoded.event_queue).await?;
-        let data = account_info.data.ok_or("Failed to get account data")?;
-        decode_event_queue(data)
+    pub fn base_size_number_to_lots(&self, size: Decimal, rounding: Rounding) -> Option<BaseLots> {
+        self.lot_conversion()
+            .base_size_number_to_lots(size, rounding)
     }
 
-    async fn load_fills(connection: Connection, limit: u64) -> Result<Vec<FillEvent>, Box<dyn Error>> {
-        let account_info = connection.get_account_info(self.decoded.event_queue).await?;
-        let data = account_info.data.ok_or("Failed to get account data")?;
-        let events = decode_event_queue(data, limit);
+    /// Quote tokens for a size in quote lots.
+    pub fn quote_size_lots_to_number(
+        &self,
+        size: QuoteLots,
+        rounding: Rounding,
+    ) -> Option<Decimal> {
+        self.lot_conversion()
+            .quote_size_lots_to_number(size, rounding)
+    }
 
-        Ok(events.iter()
-            .filter(|event| event.event_flags.fill && event.native_quantity_paid > 0)
-            .map(|event| self.parse_fill_event(event))
-            .collect())
+    pub fn quote_size_number_to_lots(
+        &self,
+        size: Decimal,
+        rounding: Rounding,
+    ) -> Option<QuoteLots> {
+        self.lot_conversion()
+            .quote_size_number_to_lots(size, rounding)
     }
 
-    fn parse_fill_event(event: Event) -> FillEvent {
-        let (mut size, mut price, mut side, mut price_before_fees): (f64, f64, String, u64);
-    
-        if event.event_flags.bid {
-            side = String::from("buy");
-            
-            price_before_fees = if event.event_flags.maker {
-                event.native_quantity_paid + event.native_fee_or_rebate
-            } else {
-                event.native_quantity_paid - event.native_fee_or_rebate
-            };
-    
-            price = divide_bn_to_number(price_before_fees * self.base_spl_token_multiplier,
-                                        self.quote_spl_token_multiplier * event.native_quantity_released);
-    
-            size = divide_bn_to_number(event.native_quantity_released, self.base_spl_token_multiplier);
-    
-        } else {
-            side = String::from("sell");
-    
-            price_before_fees = if event.event_flags.maker {
-                event.native_quantity_released - event.native_fee_or_rebate
-            } else {
-                event.native_quantity_released + event.native_fee_or_rebate
-            };
-    
-            price = divide_bn_to_number(price_before_fees * self.base_spl_token_multiplier,
-                                        self.quote_spl_token_multiplier * event.native_quantity_paid);
-    
-            size = divide_bn_to_number(event.native_quantity_paid, self.base_spl_token_multiplier);
-        }
-    
-        FillEvent {
-            event: event,
-            side: side,
-            price: price,
-            fee_cost: self.quote_spl_size_to_number(event.native_fee_or_rebate) * 
-                      if event.event_flags.maker { -1 } else { 1 },
-            size: size,
-        }
+    /// Base tokens in one base lot.
+    pub fn min_order_size(&self) -> Option<Decimal> {
+        self.base_size_lots_to_number(BaseLots::new(1), Rounding::Nearest)
     }
 
-    fn base_spl_token_multiplier(&self) -> BN {
-        BN::from(10).pow(self.base_spl_token_decimals());
-      }
-      
-      fn quote_spl_token_multiplier(&self) -> BN {
-        BN::from(10).pow(self.quote_spl_token_decimals());
-      }
-      
-      fn price_lots_to_number(&self, price: &BN) -> f64 {
-        divide_bn_to_number(
-          &price.mul(&self.decoded.quote_lot_size).mul(&self.base_spl_token_multiplier()),
-          &self.decoded.base_lot_size.mul(&self.quote_spl_token_multiplier()),
-        )
-      }
-      
-      fn price_number_to_lots(&self, price: f64) -> BN {
-        BN::from(
-          ((price * 10_f64.powi(self.quote_spl_token_decimals())).mul(self.decoded.base_lot_size.to_f64()))
-            / (10_f64.powi(self.base_spl_token_decimals()) * self.decoded.quote_lot_size.to_f64()).round(),
-        )
-      }
-      
-      fn base_spl_size_to_number(&self, size: &BN) -> f64 {
-        divide_bn_to_number(&size, &self.base_spl_token_multiplier())
-      }
-      
-      fn quote_spl_size_to_number(&self, size: &BN) -> f64 {
-        divide_bn_to_number(&size, &self.quote_spl_token_multiplier())
-      }
-      
-      fn base_size_number_to_spl_size(&self, size: f64) -> BN {
-        BN::from((size * 10_f64.powi(self.base_spl_token_decimals())).round())
-      }
-      
-      fn quote_size_number_to_spl_size(&self, size: f64) -> BN {
-        BN::from((size * 10_f64.powi(self.quote_spl_token_decimals())).round())
-      }
-      
-      fn base_size_lots_to_number(&self, size: &BN) -> f64 {
-        divide_bn_to_number(
-          &size.mul(&self.decoded.base_lot_size),
-          &self.base_spl_token_multiplier(),
-        )
-      }
-      
-      fn base_size_number_to_lots(&self, size: f64) -> BN {
-        let native = BN::from((size * 10_f64.powi(self.base_spl_token_decimals())).round());
-        native.div(&self.decoded.base_lot_size)
-      }
-      
-      fn quote_size_lots_to_number(&self, size: &BN) -> f64 {
-        divide_bn_to_number(
-          &size.mul(&self.decoded.quote_lot_size),
-          &self.quote_spl_token_multiplier(),
-        )
-      }
-      
-      fn quote_size_number_to_lots(&self, size: f64) -> BN {
-        let native = BN::from((size * 10_f64.powi(self.quote_spl_token_decimals())).round());
-        native.div(&self.decoded.quote_lot_size)
-      }
-      
-      fn min_order_size(&self) -> f64 {
-        self.base_size_lots_to_number(&BN::from(1))
-      }
-      
-      fn tick_size(&self) -> f64 {
-        self.price_lots_to_number(&BN::from(1))
-      }
+    /// Quote tokens per base token of one price lot.
+    pub fn tick_size(&self) -> Option<Decimal> {
+        self.price_lots_to_number(PriceLots::new(1), Rounding::Nearest)
+    }
 }
 
-pub struct Memcmp {
-    offset: u64,
-    bytes: Vec<u8>,
+/// `SdkError::AccountNotFound` when `address` does not exist.
+async fn get_account(
+    connection: &RpcClient,
+    address: &Pubkey,
+    commitment: CommitmentConfig,
+) -> Result<Account, SdkError> {
+    connection
+        .get_account_with_commitment(address, commitment)
+        .await?
+        .value
+        .ok_or(SdkError::AccountNotFound(*address))
+}
+
+/// Decimals of `mint`. Wrapped SOL has the 9 decimals of SOL and is not
+/// fetched.
+pub async fn get_mint_decimals(
+    connection: &RpcClient,
+    mint: &Pubkey,
+    commitment: CommitmentConfig,
+) -> Result<u8, SdkError> {
+    if *mint == WRAPPED_SOL_MINT {
+        return Ok(9);
+    }
+    let account = get_account(connection, mint, commitment).await?;
+    spl_token::state::Mint::unpack(&account.data)
+        .map(|state| state.decimals)
+        .map_err(|_| SdkError::InvalidAccount {
+            account: *mint,
+            reason: "not a token mint",
+        })
 }
 
-lazy_static::lazy_static! {
-    static ref PROGRAM_LAYOUT_VERSIONS: HashMap<&'static str, u32> = {
-        let mut map = HashMap::new();
-        map.insert("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn", 1);
-        map.insert("BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg", 1);
-        map.insert("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o", 2);
-        map.insert("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX", 3);
-        map
+/// Accounts of `program_id` matching every filter, fetched with base64
+/// encoding.
+pub async fn get_filtered_program_accounts(
+    connection: &RpcClient,
+    program_id: &Pubkey,
+    filters: Vec<RpcFilterType>,
+) -> Result<Vec<(Pubkey, Account)>, SdkError> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(connection.commitment()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
     };
+    Ok(connection
+        .get_program_accounts_with_config(program_id, config)
+        .await?)
 }
 
-pub fn get_layout_version(program_id: PublicKey) -> u32 {
-    *PROGRAM_LAYOUT_VERSIONS.get(program_id.0).unwrap_or(&3)
+/// Tokens held by a token account, 0 for accounts that are not one.
+fn get_spl_token_balance(account: &Account, decimals: u8) -> f64 {
+    spl_token::state::Account::unpack(&account.data).map_or(0.0, |state| {
+        state.amount as f64 / 10f64.powi(i32::from(decimals))
+    })
 }
 
-pub async fn get_filtered_program_accounts(
-    rpc_client: &RpcClient,
-    program_id: Pubkey,
-    filters: Vec<(String, String)>,
-) -> Result<Vec<(Pubkey, Account)>, Box<dyn Error>> {
-    let resp = rpc_client.get_program_accounts(
-        &program_id.to_string(),
-        Some(spl_token::rpc::TokenAccountsFilter::default()),
-    )?;
+fn open_orders_address(open_orders_address_key: Option<Pubkey>) -> Result<Pubkey, SdkError> {
+    open_orders_address_key.ok_or(SdkError::InvalidOrder("open orders account is required"))
+}
+
+/// The cached entry for `key`, unless it is `max_age` old. A zero
+/// `max_age` always refetches.
+fn get_cached<T: Clone>(cache: &Cache<T>, key: &Pubkey, max_age: Duration) -> Option<Vec<T>> {
+    let cache = cache.lock().unwrap();
+    let (fetched_at, values) = cache.get(key)?;
+    (fetched_at.elapsed() < max_age).then(|| values.clone())
+}
+
+fn set_cached<T: Clone>(cache: &Cache<T>, key: &Pubkey, values: &[T]) {
+    cache
+        .lock()
+        .unwrap()
+        .insert(*key, (Instant::now(), values.to_vec()));
+}
+
+/// Every DEX-owned account starts with these five bytes.
+pub const ACCOUNT_HEAD_PADDING: &[u8; 5] = b"serum";
+/// Every DEX-owned account ends with these seven bytes.
+pub const ACCOUNT_TAIL_PADDING: &[u8; 7] = b"padding";
+
+bitflags! {
+    pub struct AccountFlags: u64 {
+        const INITIALIZED = 1 << 0;
+        const MARKET = 1 << 1;
+        const OPEN_ORDERS = 1 << 2;
+        const REQUEST_QUEUE = 1 << 3;
+        const EVENT_QUEUE = 1 << 4;
+        const BIDS = 1 << 5;
+        const ASKS = 1 << 6;
+        const DISABLED = 1 << 7;
+        const CLOSED = 1 << 8;
+        const PERMISSIONED = 1 << 9;
+        const CRANK_AUTHORITY_REQUIRED = 1 << 10;
+    }
+}
 
-    if let Some(error) = resp.error {
-        return Err(error.into());
+impl AccountFlags {
+    /// Flags naming the kind of account; an initialized account has exactly one.
+    pub const ACCOUNT_TYPES: AccountFlags = AccountFlags::MARKET
+        .union(AccountFlags::OPEN_ORDERS)
+        .union(AccountFlags::REQUEST_QUEUE)
+        .union(AccountFlags::EVENT_QUEUE)
+        .union(AccountFlags::BIDS)
+        .union(AccountFlags::ASKS);
+
+    /// Flags that only a market account may carry on top of its type.
+    pub const MARKET_OPTIONS: AccountFlags = AccountFlags::DISABLED
+        .union(AccountFlags::PERMISSIONED)
+        .union(AccountFlags::CRANK_AUTHORITY_REQUIRED);
+
+    /// Parses the raw `account_flags` word, rejecting bits the DEX does not define.
+    pub fn from_raw(bits: u64) -> Result<Self, DecodeError> {
+        AccountFlags::from_bits(bits).ok_or(DecodeError::UnknownAccountFlags(bits))
     }
 
-    let accounts = resp
-        .result
-        .iter()
-        .map(|(pubkey, account)|
-            Ok((
-                Pubkey::from_str(pubkey).unwrap(),
-                Account {
-                    lamports: account.lamports,
-                    owner: Pubkey::from_str(&account.owner).unwrap(),
-                    data: base64::decode(&account.data[0]).unwrap(),
-                    executable: account.executable,
-                    rent_epoch: account.rent_epoch,
-                },
-            ))
-        )
-        .collect::<Result<Vec<(Pubkey, Account)>, Box<dyn Error>>>()?;
+    pub fn expect_market(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::MARKET)
+    }
+
+    pub fn expect_open_orders(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::OPEN_ORDERS)
+    }
+
+    pub fn expect_request_queue(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::REQUEST_QUEUE)
+    }
+
+    pub fn expect_event_queue(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::EVENT_QUEUE)
+    }
+
+    pub fn expect_bids(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::BIDS)
+    }
+
+    pub fn expect_asks(self) -> Result<(), DecodeError> {
+        self.expect(AccountFlags::ASKS)
+    }
 
-    Ok(accounts)
+    /// Accepts either side of the book and returns whether it holds bids.
+    pub fn expect_orderbook(self) -> Result<bool, DecodeError> {
+        self.expect_bids()
+            .map(|()| true)
+            .or_else(|_| self.expect_asks().map(|()| false))
+            .map_err(|_| DecodeError::WrongAccountType {
+                expected: AccountFlags::BIDS | AccountFlags::ASKS,
+                actual: self,
+            })
+    }
+
+    /// An initialized, open account of type `kind`. Closed accounts and
+    /// accounts carrying a second type are rejected, as are the market
+    /// options on anything other than a market.
+    fn expect(self, kind: AccountFlags) -> Result<(), DecodeError> {
+        let mut allowed = AccountFlags::INITIALIZED | kind;
+        if kind == AccountFlags::MARKET {
+            allowed |= AccountFlags::MARKET_OPTIONS;
+        }
+        if self.contains(AccountFlags::INITIALIZED | kind) && allowed.contains(self) {
+            Ok(())
+        } else {
+            Err(DecodeError::WrongAccountType {
+                expected: kind,
+                actual: self,
+            })
+        }
+    }
 }
 
+/// Checks the "serum" head and "padding" tail around an account body.
+pub fn check_account_padding(data: &[u8]) -> Result<(), DecodeError> {
+    let min_len = ACCOUNT_HEAD_PADDING.len() + ACCOUNT_TAIL_PADDING.len();
+    if data.len() < min_len {
+        return Err(DecodeError::InvalidLength {
+            expected: min_len,
+            actual: data.len(),
+        });
+    }
+    if &data[..ACCOUNT_HEAD_PADDING.len()] != ACCOUNT_HEAD_PADDING {
+        return Err(DecodeError::InvalidHeadPadding);
+    }
+    if &data[data.len() - ACCOUNT_TAIL_PADDING.len()..] != ACCOUNT_TAIL_PADDING {
+        return Err(DecodeError::InvalidTailPadding);
+    }
+    Ok(())
+}
 
+/// Fields shared by every market layout, between the head padding and the
+/// version-specific trailer.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MarketStateBody {
+    pub account_flags: u64,
+    pub own_address: [u8; 32],
+    pub vault_signer_nonce: u64,
+    pub base_mint: [u8; 32],
+    pub quote_mint: [u8; 32],
+    pub base_vault: [u8; 32],
+    pub base_deposits_total: u64,
+    pub base_fees_accrued: u64,
+    pub quote_vault: [u8; 32],
+    pub quote_deposits_total: u64,
+    pub quote_fees_accrued: u64,
+    pub quote_dust_threshold: u64,
+    pub request_queue: [u8; 32],
+    pub event_queue: [u8; 32],
+    pub bids: [u8; 32],
+    pub asks: [u8; 32],
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub fee_rate_bps: u64,
+}
 
-pub static _MARKET_STAT_LAYOUT_V1: [u8; 0] = [];
-pub static MARKET_STATE_LAYOUT_V2: [u8; 0] = [];
+/// Market account of layout version 1 programs.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MarketStateLayoutV1 {
+    pub head_padding: [u8; 5],
+    pub body: MarketStateBody,
+    pub tail_padding: [u8; 7],
+}
 
-pub trait LayoutExt {
-    fn offset_of(field_name: &str) -> usize;
+/// Market account of layout version 2+ programs, which track referrer rebates.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MarketStateLayoutV2 {
+    pub head_padding: [u8; 5],
+    pub body: MarketStateBody,
+    pub referrer_rebates_accrued: u64,
+    pub tail_padding: [u8; 7],
+}
+
+/// Permissioned market account, which adds the authorities and reserved space.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MarketStateLayoutV3 {
+    pub head_padding: [u8; 5],
+    pub body: MarketStateBody,
+    pub referrer_rebates_accrued: u64,
+    pub authority: [u8; 32],
+    pub prune_authority: [u8; 32],
+    pub consume_events_authority: [u8; 32],
+    pub padding: [u8; 992],
+    pub tail_padding: [u8; 7],
+}
+
+const _: () = assert!(size_of::<MarketStateLayoutV1>() == 380);
+const _: () = assert!(size_of::<MarketStateLayoutV2>() == 388);
+const _: () = assert!(size_of::<MarketStateLayoutV3>() == 1476);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketLayout {
+    V1,
+    V2,
+    V3,
+}
+
+impl MarketLayout {
+    /// The layout of unpermissioned markets of programs with
+    /// `layout_version`. V2 and V3 share their field offsets up to the
+    /// authorities, so this is enough to build memcmp filters.
+    pub fn for_version(layout_version: u8) -> Self {
+        if layout_version == 1 {
+            MarketLayout::V1
+        } else {
+            MarketLayout::V2
+        }
+    }
+
+    /// The layout of a market account of `data_len` bytes. Version 1
+    /// programs only have V1; later programs use V2, or V3 for permissioned
+    /// markets, which are told apart by their length.
+    pub fn for_account(layout_version: u8, data_len: usize) -> Self {
+        match Self::for_version(layout_version) {
+            MarketLayout::V2 if data_len == MarketLayout::V3.span() => MarketLayout::V3,
+            layout => layout,
+        }
+    }
 }
 
-impl LayoutExt for [u8] {
-    fn offset_of(_field_name: &str) -> usize {
+const MARKET_STATE_BODY_OFFSETS: &[(&str, usize)] = &[
+    ("account_flags", offset_of!(MarketStateBody, account_flags)),
+    ("own_address", offset_of!(MarketStateBody, own_address)),
+    (
+        "vault_signer_nonce",
+        offset_of!(MarketStateBody, vault_signer_nonce),
+    ),
+    ("base_mint", offset_of!(MarketStateBody, base_mint)),
+    ("quote_mint", offset_of!(MarketStateBody, quote_mint)),
+    ("base_vault", offset_of!(MarketStateBody, base_vault)),
+    (
+        "base_deposits_total",
+        offset_of!(MarketStateBody, base_deposits_total),
+    ),
+    (
+        "base_fees_accrued",
+        offset_of!(MarketStateBody, base_fees_accrued),
+    ),
+    ("quote_vault", offset_of!(MarketStateBody, quote_vault)),
+    (
+        "quote_deposits_total",
+        offset_of!(MarketStateBody, quote_deposits_total),
+    ),
+    (
+        "quote_fees_accrued",
+        offset_of!(MarketStateBody, quote_fees_accrued),
+    ),
+    (
+        "quote_dust_threshold",
+        offset_of!(MarketStateBody, quote_dust_threshold),
+    ),
+    ("request_queue", offset_of!(MarketStateBody, request_queue)),
+    ("event_queue", offset_of!(MarketStateBody, event_queue)),
+    ("bids", offset_of!(MarketStateBody, bids)),
+    ("asks", offset_of!(MarketStateBody, asks)),
+    ("base_lot_size", offset_of!(MarketStateBody, base_lot_size)),
+    (
+        "quote_lot_size",
+        offset_of!(MarketStateBody, quote_lot_size),
+    ),
+    ("fee_rate_bps", offset_of!(MarketStateBody, fee_rate_bps)),
+];
+
+const MARKET_STATE_V1_OFFSETS: &[(&str, usize)] = &[
+    (
+        "head_padding",
+        offset_of!(MarketStateLayoutV1, head_padding),
+    ),
+    ("body", offset_of!(MarketStateLayoutV1, body)),
+    (
+        "tail_padding",
+        offset_of!(MarketStateLayoutV1, tail_padding),
+    ),
+];
+
+const MARKET_STATE_V2_OFFSETS: &[(&str, usize)] = &[
+    (
+        "head_padding",
+        offset_of!(MarketStateLayoutV2, head_padding),
+    ),
+    ("body", offset_of!(MarketStateLayoutV2, body)),
+    (
+        "referrer_rebates_accrued",
+        offset_of!(MarketStateLayoutV2, referrer_rebates_accrued),
+    ),
+    (
+        "tail_padding",
+        offset_of!(MarketStateLayoutV2, tail_padding),
+    ),
+];
+
+const MARKET_STATE_V3_OFFSETS: &[(&str, usize)] = &[
+    (
+        "head_padding",
+        offset_of!(MarketStateLayoutV3, head_padding),
+    ),
+    ("body", offset_of!(MarketStateLayoutV3, body)),
+    (
+        "referrer_rebates_accrued",
+        offset_of!(MarketStateLayoutV3, referrer_rebates_accrued),
+    ),
+    ("authority", offset_of!(MarketStateLayoutV3, authority)),
+    (
+        "prune_authority",
+        offset_of!(MarketStateLayoutV3, prune_authority),
+    ),
+    (
+        "consume_events_authority",
+        offset_of!(MarketStateLayoutV3, consume_events_authority),
+    ),
+    ("padding", offset_of!(MarketStateLayoutV3, padding)),
+    (
+        "tail_padding",
+        offset_of!(MarketStateLayoutV3, tail_padding),
+    ),
+];
+
+impl LayoutExt for MarketLayout {
+    fn offset_of(&self, field_name: &str) -> Result<usize, DecodeError> {
+        let (layout_offsets, body_offset) = match self {
+            MarketLayout::V1 => (
+                MARKET_STATE_V1_OFFSETS,
+                offset_of!(MarketStateLayoutV1, body),
+            ),
+            MarketLayout::V2 => (
+                MARKET_STATE_V2_OFFSETS,
+                offset_of!(MarketStateLayoutV2, body),
+            ),
+            MarketLayout::V3 => (
+                MARKET_STATE_V3_OFFSETS,
+                offset_of!(MarketStateLayoutV3, body),
+            ),
+        };
+        find_offset(layout_offsets, field_name)
+            .or_else(|| {
+                find_offset(MARKET_STATE_BODY_OFFSETS, field_name)
+                    .map(|offset| body_offset + offset)
+            })
+            .ok_or_else(|| DecodeError::UnknownField(field_name.to_string()))
+    }
 
+    fn span(&self) -> usize {
+        match self {
+            MarketLayout::V1 => size_of::<MarketStateLayoutV1>(),
+            MarketLayout::V2 => size_of::<MarketStateLayoutV2>(),
+            MarketLayout::V3 => size_of::<MarketStateLayoutV3>(),
+        }
     }
 }
 
+/// Decoded market account, independent of the layout it was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketState {
+    pub account_flags: AccountFlags,
+    pub own_address: Pubkey,
+    pub vault_signer_nonce: u64,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_vault: Pubkey,
+    pub base_deposits_total: u64,
+    pub base_fees_accrued: u64,
+    pub quote_vault: Pubkey,
+    pub quote_deposits_total: u64,
+    pub quote_fees_accrued: u64,
+    pub quote_dust_threshold: u64,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub fee_rate_bps: u64,
+    pub referrer_rebates_accrued: Option<u64>,
+    pub authority: Option<Pubkey>,
+    pub prune_authority: Option<Pubkey>,
+    pub consume_events_authority: Option<Pubkey>,
+}
+
+impl MarketState {
+    pub fn decode(data: &[u8], layout: MarketLayout) -> Result<Self, DecodeError> {
+        if data.len() != layout.span() {
+            return Err(DecodeError::InvalidLength {
+                expected: layout.span(),
+                actual: data.len(),
+            });
+        }
+        check_account_padding(data)?;
+        let account_flags = AccountFlags::from_raw(read_u64(data, ACCOUNT_HEAD_PADDING.len()))?;
+        account_flags.expect_market()?;
+
+        match layout {
+            MarketLayout::V1 => {
+                let state: &MarketStateLayoutV1 = bytemuck::from_bytes(data);
+                Ok(Self::from_body(&state.body))
+            }
+            MarketLayout::V2 => {
+                let state: &MarketStateLayoutV2 = bytemuck::from_bytes(data);
+                Ok(MarketState {
+                    referrer_rebates_accrued: Some(state.referrer_rebates_accrued),
+                    ..Self::from_body(&state.body)
+                })
+            }
+            MarketLayout::V3 => {
+                let state: &MarketStateLayoutV3 = bytemuck::from_bytes(data);
+                // The program only checks the crank authority of markets
+                // flagged to require one; elsewhere the key is unused.
+                let consume_events_authority = account_flags
+                    .contains(AccountFlags::CRANK_AUTHORITY_REQUIRED)
+                    .then(|| Pubkey::new_from_array(state.consume_events_authority));
+                Ok(MarketState {
+                    referrer_rebates_accrued: Some(state.referrer_rebates_accrued),
+                    authority: Some(Pubkey::new_from_array(state.authority)),
+                    prune_authority: Some(Pubkey::new_from_array(state.prune_authority)),
+                    consume_events_authority,
+                    ..Self::from_body(&state.body)
+                })
+            }
+        }
+    }
+
+    fn from_body(body: &MarketStateBody) -> Self {
+        MarketState {
+            account_flags: AccountFlags::from_bits_truncate(body.account_flags),
+            own_address: Pubkey::new_from_array(body.own_address),
+            vault_signer_nonce: body.vault_signer_nonce,
+            base_mint: Pubkey::new_from_array(body.base_mint),
+            quote_mint: Pubkey::new_from_array(body.quote_mint),
+            base_vault: Pubkey::new_from_array(body.base_vault),
+            base_deposits_total: body.base_deposits_total,
+            base_fees_accrued: body.base_fees_accrued,
+            quote_vault: Pubkey::new_from_array(body.quote_vault),
+            quote_deposits_total: body.quote_deposits_total,
+            quote_fees_accrued: body.quote_fees_accrued,
+            quote_dust_threshold: body.quote_dust_threshold,
+            request_queue: Pubkey::new_from_array(body.request_queue),
+            event_queue: Pubkey::new_from_array(body.event_queue),
+            bids: Pubkey::new_from_array(body.bids),
+            asks: Pubkey::new_from_array(body.asks),
+            base_lot_size: body.base_lot_size,
+            quote_lot_size: body.quote_lot_size,
+            fee_rate_bps: body.fee_rate_bps,
+            referrer_rebates_accrued: None,
+            authority: None,
+            prune_authority: None,
+            consume_events_authority: None,
+        }
+    }
+}
+
+/// Field offsets of a versioned account layout, used to build memcmp filters.
+pub trait LayoutExt {
+    fn offset_of(&self, field_name: &str) -> Result<usize, DecodeError>;
+    fn span(&self) -> usize;
+}
+
+pub(crate) fn find_offset(offsets: &[(&str, usize)], field_name: &str) -> Option<usize> {
+    offsets
+        .iter()
+        .find(|(name, _)| *name == field_name)
+        .map(|(_, offset)| *offset)
+}
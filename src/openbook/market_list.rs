@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use super::cluster::MarketInfo;
+use super::error::SdkError;
+
+/// An entry of a `markets.json` file as published by the Serum and OpenBook
+/// UIs.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MarketEntry {
+    name: String,
+    address: String,
+    program_id: String,
+    #[serde(default)]
+    deprecated: bool,
+}
+
+/// The markets of a `markets.json` file, indexed by name and address.
+#[derive(Debug, Clone, Default)]
+pub struct MarketList {
+    markets: Vec<MarketInfo>,
+    by_name: HashMap<String, usize>,
+    by_address: HashMap<Pubkey, usize>,
+}
+
+impl MarketList {
+    pub fn new(markets: Vec<MarketInfo>) -> Self {
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        let mut by_address = HashMap::new();
+        for (index, market) in markets.iter().enumerate() {
+            // Lists keep deprecated markets of older programs under the same
+            // name, so a name resolves to the first market still in use.
+            let replace = match by_name.get(&market.name) {
+                Some(&existing) => markets[existing].deprecated && !market.deprecated,
+                None => true,
+            };
+            if replace {
+                by_name.insert(market.name.clone(), index);
+            }
+            by_address.entry(market.address).or_insert(index);
+        }
+        MarketList {
+            markets,
+            by_name,
+            by_address,
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SdkError> {
+        let entries: Vec<MarketEntry> = serde_json::from_str(json)?;
+        Self::from_entries(entries)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, SdkError> {
+        let entries: Vec<MarketEntry> = serde_json::from_reader(reader)?;
+        Self::from_entries(entries)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SdkError> {
+        let file = File::open(path).map_err(serde_json::Error::io)?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    fn from_entries(entries: Vec<MarketEntry>) -> Result<Self, SdkError> {
+        let markets = entries
+            .into_iter()
+            .map(|entry| {
+                Ok(MarketInfo {
+                    name: entry.name,
+                    address: Pubkey::from_str(&entry.address)?,
+                    program_id: Pubkey::from_str(&entry.program_id)?,
+                    deprecated: entry.deprecated,
+                })
+            })
+            .collect::<Result<Vec<_>, SdkError>>()?;
+        Ok(Self::new(markets))
+    }
+
+    /// The market listed as `name`, e.g. `SOL/USDC`, preferring markets that
+    /// are not deprecated.
+    pub fn get(&self, name: &str) -> Option<&MarketInfo> {
+        self.by_name.get(name).map(|&index| &self.markets[index])
+    }
+
+    pub fn get_by_address(&self, address: &Pubkey) -> Option<&MarketInfo> {
+        self.by_address
+            .get(address)
+            .map(|&index| &self.markets[index])
+    }
+
+    /// All markets, in file order.
+    pub fn markets(&self) -> &[MarketInfo] {
+        &self.markets
+    }
+
+    /// The markets that are not deprecated, in file order.
+    pub fn active(&self) -> impl Iterator<Item = &MarketInfo> {
+        self.markets.iter().filter(|market| !market.deprecated)
+    }
+
+    pub fn len(&self) -> usize {
+        self.markets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.is_empty()
+    }
+}
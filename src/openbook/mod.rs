@@ -1,3 +1,4 @@
+pub mod account;
 pub mod market;
 pub mod order;
 pub mod slab;
@@ -5,3 +6,14 @@ pub mod queue;
 pub mod error;
 pub mod fees;
 pub mod instruction;
+pub mod transaction;
+pub mod explain;
+pub mod logs;
+pub mod registry;
+pub mod cluster;
+pub mod market_list;
+pub mod conversion;
+pub mod units;
+pub mod validation;
+pub mod order_id;
+pub mod l2;
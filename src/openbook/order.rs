@@ -1,163 +1,196 @@
-use solana_sdk::{
-    pubkey::Pubkey,
-    commitment_config::CommitmentConfig,
-    program_pack::Pack,
-    account::Account,
-    program_error::ProgramError,
-    account_info::AccountInfo,
-    system_instruction,
-};
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_filter::RpcFilterType;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::{account::Account, instruction::Instruction, pubkey::Pubkey, system_instruction};
 
 use bytemuck::{Pod, Zeroable};
-use std::mem::size_of;
-use std::{convert::TryInto, str::FromStr};
-use std::collections::HashMap;
-use std::error::Error;
-use borsh::{BorshDeserialize};
-use bigdecimal::BigDecimal;
-use std::collections::VecDeque;
-use std::iter::Iterator;
-use num_bigint::BigUint;
-
-use crate::market::Market;
-use crate::slab::Slab;
-use crate base64;
-use base64::{encode, decode};
-
-#[derive(Debug)]
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+use std::mem::{offset_of, size_of};
+
+use super::conversion::{LotConversion, Rounding};
+use super::error::{DecodeError, SdkError};
+use super::l2::{aggregate_l2, L2Level, L2Options};
+use super::market::{
+    check_account_padding, find_offset, get_filtered_program_accounts, AccountFlags, LayoutExt,
+    Market,
+};
+use super::order_id::OrderId;
+use super::registry::ProgramRegistry;
+use super::slab::{LeafNode, Slab};
+use super::units::{BaseLots, PriceLots};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
     Buy,
     Sell,
 }
 
-
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OrderType {
     Limit,
     Ioc,
     PostOnly,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SelfTradeBehavior {
     DecrementTake,
     CancelProvide,
     AbortTransaction,
 }
 
-#[derive(Debug)]
-pub struct OrderParamsBase<T = Account> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderParamsBase {
     pub side: Side,
-    pub price: f64,
-    pub size: f64,
+    pub price: Decimal,
+    pub size: Decimal,
     pub order_type: Option<OrderType>,
-    pub client_id: Option<BN>,
+    pub client_id: Option<u64>,
     pub self_trade_behavior: Option<SelfTradeBehavior>,
     pub max_ts: Option<i64>,
 }
 
-#[derive(Debug)]
-pub struct OrderParamsAccounts<T = Account> {
-    pub owner: T,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderParamsAccounts {
+    pub owner: Pubkey,
     pub payer: Pubkey,
     pub open_orders_address_key: Option<Pubkey>,
-    pub open_orders_account: Option<T>,
     pub fee_discount_pubkey: Option<Pubkey>,
     pub program_id: Option<Pubkey>,
 }
 
-#[derive(Debug)]
-pub struct OrderParams<T = Account> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderParams {
     pub side: Side,
-    pub price: f64,
-    pub size: f64,
+    pub price: Decimal,
+    pub size: Decimal,
     pub order_type: Option<OrderType>,
-    pub client_id: Option<BN>,
+    pub client_id: Option<u64>,
     pub self_trade_behavior: Option<SelfTradeBehavior>,
     pub max_ts: Option<i64>,
-    pub owner: T,
+    pub owner: Pubkey,
     pub payer: Pubkey,
     pub open_orders_address_key: Option<Pubkey>,
-    pub open_orders_account: Option<T>,
     pub fee_discount_pubkey: Option<Pubkey>,
     pub program_id: Option<Pubkey>,
     pub replace_if_exists: Option<bool>,
 }
 
-#[derive(Debug)]
-pub struct SendTakeParamsBase<T = Account> {
+impl OrderParams {
+    pub fn new(base: OrderParamsBase, accounts: OrderParamsAccounts) -> Self {
+        OrderParams {
+            side: base.side,
+            price: base.price,
+            size: base.size,
+            order_type: base.order_type,
+            client_id: base.client_id,
+            self_trade_behavior: base.self_trade_behavior,
+            max_ts: base.max_ts,
+            owner: accounts.owner,
+            payer: accounts.payer,
+            open_orders_address_key: accounts.open_orders_address_key,
+            fee_discount_pubkey: accounts.fee_discount_pubkey,
+            program_id: accounts.program_id,
+            replace_if_exists: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendTakeParamsBase {
     pub side: Side,
-    pub price: f64,
-    pub max_base_size: f64,
-    pub max_quote_size: f64,
-    pub min_base_size: f64,
-    pub min_quote_size: f64,
-    pub limit: Option<i64>,
+    pub price: Decimal,
+    pub max_base_size: Decimal,
+    pub max_quote_size: Decimal,
+    pub min_base_size: Decimal,
+    pub min_quote_size: Decimal,
+    pub limit: Option<u16>,
 }
 
-#[derive(Debug)]
-pub struct SendTakeParamsAccounts<T = Account> {
-    pub owner: T,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendTakeParamsAccounts {
+    pub owner: Pubkey,
     pub base_wallet: Pubkey,
     pub quote_wallet: Pubkey,
-    pub vault_signer: Option<Pubkey>,
     pub fee_discount_pubkey: Option<Pubkey>,
     pub program_id: Option<Pubkey>,
 }
 
-#[derive(Debug)]
-pub struct SendTakeParams<T = Account> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendTakeParams {
     pub side: Side,
-    pub price: f64,
-    pub max_base_size: f64,
-    pub max_quote_size: f64,
-    pub min_base_size: f64,
-    pub min_quote_size: f64,
-    pub limit: Option<i64>,
-    pub owner: T,
+    pub price: Decimal,
+    pub max_base_size: Decimal,
+    pub max_quote_size: Decimal,
+    pub min_base_size: Decimal,
+    pub min_quote_size: Decimal,
+    pub limit: Option<u16>,
+    pub owner: Pubkey,
     pub base_wallet: Pubkey,
     pub quote_wallet: Pubkey,
-    pub vault_signer: Option<Pubkey>,
     pub fee_discount_pubkey: Option<Pubkey>,
     pub program_id: Option<Pubkey>,
 }
 
-#[repr(C)]
+impl SendTakeParams {
+    pub fn new(base: SendTakeParamsBase, accounts: SendTakeParamsAccounts) -> Self {
+        SendTakeParams {
+            side: base.side,
+            price: base.price,
+            max_base_size: base.max_base_size,
+            max_quote_size: base.max_quote_size,
+            min_base_size: base.min_base_size,
+            min_quote_size: base.min_quote_size,
+            limit: base.limit,
+            owner: accounts.owner,
+            base_wallet: accounts.base_wallet,
+            quote_wallet: accounts.quote_wallet,
+            fee_discount_pubkey: accounts.fee_discount_pubkey,
+            program_id: accounts.program_id,
+        }
+    }
+}
+
+#[repr(C, packed)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct OpenOrdersLayoutV1 {
-    account_flags: u64, 
-    market: [u8; 32],   
-    owner: [u8; 32],    
-    base_token_free: u64,
-    base_token_total: u64,
-    quote_token_free: u64,
-    quote_token_total: u64,
-    free_slot_bits: u128,
-    is_bid_bits: u128,
-    orders: [u128; 128],
-    client_ids: [u64; 128],
-    padding: [u8; 7],   
+    head_padding: [u8; 5],
+    pub account_flags: u64,
+    pub market: [u8; 32],
+    pub owner: [u8; 32],
+    pub base_token_free: u64,
+    pub base_token_total: u64,
+    pub quote_token_free: u64,
+    pub quote_token_total: u64,
+    pub free_slot_bits: u128,
+    pub is_bid_bits: u128,
+    pub orders: [u128; 128],
+    pub client_ids: [u64; 128],
+    padding: [u8; 7],
 }
 
 impl OpenOrdersLayoutV1 {
-    pub fn new() -> Self {
-        unsafe { std::mem::zeroed() }
-    }
     pub fn size() -> usize {
         size_of::<Self>()
     }
 }
 
-const _: () = assert!(size_of::<OpenOrdersLayoutV1>() == 
-    5 + size_of::<u64>() + 32 + 32 + size_of::<u64>() * 4 
-    + size_of::<u128>() * 2 + size_of::<u128>() * 128 + 
-    size_of::<u64>() * 128 + 7
+const _: () = assert!(
+    size_of::<OpenOrdersLayoutV1>()
+        == 5 + size_of::<u64>()
+            + 32
+            + 32
+            + size_of::<u64>() * 4
+            + size_of::<u128>() * 2
+            + size_of::<u128>() * 128
+            + size_of::<u64>() * 128
+            + 7
 );
 
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
 pub struct OpenOrdersLayoutV2 {
+    head_padding: [u8; 5],
     pub account_flags: u64,
     pub market: [u8; 32],
     pub owner: [u8; 32],
@@ -170,396 +203,517 @@ pub struct OpenOrdersLayoutV2 {
     pub orders: [u128; 128],
     pub client_ids: [u64; 128],
     pub referrer_rebates_accrued: u64,
-    _padding: [u8; 7],
+    padding: [u8; 7],
 }
 
 impl OpenOrdersLayoutV2 {
-    pub fn new() -> Self {
-        unsafe { std::mem::zeroed() }
-    }
     pub fn size() -> usize {
         size_of::<Self>()
     }
 }
 
-pub struct OpenOrders {
-    program_id: Pubkey,
-    address: Pubkey,
-    market: Pubkey,
-    owner: Pubkey,
-    base_token_free: u64,
-    base_token_total: u64,
-    quote_token_free: u64,
-    quote_token_total: u64,
-    free_slot_bits: u128,
-    is_bid_bits: u128,
-    orders: [u128; 128],
-    client_ids: [u64; 128],
+const _: () = assert!(size_of::<OpenOrdersLayoutV2>() == size_of::<OpenOrdersLayoutV1>() + 8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenOrdersLayout {
+    V1,
+    V2,
 }
 
-impl OpenOrders {
-    pub fn new(address: Pubkey, 
-        decoded: DecodedType,   
-        program_id: Pubkey) -> Self {
-            let mut instance = Self {
-                address,
-                program_id: program_id,
+impl OpenOrdersLayout {
+    /// Version 1 programs use V1; later programs track referrer rebates.
+    pub fn for_version(layout_version: u8) -> Self {
+        if layout_version == 1 {
+            OpenOrdersLayout::V1
+        } else {
+            OpenOrdersLayout::V2
+        }
+    }
+}
+
+const OPEN_ORDERS_V1_OFFSETS: &[(&str, usize)] = &[
+    ("head_padding", offset_of!(OpenOrdersLayoutV1, head_padding)),
+    (
+        "account_flags",
+        offset_of!(OpenOrdersLayoutV1, account_flags),
+    ),
+    ("market", offset_of!(OpenOrdersLayoutV1, market)),
+    ("owner", offset_of!(OpenOrdersLayoutV1, owner)),
+    (
+        "base_token_free",
+        offset_of!(OpenOrdersLayoutV1, base_token_free),
+    ),
+    (
+        "base_token_total",
+        offset_of!(OpenOrdersLayoutV1, base_token_total),
+    ),
+    (
+        "quote_token_free",
+        offset_of!(OpenOrdersLayoutV1, quote_token_free),
+    ),
+    (
+        "quote_token_total",
+        offset_of!(OpenOrdersLayoutV1, quote_token_total),
+    ),
+    (
+        "free_slot_bits",
+        offset_of!(OpenOrdersLayoutV1, free_slot_bits),
+    ),
+    ("is_bid_bits", offset_of!(OpenOrdersLayoutV1, is_bid_bits)),
+    ("orders", offset_of!(OpenOrdersLayoutV1, orders)),
+    ("client_ids", offset_of!(OpenOrdersLayoutV1, client_ids)),
+    ("tail_padding", offset_of!(OpenOrdersLayoutV1, padding)),
+];
+
+const OPEN_ORDERS_V2_OFFSETS: &[(&str, usize)] = &[
+    ("head_padding", offset_of!(OpenOrdersLayoutV2, head_padding)),
+    (
+        "account_flags",
+        offset_of!(OpenOrdersLayoutV2, account_flags),
+    ),
+    ("market", offset_of!(OpenOrdersLayoutV2, market)),
+    ("owner", offset_of!(OpenOrdersLayoutV2, owner)),
+    (
+        "base_token_free",
+        offset_of!(OpenOrdersLayoutV2, base_token_free),
+    ),
+    (
+        "base_token_total",
+        offset_of!(OpenOrdersLayoutV2, base_token_total),
+    ),
+    (
+        "quote_token_free",
+        offset_of!(OpenOrdersLayoutV2, quote_token_free),
+    ),
+    (
+        "quote_token_total",
+        offset_of!(OpenOrdersLayoutV2, quote_token_total),
+    ),
+    (
+        "free_slot_bits",
+        offset_of!(OpenOrdersLayoutV2, free_slot_bits),
+    ),
+    ("is_bid_bits", offset_of!(OpenOrdersLayoutV2, is_bid_bits)),
+    ("orders", offset_of!(OpenOrdersLayoutV2, orders)),
+    ("client_ids", offset_of!(OpenOrdersLayoutV2, client_ids)),
+    (
+        "referrer_rebates_accrued",
+        offset_of!(OpenOrdersLayoutV2, referrer_rebates_accrued),
+    ),
+    ("tail_padding", offset_of!(OpenOrdersLayoutV2, padding)),
+];
+
+impl LayoutExt for OpenOrdersLayout {
+    fn offset_of(&self, field_name: &str) -> Result<usize, DecodeError> {
+        let offsets = match self {
+            OpenOrdersLayout::V1 => OPEN_ORDERS_V1_OFFSETS,
+            OpenOrdersLayout::V2 => OPEN_ORDERS_V2_OFFSETS,
         };
-        instance
+        find_offset(offsets, field_name)
+            .ok_or_else(|| DecodeError::UnknownField(field_name.to_string()))
     }
-    fn get_layout_version(program_id: &Pubkey) -> u8 {
-        unimplemented!()
+
+    fn span(&self) -> usize {
+        match self {
+            OpenOrdersLayout::V1 => OpenOrdersLayoutV1::size(),
+            OpenOrdersLayout::V2 => OpenOrdersLayoutV2::size(),
+        }
     }
+}
 
-    fn get_layout(program_id: &Pubkey) -> &'static str {
-        if get_layout_version(program_id) == 1 {
-            OpenOrdersLayoutV2
-        } else {
-            OpenOrdersLayoutV2
+/// Decoded open orders account, independent of the layout it was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOrdersState {
+    pub account_flags: AccountFlags,
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub base_token_free: u64,
+    pub base_token_total: u64,
+    pub quote_token_free: u64,
+    pub quote_token_total: u64,
+    pub free_slot_bits: u128,
+    pub is_bid_bits: u128,
+    pub orders: [u128; 128],
+    pub client_ids: [u64; 128],
+    pub referrer_rebates_accrued: Option<u64>,
+}
+
+impl OpenOrdersState {
+    pub fn decode(data: &[u8], layout: OpenOrdersLayout) -> Result<Self, DecodeError> {
+        if data.len() != layout.span() {
+            return Err(DecodeError::InvalidLength {
+                expected: layout.span(),
+                actual: data.len(),
+            });
         }
+        check_account_padding(data)?;
+
+        match layout {
+            OpenOrdersLayout::V1 => {
+                let state: &OpenOrdersLayoutV1 = bytemuck::from_bytes(data);
+                let account_flags = AccountFlags::from_raw(state.account_flags)?;
+                account_flags.expect_open_orders()?;
+                Ok(OpenOrdersState {
+                    account_flags,
+                    market: Pubkey::new_from_array(state.market),
+                    owner: Pubkey::new_from_array(state.owner),
+                    base_token_free: state.base_token_free,
+                    base_token_total: state.base_token_total,
+                    quote_token_free: state.quote_token_free,
+                    quote_token_total: state.quote_token_total,
+                    free_slot_bits: state.free_slot_bits,
+                    is_bid_bits: state.is_bid_bits,
+                    orders: state.orders,
+                    client_ids: state.client_ids,
+                    referrer_rebates_accrued: None,
+                })
+            }
+            OpenOrdersLayout::V2 => {
+                let state: &OpenOrdersLayoutV2 = bytemuck::from_bytes(data);
+                let account_flags = AccountFlags::from_raw(state.account_flags)?;
+                account_flags.expect_open_orders()?;
+                Ok(OpenOrdersState {
+                    account_flags,
+                    market: Pubkey::new_from_array(state.market),
+                    owner: Pubkey::new_from_array(state.owner),
+                    base_token_free: state.base_token_free,
+                    base_token_total: state.base_token_total,
+                    quote_token_free: state.quote_token_free,
+                    quote_token_total: state.quote_token_total,
+                    free_slot_bits: state.free_slot_bits,
+                    is_bid_bits: state.is_bid_bits,
+                    orders: state.orders,
+                    client_ids: state.client_ids,
+                    referrer_rebates_accrued: Some(state.referrer_rebates_accrued),
+                })
+            }
+        }
+    }
+}
+
+/// An open orders account: the funds and order slots an owner has on one
+/// market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrders {
+    pub program_id: Pubkey,
+    pub address: Pubkey,
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub base_token_free: u64,
+    pub base_token_total: u64,
+    pub quote_token_free: u64,
+    pub quote_token_total: u64,
+    pub free_slot_bits: u128,
+    pub is_bid_bits: u128,
+    pub orders: [u128; 128],
+    pub client_ids: [u64; 128],
+}
+
+impl OpenOrders {
+    pub fn new(address: Pubkey, state: OpenOrdersState, program_id: Pubkey) -> Self {
+        OpenOrders {
+            program_id,
+            address,
+            market: state.market,
+            owner: state.owner,
+            base_token_free: state.base_token_free,
+            base_token_total: state.base_token_total,
+            quote_token_free: state.quote_token_free,
+            quote_token_total: state.quote_token_total,
+            free_slot_bits: state.free_slot_bits,
+            is_bid_bits: state.is_bid_bits,
+            orders: state.orders,
+            client_ids: state.client_ids,
+        }
+    }
+
+    fn get_layout(program_id: &Pubkey) -> OpenOrdersLayout {
+        OpenOrdersLayout::for_version(ProgramRegistry::global().layout_version(program_id))
     }
 
-    pub async fn get_derived_00_account_pubkey(
+    /// The account `owner_address` creates for a market with
+    /// `create_account_with_seed`, and its seed: the first 32 characters of
+    /// the market address.
+    pub fn get_derived_oo_account_pubkey(
         owner_address: &Pubkey,
         market_address: &Pubkey,
         program_id: &Pubkey,
-    ) -> Result<(Pubkey, String), Box<dyn std::error::Error>> {
-        let seed = market_address.to_string().get(..32)
-            .ok_or("Invalid seed length")?.to_string();
-        let (public_key, _) = Pubkey::create_with_seed(
-            owner_address,
-            &seed,
-            program_id,
-        )?;
+    ) -> Result<(Pubkey, String), SdkError> {
+        let seed = market_address
+            .to_string()
+            .get(..32)
+            .ok_or(SdkError::InvalidAccount {
+                account: *market_address,
+                reason: "address is too short to seed an open orders account",
+            })?
+            .to_string();
+        let public_key = Pubkey::create_with_seed(owner_address, &seed, program_id)?;
         Ok((public_key, seed))
     }
 
-    async fn find_for_owner(
+    /// Every open orders account of `owner_address`, on any market of the
+    /// program.
+    pub async fn find_for_owner(
         connection: &RpcClient,
-        owner_address: &str,
-        program_id: &str,
-    ) -> Result<Vec<OpenOrders>, Box<dyn std::error::Error>> {
-        let owner_pubkey = Pubkey::from_str(owner_address)?;
-        let program_pubkey = Pubkey::from_str(program_id)?;
-    
+        owner_address: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Result<Vec<OpenOrders>, SdkError> {
+        let layout = Self::get_layout(program_id);
         let filters = vec![
-            solana_client::rpc_filter::RpcFilterType::Memcmp {
-                offset: OpenOrders::get_layout(&program_pubkey).offset_of("owner")?,
-                bytes: bs58::decode(owner_pubkey.to_string()).into_vec()?,
-            },
-            solana_client::rpc_filter::RpcFilterType::DataSize(
-                OpenOrders::get_layout(&program_pubkey).span as usize,
-            ),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                layout.offset_of("owner").map_err(SdkError::Layout)?,
+                owner_address.to_bytes().to_vec(),
+            )),
+            RpcFilterType::DataSize(layout.span() as u64),
         ];
-    
-        let accounts: <<Result<Vec<(Pubkey, solana_sdk::account::Account)>, 
-            solana_client::client_error::ClientError> as IntoFuture>::Output as Try>::Output 
-                = connection.get_program_accounts_with_config(
-                    &program_pubkey,
-                    solana_client::rpc_config::RpcProgramAccountsConfig {
-                        filters: Some(filters),
-                        account_config: solana_client::rpc_config::RpcAccountInfoConfig {
-                            encoding: Some(solana_client::rpc_config::UiAccountEncoding::Base64),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    CommitmentConfig::confirmed(),
-            ).await?;
-    
-        let open_orders: Vec<OpenOrders> = accounts.iter().map(|(public_key, account)| {
-            OpenOrders::from_account_info(public_key, account, &program_pubkey)
-        }).collect();
-    
-        Ok(open_orders)
+        let accounts = get_filtered_program_accounts(connection, program_id, filters).await?;
+        accounts
+            .iter()
+            .map(|(public_key, account)| {
+                OpenOrders::from_account_info(public_key, account, program_id)
+            })
+            .collect()
     }
 
-    async fn find_for_market_and_owner(
+    /// The open orders accounts of `owner_address` on one market. The
+    /// account derived with `get_derived_oo_account_pubkey` is used when it
+    /// exists; otherwise, unless `force_seed_account` is set, any account of
+    /// the owner on the market is searched for.
+    pub async fn find_for_market_and_owner(
         connection: &RpcClient,
         market_address: &Pubkey,
         owner_address: &Pubkey,
         program_id: &Pubkey,
         force_seed_account: bool,
-    ) -> Result<Vec<OpenOrders>, Box<dyn std::error::Error>> {
-        let account = get_derived_oo_account_pubkey(
-            owner_address,
-            market_address,
-            program_id,
-        ).await?;
-        let oo_account_info = connection.get_account(&account).await?;
-        if let Some(account_info) = oo_account_info {
-            return Ok(vec![
-                OpenOrders::from_account_info(&account, &account_info, program_id),
-            ]);
+    ) -> Result<Vec<OpenOrders>, SdkError> {
+        let (account, _) =
+            Self::get_derived_oo_account_pubkey(owner_address, market_address, program_id)?;
+        let account_info = connection
+            .get_account_with_commitment(&account, connection.commitment())
+            .await?
+            .value;
+        if let Some(account_info) = account_info {
+            return Ok(vec![OpenOrders::from_account_info(
+                &account,
+                &account_info,
+                program_id,
+            )?]);
         }
         if force_seed_account {
             return Ok(vec![]);
         }
+
+        let layout = Self::get_layout(program_id);
         let filters = vec![
-            RpcFilterType::Memcmp(Memcmp {
-                offset: get_layout(program_id)?.offset_of("market")?,
-                bytes: MemcmpEncodedBytes::Base58(marketAddress.to_string()),
-                encoding: None,
-            }),
-            RpcFilterType::Memcmp(Memcmp {
-                offset: get_layout(program_id)?.offset_of("owner")?,
-                bytes: MemcmpEncodedBytes::Base58(ownerAddress.to_string()),
-                encoding: None,
-            }),
-            RpcFilterType::DataSize(get_layout(program_id)?.span()?),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                layout.offset_of("market").map_err(SdkError::Layout)?,
+                market_address.to_bytes().to_vec(),
+            )),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                layout.offset_of("owner").map_err(SdkError::Layout)?,
+                owner_address.to_bytes().to_vec(),
+            )),
+            RpcFilterType::DataSize(layout.span() as u64),
         ];
-        let accounts = get_filtered_program_accounts(
-            connection,
-            program_id,
-            filters,
-        ).await?;
-        Ok(accounts.iter().map(|(public_key, account_info)| {
-            OpenOrders::from_account_info(public_key, account_info, program_id)
-        }).collect())
+        let accounts = get_filtered_program_accounts(connection, program_id, filters).await?;
+        accounts
+            .iter()
+            .map(|(public_key, account_info)| {
+                OpenOrders::from_account_info(public_key, account_info, program_id)
+            })
+            .collect()
     }
 
-    async fn load(
+    pub async fn load(
         connection: &RpcClient,
         address: &Pubkey,
         program_id: &Pubkey,
-    ) -> Result<Self, Box<dyn Error>> {
-        let account_info = connection.get_account_info(address).await?;
-        if account_info.is_none() {
-            return Err("Open orders account not found".into());
-        }
-        Ok(OpenOrders::from_account_info(address, &account_info.unwrap(), program_id))
+    ) -> Result<Self, SdkError> {
+        let account_info = connection
+            .get_account_with_commitment(address, connection.commitment())
+            .await?
+            .value
+            .ok_or(SdkError::AccountNotFound(*address))?;
+        OpenOrders::from_account_info(address, &account_info, program_id)
     }
 
     pub fn from_account_info(
         address: &Pubkey,
-        account_info: &AccountInfo,
+        account_info: &Account,
         program_id: &Pubkey,
-    ) -> Result<Self, ProgramError> {
+    ) -> Result<Self, SdkError> {
         let owner = &account_info.owner;
         if owner != program_id {
-            return Err(ProgramError::Custom(0));
+            return Err(SdkError::InvalidAccountOwner {
+                account: *address,
+                owner: *owner,
+                expected: *program_id,
+            });
         }
-        let data = &account_info.data.borrow();
-        let decoded = Self::get_layout(program_id).try_from_slice(data)?;
-        if !decoded.account_flags.initialized || !decoded.account_flags.open_orders {
-            return Err(ProgramError::Custom(1)); 
-        }
-        Ok(OpenOrders {
-            address: address,
-            decoded: decoded,
-            program_id: program_id,
-        })
+        let state = OpenOrdersState::decode(&account_info.data, Self::get_layout(program_id))
+            .map_err(|err| SdkError::decode(*address, err))?;
+        Ok(OpenOrders::new(*address, state, *program_id))
     }
 
-    async fn make_create_account_transaction(
+    pub async fn make_create_account_instruction(
         connection: &RpcClient,
-        market_address: &Pubkey,
         owner_address: &Pubkey,
         new_account_address: &Pubkey,
         program_id: &Pubkey,
         seed: &str,
-    ) -> Result<Transaction, Box<dyn std::error::Error>> {
-        let lamports = connection.get_minimum_balance_for_rent_exemption(
-            get_layout(program_id).span,
-        ).await?;
-    
-        let create_account_instruction = system_instruction::create_account_with_seed(
+    ) -> Result<Instruction, SdkError> {
+        let span = Self::get_layout(program_id).span();
+        let lamports = connection
+            .get_minimum_balance_for_rent_exemption(span)
+            .await?;
+        Ok(system_instruction::create_account_with_seed(
             owner_address,
             new_account_address,
             owner_address,
             seed,
             lamports,
-            get_layout(program_id).span, 
+            span as u64,
             program_id,
-        );
-    
-        let transaction = Transaction::new_signed_with_payer(
-            &[create_account_instruction],
-            Some(owner_address),
-            &[],
-            connection.get_latest_blockhash().await?,
-        );
-    
-        Ok(transaction)
+        ))
     }
 
-    fn public_key(&self) -> &str {
-        &self.address
+    pub fn public_key(&self) -> Pubkey {
+        self.address
     }
-
 }
 
+/// One side of a market's order book.
 pub struct Orderbook {
-    market: Market,
+    conversion: LotConversion,
     is_bids: bool,
     slab: Slab,
 }
 
 impl Orderbook {
-    fn new(market: Market, account_flags: AccountFlags, slab: Slab) -> Result<Self, Box<dyn Error>> {
-        if !account_flags.initialized || !(account_flags.bids ^ account_flags.asks) {
-            Err("Invalid orderbook".into())
-        } else {
-            Ok(Orderbook {
-                market,
-                is_bids: account_flags.bids,
-                slab,
-            })
-        }
+    fn new(
+        conversion: LotConversion,
+        account_flags: AccountFlags,
+        slab: Slab,
+    ) -> Result<Self, DecodeError> {
+        let is_bids = account_flags.expect_orderbook()?;
+        Ok(Orderbook {
+            conversion,
+            is_bids,
+            slab,
+        })
     }
 
-    fn layout() -> &'static OrderbookLayout {
-        &ORDERBOOK_LAYOUT
+    /// Decodes the bids (`Side::Buy`) or asks (`Side::Sell`) account of `market`.
+    pub fn decode(market: &Market, buffer: &[u8], side: Side) -> Result<Self, SdkError> {
+        let address = match side {
+            Side::Buy => market.bids_address(),
+            Side::Sell => market.asks_address(),
+        };
+        let decode = || {
+            let (account_flags, slab) = Slab::decode_account(buffer)?;
+            match side {
+                Side::Buy => account_flags.expect_bids()?,
+                Side::Sell => account_flags.expect_asks()?,
+            }
+            Orderbook::new(market.lot_conversion(), account_flags, slab)
+        };
+        decode().map_err(|err| SdkError::decode(address, err))
     }
 
-    fn decode(market: Market, buffer: &[u8]) -> Result<Self, Box<dyn Error>> {
-        let (account_flags, slab) = Orderbook::layout().decode(buffer)?;
-        Orderbook::new(market, account_flags, slab)
+    /// The price levels of this side of the book, best first.
+    pub fn get_l2(&self, options: &L2Options) -> Vec<L2Level> {
+        let orders = self
+            .slab
+            .items(self.is_bids)
+            .map(|leaf| (leaf.order_id().price_lots(), BaseLots::new(leaf.quantity)));
+        aggregate_l2(&self.conversion, self.side(), orders, options)
     }
 
-    fn get_l2(&self, depth: usize) -> Vec<(f64, f64, BigDecimal, BigDecimal)> {
-        let descending = self.is_bids;
-        let mut levels: VecDeque<(BigDecimal, BigDecimal)> = VecDeque::new();
-        for item in self.slab.items(descending) {
-            let price = self.market.get_price_from_key(&item.key);
-            if let Some(last) = levels.back_mut() {
-                if last.0 == price {
-                    last.1 = last.1.clone() + &item.quantity;
-                    continue;
-                }
-            }
-            if levels.len() == depth {
-                break;
-            }
-            levels.push_back((price, item.quantity));
+    pub fn side(&self) -> Side {
+        if self.is_bids {
+            Side::Buy
+        } else {
+            Side::Sell
         }
-        levels
-            .into_iter()
-            .map(|(price_lots, size_lots)| {
-                (
-                    self.market.price_lots_to_number(&price_lots),
-                    self.market.base_size_lots_to_number(&size_lots),
-                    price_lots,
-                    size_lots,
-                )
-            })
-            .collect()
     }
 
-    fn items(&self, descending: bool) -> impl Iterator<Item = Order> + '_ {
-        self.slab.items(descending).into_iter().map(move |item| {
-            let price = get_price_from_key(item.key);
-            Order {
-                order_id: item.key,
-                client_id: item.client_order_id,
-                open_orders_address: item.owner,
-                open_orders_slot: item.owner_slot,
-                fee_tier: item.fee_tier,
-                price: (self.market.price_lots_to_number)(price),
-                price_lots: price,
-                size: (self.market.base_size_lots_to_number)(item.quantity),
-                size_lots: item.quantity,
-                side: if self.is_bids { Side::Buy } else { Side::Sell },
-            }
-        })
+    pub fn items(&self, descending: bool) -> impl Iterator<Item = Order> + '_ {
+        self.slab
+            .items(descending)
+            .map(move |leaf| self.leaf_to_order(leaf))
     }
 
-}
+    /// Highest bid or lowest ask, depending on the side of the book.
+    pub fn best(&self) -> Option<Order> {
+        let leaf = if self.is_bids {
+            self.slab.find_max()
+        } else {
+            self.slab.find_min()
+        };
+        leaf.map(|leaf| self.leaf_to_order(leaf))
+    }
+
+    /// The resting order with `order_id`, e.g. to check that an order is
+    /// still on the book before cancelling it.
+    pub fn get(&self, order_id: OrderId) -> Option<Order> {
+        self.slab
+            .get(order_id.get())
+            .map(|leaf| self.leaf_to_order(leaf))
+    }
 
-fn get_price_from_key(key: u64) -> u64 {
-    key.ushrn(64)
+    /// The orders resting in any of `open_orders_accounts`.
+    pub fn orders_for(&self, open_orders_accounts: &HashSet<Pubkey>) -> Vec<Order> {
+        self.items(false)
+            .filter(|order| open_orders_accounts.contains(&order.open_orders_address))
+            .collect()
+    }
+
+    fn leaf_to_order(&self, leaf: &LeafNode) -> Order {
+        let order_id = leaf.order_id();
+        let price = order_id.price_lots();
+        let size = BaseLots::new(leaf.quantity);
+        Order {
+            order_id,
+            // The program stores orders placed without a client id as 0.
+            client_id: (leaf.client_order_id != 0).then_some(leaf.client_order_id),
+            open_orders_address: leaf.owner,
+            open_orders_slot: leaf.owner_slot,
+            fee_tier: leaf.fee_tier,
+            price: self
+                .conversion
+                .price_lots_to_number(price, Rounding::Nearest)
+                .unwrap_or_default(),
+            price_lots: price,
+            size: self
+                .conversion
+                .base_size_lots_to_number(size, Rounding::Nearest)
+                .unwrap_or_default(),
+            size_lots: size,
+            side: self.side(),
+        }
+    }
 }
 
-impl IntoIterator for Orderbook {
+impl<'a> IntoIterator for &'a Orderbook {
     type Item = Order;
-    type IntoIter = Box<dyn Iterator<Item = Self::Item>>;
+    type IntoIter = Box<dyn Iterator<Item = Order> + 'a>;
 
+    /// Orders from the lowest price up.
     fn into_iter(self) -> Self::IntoIter {
         Box::new(self.items(false))
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Order {
-    pub order_id: BN,
+    pub order_id: OrderId,
     pub open_orders_address: Pubkey,
-    pub open_orders_slot: u64,
-    pub price: f64,
-    pub price_lots: BN,
-    pub size: f64,
-    pub fee_tier: u64,
-    pub size_lots: BN,
+    pub open_orders_slot: u8,
+    pub price: Decimal,
+    pub price_lots: PriceLots,
+    pub size: Decimal,
+    pub fee_tier: u8,
+    pub size_lots: BaseLots,
     pub side: Side,
-    pub client_id: Option<BN>,
-}
-
-fn get_price_from_key(key: u128) -> u64 {
-    key >> 64
+    pub client_id: Option<u64>,
 }
-
-fn divide_bn_to_number(numerator: &BN, denominator: &BN) -> f64 {
-    if numerator.bit_length() <= 53 && denominator.bit_length() <= 53 {
-        return f64::from(numerator.to_u64().unwrap()) / f64::from(denominator.to_u64().unwrap());
-    }
-
-    let gcd_nd = numerator.gcd(denominator);
-
-    let mut temp_numerator = numerator.div(&gcd_nd);
-    let mut temp_denominator = denominator.div(&gcd_nd);
-
-    if temp_numerator.bit_length() <= 53 && temp_denominator.bit_length() <= 53 {
-        return f64::from(temp_numerator.to_u64().unwrap()) / f64::from(temp_denominator.to_u64().unwrap());
-    }
-
-    let numerator_shift = temp_numerator.leading_zeros();
-    if numerator_shift > 0 {
-        temp_numerator >>= numerator_shift;
-    }
-    let denominator_shift = temp_denominator.leading_zeros();
-    if denominator_shift > 0 {
-        temp_denominator >>= denominator_shift;
-    }
-
-    let exponent_bias = 2_f64.powi(numerator_shift as i32 - denominator_shift as i32);
-
-    if temp_numerator.bit_length() <= 53 && temp_denominator.bit_length() <= 53 {
-        return exponent_bias * (f64::from(temp_numerator.to_u64().unwrap()) / f64::from(temp_denominator.to_u64().unwrap()));
-    }
-
-    let string_math = f64::from_str(&temp_numerator.to_string())? / f64::from_str(&temp_denominator.to_string())?;
-    return exponent_bias * string_math;
-}
-
-async fn get_mint_decimals(connection: &Connection, mint: &Pubkey) -> Result<u8, ProgramError> {
-    if mint == &WRAPPED_SOL_MINT {
-        return Ok(9);
-    }
-
-    let account_info = connection.get_account(mint).await?;
-    let data = account_info.data.as_ref().ok_or(ProgramError::InvalidAccountData)?;
-
-    let (_, decimals) = MINT_LAYOUT.decode(data)?;
-
-    Ok(decimals)
-}
-
-async fn get_filtered_program_accounts(
-    connection: &Connection, program_id: &Pubkey, filters: Vec<Filter>) -> Result<Vec<(Pubkey, AccountInfo)>, ProgramError> 
-    {
-        let resp = connection.get_program_accounts(&program_id, Some(&filters)).await?;
-    
-        let result = resp.into_iter().map(|v| {
-            let pubkey = Pubkey::from_str(&v.pubkey).unwrap();
-            let data = base64::decode(v.account.data[0]).unwrap();
-            let owner = Pubkey::from_str(&v.account.owner).unwrap();
-            let lamports = v.account.lamports;
-
-            (pubkey, AccountInfo { data, executable: v.account.executable, owner, lamports })
-        }).collect();
-
-        Ok(result)
-}
-
-fn throw_if_null<T>(value: Option<T>, message: &'static str) -> Result<T, ProgramError> {
-    value.ok_or_else(|| ProgramError::Custom(message.into()))
-}
-
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use super::order::Side;
+use super::units::PriceLots;
+
+/// The id of a resting order, which is also its key in the bids or asks slab:
+/// the price in lots in the upper 64 bits and the sequence number in the
+/// lower 64. Bids store the sequence number inverted, so that earlier bids at
+/// the same price sort higher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderId(pub u128);
+
+impl OrderId {
+    pub fn new(side: Side, price: PriceLots, seq_num: u64) -> Self {
+        let seq = match side {
+            Side::Buy => !seq_num,
+            Side::Sell => seq_num,
+        };
+        OrderId((u128::from(price.get()) << 64) | u128::from(seq))
+    }
+
+    pub fn get(self) -> u128 {
+        self.0
+    }
+
+    pub fn price_lots(self) -> PriceLots {
+        PriceLots::new((self.0 >> 64) as u64)
+    }
+
+    /// The sequence number the order was placed with, given the side of the
+    /// book it rests on.
+    pub fn seq_num(self, side: Side) -> u64 {
+        let seq = self.0 as u64;
+        match side {
+            Side::Buy => !seq,
+            Side::Sell => seq,
+        }
+    }
+
+    /// The side the id most likely belongs to, for ids seen without one.
+    /// Sequence numbers stay far below 2^63, so only inverted bid sequence
+    /// numbers have the top bit of the lower half set.
+    pub fn side_hint(self) -> Side {
+        if (self.0 as u64) >> 63 == 1 {
+            Side::Buy
+        } else {
+            Side::Sell
+        }
+    }
+}
+
+impl From<u128> for OrderId {
+    fn from(key: u128) -> Self {
+        OrderId(key)
+    }
+}
+
+impl From<OrderId> for u128 {
+    fn from(order_id: OrderId) -> Self {
+        order_id.0
+    }
+}
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::LowerHex for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
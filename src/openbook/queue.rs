@@ -1,103 +1,183 @@
-use solana_sdk::{
-    address_lookup_table::program,
-    account::Account,
-    commitment_config::CommitmentConfig,
-    pubkey::Pubkey,
-    connection::Connection,
-    transaction::Transaction,
-    program_pack::{Pack, IsInitialized},
-    system_instruction,
-    sysvar::{rent, Sysvar},
-    instruction::{Instruction, SystemProgram},
-};
+use solana_sdk::pubkey::Pubkey;
 
 use bitflags::bitflags;
-// use std::io::Error;
+use std::convert::TryInto;
 
-#[derive(Debug)]
-struct RequestQueueHeader {
-    account_flags: AccountFlags,
-    head: u32,
-    count: u32,
-    next_seq_num: u32,
-    _padding: [u8; 12], 
+use super::error::DecodeError;
+use super::market::{
+    check_account_padding, AccountFlags, ACCOUNT_HEAD_PADDING, ACCOUNT_TAIL_PADDING,
+};
+use super::order::{OrderType, Side};
+use super::order_id::OrderId;
+use super::slab::{read_u128, read_u32, read_u64};
+
+const QUEUE_HEADER_LEN: usize = 37;
+
+pub const REQUEST_QUEUE_HEADER_LEN: usize = QUEUE_HEADER_LEN;
+pub const REQUEST_LEN: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestQueueHeader {
+    pub account_flags: AccountFlags,
+    pub head: u32,
+    pub count: u32,
+    pub next_seq_num: u32,
 }
 
 impl RequestQueueHeader {
-    fn decode(input: &[u8]) -> Self {
-        let (account_flags, head, count, next_seq_num, _, padding) = struct_def![
-            blob(5),
-            AccountFlags,
-            u32,
-            zeros(4),
-            u32,
-            zeros(4)
-        ]
-        .unpack(input)
-        .unwrap();
-
-        RequestQueueHeader {
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let (account_flags, head, count, next_seq_num) = decode_queue_header(data)?;
+        account_flags.expect_request_queue()?;
+        Ok(RequestQueueHeader {
             account_flags,
             head,
             count,
             next_seq_num,
-            _padding: padding,
-        }
+        })
     }
 }
 
-#[derive(Debug)]
-pub struct RequestFlags {
-    pub new_order: bool,
-    pub cancel_order: bool,
-    pub bid: bool,
-    pub post_only: bool,
-    pub ioc: bool,
+bitflags! {
+    pub struct RequestFlags: u8 {
+        const NEW_ORDER = 0b0000_0001;
+        const CANCEL_ORDER = 0b0000_0010;
+        const BID = 0b0000_0100;
+        const POST_ONLY = 0b0000_1000;
+        const IOC = 0b0001_0000;
+    }
 }
 
-impl Pack for RequestFlags {
-    const LEN: usize = 1;
-
-    fn unpack_from_slice(src: &[u8]) -> Result<Self, std::io::Error> {
-        if src.len() < Self::LEN {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Input too short"));
-        }
-
-        let request_flags_byte = src[0];
-        let new_order = (request_flags_byte >> 4) & 1 == 1;
-        let cancel_order = (request_flags_byte >> 3) & 1 == 1;
-        let bid = (request_flags_byte >> 2) & 1 == 1;
-        let post_only = (request_flags_byte >> 1) & 1 == 1;
-        let ioc = request_flags_byte & 1 == 1;
-
-        Ok(Self {
-            new_order,
-            cancel_order,
-            bid,
-            post_only,
-            ioc,
-        })
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    NewOrder {
+        side: Side,
+        order_type: OrderType,
+        max_base_size: u64,
+        native_quote_quantity_locked: u64,
+    },
+    CancelOrder {
+        side: Side,
+        cancel_id: u64,
+    },
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Request {
     pub flags: RequestFlags,
+    pub kind: RequestKind,
+    pub seq_num: u32,
     pub open_orders_slot: u8,
     pub fee_tier: u8,
-    pub blob: [u8; 5],
     pub max_base_size_or_cancel_id: u64,
     pub native_quote_quantity_locked: u64,
-    pub order_id: u128,
+    pub order_id: OrderId,
     pub open_orders: Pubkey,
     pub client_order_id: u64,
 }
 
+impl Request {
+    fn decode(data: &[u8], seq_num: u32) -> Result<Self, DecodeError> {
+        let flags = RequestFlags::from_bits_truncate(data[0]);
+        let side = if flags.contains(RequestFlags::BID) {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let max_base_size_or_cancel_id = read_u64(data, 8);
+        let native_quote_quantity_locked = read_u64(data, 16);
+
+        let kind = match (
+            flags.contains(RequestFlags::NEW_ORDER),
+            flags.contains(RequestFlags::CANCEL_ORDER),
+        ) {
+            (true, false) => RequestKind::NewOrder {
+                side,
+                order_type: if flags.contains(RequestFlags::POST_ONLY) {
+                    OrderType::PostOnly
+                } else if flags.contains(RequestFlags::IOC) {
+                    OrderType::Ioc
+                } else {
+                    OrderType::Limit
+                },
+                max_base_size: max_base_size_or_cancel_id,
+                native_quote_quantity_locked,
+            },
+            (false, true) => RequestKind::CancelOrder {
+                side,
+                cancel_id: max_base_size_or_cancel_id,
+            },
+            _ => return Err(DecodeError::InvalidRequestFlags(data[0])),
+        };
+
+        Ok(Request {
+            flags,
+            kind,
+            seq_num,
+            open_orders_slot: data[1],
+            fee_tier: data[2],
+            max_base_size_or_cancel_id,
+            native_quote_quantity_locked,
+            order_id: OrderId(read_u128(data, 24)),
+            open_orders: Pubkey::new_from_array(data[40..72].try_into().unwrap()),
+            client_order_id: read_u64(data, 72),
+        })
+    }
+}
+
+/// Decodes the pending requests of a request queue account in sequence order.
+///
+/// With `history`, returns up to that many of the most recent requests
+/// instead, newest first. Consumed slots are not cleared and may hold
+/// anything, so history skips the ones that do not decode; pending requests
+/// that do not decode fail the whole queue.
+pub fn decode_request_queue(
+    data: &[u8],
+    history: Option<usize>,
+) -> Result<Vec<Request>, DecodeError> {
+    let header = RequestQueueHeader::decode(data)?;
+    let nodes = queue_nodes(data, REQUEST_QUEUE_HEADER_LEN)?;
+    let slots = ring_slots(
+        header.head,
+        header.count,
+        header.next_seq_num,
+        nodes.len() / REQUEST_LEN,
+        history,
+    )?;
+    let requests = slots.map(|(index, seq_num)| {
+        Request::decode(
+            &nodes[index * REQUEST_LEN..(index + 1) * REQUEST_LEN],
+            seq_num,
+        )
+    });
+    if history.is_some() {
+        Ok(requests.filter_map(Result::ok).collect())
+    } else {
+        requests.collect()
+    }
+}
+
+pub const EVENT_QUEUE_HEADER_LEN: usize = QUEUE_HEADER_LEN;
+pub const EVENT_LEN: usize = 88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct EventQueueHeader {
-    blob: [u8; 5],
-    account_flags: AccountFlags,
-    head: u32,
-    count: u32,
-    seq_num: u32,
+    pub account_flags: AccountFlags,
+    pub head: u32,
+    pub count: u32,
+    pub seq_num: u32,
+}
+
+impl EventQueueHeader {
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let (account_flags, head, count, seq_num) = decode_queue_header(data)?;
+        account_flags.expect_event_queue()?;
+        Ok(EventQueueHeader {
+            account_flags,
+            head,
+            count,
+            seq_num,
+        })
+    }
 }
 
 bitflags! {
@@ -109,28 +189,126 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Event {
-    flags: EventFlags,
-    open_orders_slot: u8,
-    fee_tier: u8,
-    blob: [u8; 5],
-    native_quantity_released: u64,
-    native_quantity_paid: u64,
-    native_fee_or_rebate: u64,
-    order_id: u128,
-    open_orders: Pubkey,
-    client_order_id: u64,
+    pub event_flags: EventFlags,
+    pub seq_num: u32,
+    pub open_orders_slot: u8,
+    pub fee_tier: u8,
+    pub native_quantity_released: u64,
+    pub native_quantity_paid: u64,
+    pub native_fee_or_rebate: u64,
+    pub order_id: OrderId,
+    pub open_orders: Pubkey,
+    pub client_order_id: u64,
 }
 
-pub struct Event {
-    event_flags: EventFlags,
-    seq_num: Option<u32>,
-    order_id: u128,
-    open_orders: Pubkey,
-    open_orders_slot: u8,
-    fee_tier: u8,
-    native_quantity_released: u128,
-    native_quantity_paid: u128,
-    native_fee_or_rebate: u128,
+impl Event {
+    fn decode(data: &[u8], seq_num: u32) -> Self {
+        Event {
+            event_flags: EventFlags::from_bits_truncate(data[0]),
+            seq_num,
+            open_orders_slot: data[1],
+            fee_tier: data[2],
+            native_quantity_released: read_u64(data, 8),
+            native_quantity_paid: read_u64(data, 16),
+            native_fee_or_rebate: read_u64(data, 24),
+            order_id: OrderId(read_u128(data, 32)),
+            open_orders: Pubkey::new_from_array(data[48..80].try_into().unwrap()),
+            client_order_id: read_u64(data, 80),
+        }
+    }
+}
+
+/// Decodes the events of an event queue account in sequence order.
+///
+/// With `history`, returns up to that many of the most recent events instead,
+/// newest first, including ones already consumed but not yet overwritten.
+pub fn decode_event_queue(data: &[u8], history: Option<usize>) -> Result<Vec<Event>, DecodeError> {
+    let header = EventQueueHeader::decode(data)?;
+    let nodes = queue_nodes(data, EVENT_QUEUE_HEADER_LEN)?;
+    let slots = ring_slots(
+        header.head,
+        header.count,
+        header.seq_num,
+        nodes.len() / EVENT_LEN,
+        history,
+    )?;
+    Ok(slots
+        .map(|(index, seq_num)| {
+            Event::decode(&nodes[index * EVENT_LEN..(index + 1) * EVENT_LEN], seq_num)
+        })
+        .collect())
+}
+
+/// Both queues share the header layout: head padding, account flags, then
+/// head, count and sequence number as `u32`s each followed by four zero bytes.
+fn decode_queue_header(data: &[u8]) -> Result<(AccountFlags, u32, u32, u32), DecodeError> {
+    if data.len() < QUEUE_HEADER_LEN {
+        return Err(DecodeError::InvalidLength {
+            expected: QUEUE_HEADER_LEN,
+            actual: data.len(),
+        });
+    }
+    if &data[..ACCOUNT_HEAD_PADDING.len()] != ACCOUNT_HEAD_PADDING {
+        return Err(DecodeError::InvalidHeadPadding);
+    }
+
+    Ok((
+        AccountFlags::from_raw(read_u64(data, 5))?,
+        read_u32(data, 13),
+        read_u32(data, 21),
+        read_u32(data, 29),
+    ))
 }
 
+/// The node area between the queue header and the tail padding.
+fn queue_nodes(data: &[u8], header_len: usize) -> Result<&[u8], DecodeError> {
+    let min_len = header_len + ACCOUNT_TAIL_PADDING.len();
+    if data.len() < min_len {
+        return Err(DecodeError::InvalidLength {
+            expected: min_len,
+            actual: data.len(),
+        });
+    }
+    check_account_padding(data)?;
+    Ok(&data[header_len..data.len() - ACCOUNT_TAIL_PADDING.len()])
+}
+
+/// Ring-buffer slot indices paired with sequence numbers, shared by the
+/// request and event queues. `next_seq_num` is the number the next pushed
+/// node will get, so the newest node holds `next_seq_num - 1`. History stops
+/// at the first node ever pushed, as a queue that has not wrapped yet has
+/// never written the slots beyond it.
+fn ring_slots(
+    head: u32,
+    count: u32,
+    next_seq_num: u32,
+    alloc_len: usize,
+    history: Option<usize>,
+) -> Result<impl Iterator<Item = (usize, u32)>, DecodeError> {
+    if count as usize > alloc_len || (alloc_len > 0 && head as usize >= alloc_len) {
+        return Err(DecodeError::QueueOverflow {
+            head,
+            count,
+            capacity: alloc_len,
+        });
+    }
+
+    let head = head as usize;
+    let count = count as usize;
+    let slots: Box<dyn Iterator<Item = (usize, u32)>> = match history {
+        Some(history) => {
+            let written = usize::try_from(next_seq_num).unwrap_or(usize::MAX);
+            Box::new((0..history.min(alloc_len).min(written)).map(move |i| {
+                let index = (head + count + alloc_len - 1 - i) % alloc_len;
+                (index, next_seq_num.wrapping_sub(1 + i as u32))
+            }))
+        }
+        None => Box::new((0..count).map(move |i| {
+            let index = (head + i) % alloc_len;
+            (index, next_seq_num.wrapping_sub((count - i) as u32))
+        })),
+    };
+    Ok(slots)
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use solana_sdk::pubkey::Pubkey;
+
+/// Layout version assumed for programs that are not registered.
+pub const DEFAULT_LAYOUT_VERSION: u8 = 3;
+
+const KNOWN_PROGRAMS: &[(&str, u8)] = &[
+    ("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn", 1),
+    ("BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg", 1),
+    ("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o", 2),
+    ("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX", 3),
+];
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: ProgramRegistry = ProgramRegistry::with_known_programs();
+}
+
+/// A DEX deployment and the features it supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramInfo {
+    pub program_id: Pubkey,
+    pub layout_version: u8,
+    pub supports_srm_fee_discounts: bool,
+    pub supports_referral_fees: bool,
+    pub uses_request_queue: bool,
+}
+
+impl ProgramInfo {
+    /// A program with the features of the known deployments of
+    /// `layout_version`. Forks that differ can set the fields directly.
+    pub fn new(program_id: Pubkey, layout_version: u8) -> Self {
+        ProgramInfo {
+            program_id,
+            layout_version,
+            supports_srm_fee_discounts: layout_version > 1,
+            supports_referral_fees: layout_version > 1,
+            uses_request_queue: layout_version <= 2,
+        }
+    }
+}
+
+/// The DEX programs the SDK recognizes. The global registry starts with the
+/// known mainnet deployments; forks such as devnet deployments are added with
+/// `register`.
+#[derive(Debug, Default)]
+pub struct ProgramRegistry {
+    programs: RwLock<HashMap<Pubkey, ProgramInfo>>,
+}
+
+impl ProgramRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_known_programs() -> Self {
+        let registry = Self::new();
+        for (program_id, layout_version) in KNOWN_PROGRAMS {
+            let program_id = Pubkey::from_str(program_id).unwrap();
+            registry.register(ProgramInfo::new(program_id, *layout_version));
+        }
+        registry
+    }
+
+    /// The registry used by the decoders and market helpers.
+    pub fn global() -> &'static ProgramRegistry {
+        &GLOBAL
+    }
+
+    /// Adds `info`, replacing any entry for the same program.
+    pub fn register(&self, info: ProgramInfo) {
+        self.programs.write().unwrap().insert(info.program_id, info);
+    }
+
+    pub fn get(&self, program_id: &Pubkey) -> Option<ProgramInfo> {
+        self.programs.read().unwrap().get(program_id).copied()
+    }
+
+    pub fn is_known(&self, program_id: &Pubkey) -> bool {
+        self.programs.read().unwrap().contains_key(program_id)
+    }
+
+    /// The registered entry, or the features of `DEFAULT_LAYOUT_VERSION` for
+    /// unregistered programs.
+    pub fn info(&self, program_id: &Pubkey) -> ProgramInfo {
+        self.get(program_id)
+            .unwrap_or_else(|| ProgramInfo::new(*program_id, DEFAULT_LAYOUT_VERSION))
+    }
+
+    pub fn layout_version(&self, program_id: &Pubkey) -> u8 {
+        self.info(program_id).layout_version
+    }
+
+    pub fn supports_srm_fee_discounts(&self, program_id: &Pubkey) -> bool {
+        self.info(program_id).supports_srm_fee_discounts
+    }
+
+    pub fn supports_referral_fees(&self, program_id: &Pubkey) -> bool {
+        self.info(program_id).supports_referral_fees
+    }
+
+    pub fn uses_request_queue(&self, program_id: &Pubkey) -> bool {
+        self.info(program_id).uses_request_queue
+    }
+
+    pub fn program_ids(&self) -> Vec<Pubkey> {
+        self.programs.read().unwrap().keys().copied().collect()
+    }
+}
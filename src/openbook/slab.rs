@@ -1,183 +1,531 @@
+use solana_sdk::pubkey::Pubkey;
 
-use solana_sdk::{
-    pubkey::Pubkey,
-    commitment_config::CommitmentConfig,
-    program_pack::Pack,
-    account::Account,
-    program_error::ProgramError,
-    account_info::AccountInfo,
-    system_instruction,
+use std::convert::TryInto;
+
+use super::error::{DecodeError, EncodeError};
+use super::market::{
+    check_account_padding, AccountFlags, ACCOUNT_HEAD_PADDING, ACCOUNT_TAIL_PADDING,
 };
+use super::order::Side;
+use super::order_id::OrderId;
+use super::units::PriceLots;
+
+pub const SLAB_HEADER_LEN: usize = 32;
+pub const SLAB_NODE_LEN: usize = 72;
 
-use std::slice::Iter;
-use std::io::Error;
+const ACCOUNT_FLAGS_LEN: usize = 8;
 
-#[derive(Debug)]
-struct Slab {
-    header: Header,
-    nodes: Vec<SlabNode>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slab {
+    pub header: Header,
+    pub nodes: Vec<SlabNode>,
 }
 
-#[derive(Debug)]
-struct Header {
-    bump_index: u32,
-    free_list_len: u32,
-    free_list_head: u32,
-    root: u32,
-    leaf_count: u32,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub bump_index: u32,
+    pub free_list_len: u32,
+    pub free_list_head: u32,
+    pub root: u32,
+    pub leaf_count: u32,
 }
 
-#[derive(Debug)]
-enum SlabNode {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlabNode {
     Uninitialized,
     InnerNode {
         prefix_len: u32,
         key: u128,
         children: [u32; 2],
     },
-    LeafNode {
-        owner_slot: u8,
-        fee_tier: u8,
-        key: u128,
-        owner: PublicKey,
-        quantity: u64,
-        client_order_id: u64,
-    },
+    LeafNode(LeafNode),
     FreeNode {
         next: u32,
     },
-    LastFreeNode
-}
-
-let slab_header_layout = struct![
-    u32, 
-    zeros(4),
-    u32, 
-    zeros(4),
-    u32, 
-    u32, 
-    u32,
-];
-
-let slab_node_layout = union![
-    u32, 
-    blob(68), 
-    |tag| match tag {
-        0 => struct![],
-        1 => struct![
-            u32; 
-            u128; 
-            seq(u32, 2); 
-        ],
-        2 => struct![
-            u8, 
-            u8, 
-            blob(2),
-            u128, 
-            public_key_layout,
-            u64, 
-            u64, 
-        ],
-        3 => struct![
-            u32; 
-        ],
-        4 => struct![],
-    }
-];
-
-let slab_layout = struct![
-    slab_header_layout,
-    seq(slab_node_layout, offset(|v| v.0.bumpIndex, |v| v.0.bumpIndex - v.0.layout_size()), "nodes")
-];
+    LastFreeNode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafNode {
+    pub owner_slot: u8,
+    pub fee_tier: u8,
+    pub key: u128,
+    pub owner: Pubkey,
+    pub quantity: u64,
+    pub client_order_id: u64,
+}
+
+impl LeafNode {
+    pub fn order_id(&self) -> OrderId {
+        OrderId(self.key)
+    }
+}
 
 impl Slab {
-    fn get(&self, search_key: &u128) -> Option<&SlabNode> {
+    /// Decodes a whole bids or asks account: head padding, account flags,
+    /// the slab and the tail padding.
+    pub fn decode_account(data: &[u8]) -> Result<(AccountFlags, Slab), DecodeError> {
+        let min_len = ACCOUNT_HEAD_PADDING.len()
+            + ACCOUNT_FLAGS_LEN
+            + SLAB_HEADER_LEN
+            + ACCOUNT_TAIL_PADDING.len();
+        if data.len() < min_len {
+            return Err(DecodeError::InvalidLength {
+                expected: min_len,
+                actual: data.len(),
+            });
+        }
+        check_account_padding(data)?;
+
+        let flags_offset = ACCOUNT_HEAD_PADDING.len();
+        let slab_offset = flags_offset + ACCOUNT_FLAGS_LEN;
+        let account_flags = AccountFlags::from_raw(read_u64(data, flags_offset))?;
+        account_flags.expect_orderbook()?;
+        let slab = Slab::decode(&data[slab_offset..data.len() - ACCOUNT_TAIL_PADDING.len()])
+            .map_err(|err| err.at_offset(slab_offset))?;
+
+        Ok((account_flags, slab))
+    }
+
+    /// Decodes the slab header and the `bump_index` nodes that follow it.
+    pub fn decode(data: &[u8]) -> Result<Slab, DecodeError> {
+        if data.len() < SLAB_HEADER_LEN {
+            return Err(DecodeError::InvalidLength {
+                expected: SLAB_HEADER_LEN,
+                actual: data.len(),
+            });
+        }
+
+        let header = Header {
+            bump_index: read_u32(data, 0),
+            free_list_len: read_u32(data, 8),
+            free_list_head: read_u32(data, 16),
+            root: read_u32(data, 20),
+            leaf_count: read_u32(data, 24),
+        };
+
+        let capacity = (data.len() - SLAB_HEADER_LEN) / SLAB_NODE_LEN;
+        if header.bump_index as usize > capacity {
+            return Err(DecodeError::SlabOverflow {
+                bump_index: header.bump_index,
+                capacity,
+            });
+        }
+
+        let nodes = data[SLAB_HEADER_LEN..]
+            .chunks_exact(SLAB_NODE_LEN)
+            .take(header.bump_index as usize)
+            .enumerate()
+            .map(|(index, node)| {
+                SlabNode::decode(node)
+                    .map_err(|err| err.at_offset(SLAB_HEADER_LEN + index * SLAB_NODE_LEN))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Slab { header, nodes })
+    }
+
+    /// Looks up the leaf with exactly `search_key` by walking the critbit tree.
+    pub fn get(&self, search_key: u128) -> Option<&LeafNode> {
         if self.header.leaf_count == 0 {
             return None;
         }
-        
-        let mut search_key_bn = search_key.clone();
-        if let SlabNode::LeafNode { key, .. } = &self.nodes[self.header.root] {
-            if let Some(leaf_node) = key.as_ref() == Some(&search_key_bn) {
-                return Some(&leaf_node);
-            } else {
-                return None;
-            }
-        }
-        
+
         let mut index = self.header.root;
-        loop {
-            if let SlabNode::LeafNode { key, .. } = &self.nodes[index] {
-                if key.as_ref() == Some(&search_key_bn) {
-                    return Some(&leaf_node);
-                } else {
-                    return None;
+        for _ in 0..self.nodes.len() {
+            match self.node(index)? {
+                SlabNode::LeafNode(leaf) => {
+                    return if leaf.key == search_key {
+                        Some(leaf)
+                    } else {
+                        None
+                    };
                 }
-            } else if let SlabNode::InnerNode { prefix_len, key, children } = &self.nodes[index] {
-                if !key.bitwise_xor(&search_key_bn).shift_right(128 - prefix_len).is_zero() {
-                    return None;
+                SlabNode::InnerNode {
+                    prefix_len,
+                    key,
+                    children,
+                } => {
+                    let prefix_len = *prefix_len;
+                    if prefix_len >= 128 {
+                        return None;
+                    }
+                    let common_prefix = (key ^ search_key)
+                        .checked_shr(128 - prefix_len)
+                        .unwrap_or(0);
+                    if common_prefix != 0 {
+                        return None;
+                    }
+                    let critbit = (search_key >> (127 - prefix_len)) & 1;
+                    index = children[critbit as usize];
                 }
-                index = children[if search_key.testn(128 - prefix_len - 1) {1} else {0}];
-            } else {
-                panic!("Invalid slab");
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Leaf with the lowest key, i.e. the best ask.
+    pub fn find_min(&self) -> Option<&LeafNode> {
+        self.find_edge(0)
+    }
+
+    /// Leaf with the highest key, i.e. the best bid.
+    pub fn find_max(&self) -> Option<&LeafNode> {
+        self.find_edge(1)
+    }
+
+    fn find_edge(&self, side: usize) -> Option<&LeafNode> {
+        if self.header.leaf_count == 0 {
+            return None;
+        }
+
+        let mut index = self.header.root;
+        for _ in 0..self.nodes.len() {
+            match self.node(index)? {
+                SlabNode::LeafNode(leaf) => return Some(leaf),
+                SlabNode::InnerNode { children, .. } => index = children[side],
+                _ => return None,
             }
         }
+        None
     }
 
-    pub fn iter(&self) -> Iter<'_, SlabNode> {
+    fn node(&self, index: u32) -> Option<&SlabNode> {
+        self.nodes.get(index as usize)
+    }
+
+    pub fn iter(&self) -> LeafIter<'_> {
         self.items(false)
     }
 
-    fn items(&self, descending: bool) -> impl Iterator<Item = &SlabNode> {
-        let stack: Vec<u32> = vec![self.header.root];
-        let nodes = &self.nodes;
+    /// Lazily walks the leaves in key order, highest key first when `descending`.
+    pub fn items(&self, descending: bool) -> LeafIter<'_> {
+        let mut iter = LeafIter {
+            slab: self,
+            stack: [0; MAX_TREE_DEPTH],
+            len: 0,
+            descending,
+        };
+        if self.header.leaf_count > 0 {
+            iter.push(self.header.root);
+        }
+        iter
+    }
+}
+
+/// A critbit path has at most one inner node per prefix length plus the leaf,
+/// which bounds the pending-sibling stack of an in-order walk.
+const MAX_TREE_DEPTH: usize = 129;
+
+pub struct LeafIter<'a> {
+    slab: &'a Slab,
+    stack: [u32; MAX_TREE_DEPTH],
+    len: usize,
+    descending: bool,
+}
 
-        std::iter::from_fn(move || {
-            if let Some(index) = stack.last().copied() {
-                let node = &nodes[index as usize];
-                stack.pop();
+impl<'a> LeafIter<'a> {
+    fn push(&mut self, index: u32) -> bool {
+        if self.len == MAX_TREE_DEPTH {
+            return false;
+        }
+        self.stack[self.len] = index;
+        self.len += 1;
+        true
+    }
+}
 
-                if let SlabNode::LeafNode { .. } = node {
-                    return Some(node);
-                } else if let SlabNode::InnerNode { children, .. } = node {
-                    if descending {
-                        stack.push(children[0]);
-                        stack.push(children[1]);
+impl<'a> Iterator for LeafIter<'a> {
+    type Item = &'a LeafNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.len > 0 {
+            self.len -= 1;
+            let slab = self.slab;
+            match slab.node(self.stack[self.len]) {
+                Some(SlabNode::LeafNode(leaf)) => return Some(leaf),
+                Some(SlabNode::InnerNode { children, .. }) => {
+                    let (first, second) = if self.descending {
+                        (children[1], children[0])
                     } else {
-                        stack.push(children[1]);
-                        stack.push(children[0]);
+                        (children[0], children[1])
+                    };
+                    if !self.push(second) || !self.push(first) {
+                        // Deeper than any valid critbit tree, so the slab is corrupt.
+                        self.len = 0;
                     }
                 }
+                _ => {}
             }
+        }
+        None
+    }
+}
 
-            None
-        }).filter_map(std::convert::identity)
+impl Header {
+    fn encode(&self, dst: &mut [u8]) {
+        dst[..SLAB_HEADER_LEN].fill(0);
+        write_u32(dst, 0, self.bump_index);
+        write_u32(dst, 8, self.free_list_len);
+        write_u32(dst, 16, self.free_list_head);
+        write_u32(dst, 20, self.root);
+        write_u32(dst, 24, self.leaf_count);
     }
 }
 
-export function setLayoutDecoder(layout, decoder) {
-    const originalDecode = layout.decode;
-    layout.decode = function decode(b, offset = 0) {
-      return decoder(originalDecode.call(this, b, offset));
-    };
+/// One resting order to place in a synthetic bids or asks account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlabOrder {
+    pub price_lots: u64,
+    pub seq_num: u64,
+    pub owner: Pubkey,
+    pub owner_slot: u8,
+    pub fee_tier: u8,
+    pub quantity: u64,
+    pub client_order_id: u64,
 }
-  
-export function setLayoutEncoder(layout, encoder) {
-    const originalEncode = layout.encode;
-    layout.encode = function encode(src, b, offset) {
-        return originalEncode.call(this, encoder(src), b, offset);
-    };
-    return layout;
+
+/// Builds a byte-exact bids or asks account from a list of orders, mainly
+/// for tests and simulations.
+#[derive(Debug, Clone)]
+pub struct SlabBuilder {
+    is_bids: bool,
+    orders: Vec<SlabOrder>,
+    free_nodes: u32,
+    capacity: Option<usize>,
 }
 
-setLayoutDecoder(SLAB_LAYOUT, |data| {
-    let header = Header {
-        bump_index: data[0],
-        free_list_len: data[2],
-        free_list_head: data[5],
-        root: data[6],
-        leaf_count: data[8],
-    };
+impl SlabBuilder {
+    pub fn new(is_bids: bool) -> Self {
+        SlabBuilder {
+            is_bids,
+            orders: Vec::new(),
+            free_nodes: 0,
+            capacity: None,
+        }
+    }
+
+    pub fn order(mut self, order: SlabOrder) -> Self {
+        self.orders.push(order);
+        self
+    }
+
+    pub fn orders(mut self, orders: impl IntoIterator<Item = SlabOrder>) -> Self {
+        self.orders.extend(orders);
+        self
+    }
+
+    /// Appends `count` nodes to the free list after the tree nodes.
+    pub fn free_nodes(mut self, count: u32) -> Self {
+        self.free_nodes = count;
+        self
+    }
+
+    /// Total node slots in the account; defaults to exactly the nodes in use.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Builds the slab header and nodes, without the account framing.
+    pub fn build_slab(&self) -> Result<Slab, EncodeError> {
+        let side = if self.is_bids { Side::Buy } else { Side::Sell };
+        let mut leaves: Vec<LeafNode> = self
+            .orders
+            .iter()
+            .map(|order| LeafNode {
+                owner_slot: order.owner_slot,
+                fee_tier: order.fee_tier,
+                key: OrderId::new(side, PriceLots::new(order.price_lots), order.seq_num).get(),
+                owner: order.owner,
+                quantity: order.quantity,
+                client_order_id: order.client_order_id,
+            })
+            .collect();
+        leaves.sort_by_key(|leaf| leaf.key);
+        if let Some(pair) = leaves.windows(2).find(|pair| pair[0].key == pair[1].key) {
+            return Err(EncodeError::DuplicateKey(pair[0].key));
+        }
+
+        let mut nodes = Vec::with_capacity(2 * leaves.len() + self.free_nodes as usize);
+        if !leaves.is_empty() {
+            push_subtree(&mut nodes, &leaves);
+        }
+
+        let tree_len = nodes.len() as u32;
+        for index in tree_len..tree_len + self.free_nodes {
+            nodes.push(if index == tree_len {
+                SlabNode::LastFreeNode
+            } else {
+                SlabNode::FreeNode { next: index - 1 }
+            });
+        }
+
+        let header = Header {
+            bump_index: nodes.len() as u32,
+            free_list_len: self.free_nodes,
+            free_list_head: if self.free_nodes > 0 {
+                nodes.len() as u32 - 1
+            } else {
+                0
+            },
+            root: 0,
+            leaf_count: leaves.len() as u32,
+        };
+
+        Ok(Slab { header, nodes })
+    }
+
+    /// Builds the full account data: head padding, account flags, slab and
+    /// tail padding.
+    pub fn build(&self) -> Result<Vec<u8>, EncodeError> {
+        let slab = self.build_slab()?;
+        let capacity = self.capacity.unwrap_or(slab.nodes.len());
+        if capacity < slab.nodes.len() {
+            return Err(EncodeError::CapacityExceeded {
+                required: slab.nodes.len(),
+                capacity,
+            });
+        }
+
+        let side = if self.is_bids {
+            AccountFlags::BIDS
+        } else {
+            AccountFlags::ASKS
+        };
+        let account_flags = AccountFlags::INITIALIZED | side;
+
+        let slab_offset = ACCOUNT_HEAD_PADDING.len() + ACCOUNT_FLAGS_LEN;
+        let nodes_offset = slab_offset + SLAB_HEADER_LEN;
+        let mut data =
+            vec![0u8; nodes_offset + capacity * SLAB_NODE_LEN + ACCOUNT_TAIL_PADDING.len()];
+
+        data[..ACCOUNT_HEAD_PADDING.len()].copy_from_slice(ACCOUNT_HEAD_PADDING);
+        data[ACCOUNT_HEAD_PADDING.len()..slab_offset]
+            .copy_from_slice(&account_flags.bits().to_le_bytes());
+        slab.header.encode(&mut data[slab_offset..nodes_offset]);
+        for (node, dst) in slab
+            .nodes
+            .iter()
+            .zip(data[nodes_offset..].chunks_exact_mut(SLAB_NODE_LEN))
+        {
+            node.encode(dst);
+        }
+        let tail_offset = data.len() - ACCOUNT_TAIL_PADDING.len();
+        data[tail_offset..].copy_from_slice(ACCOUNT_TAIL_PADDING);
 
-    Slab { header, nodes }
-});
\ No newline at end of file
+        Ok(data)
+    }
+}
+
+/// Appends the critbit subtree over `leaves` (sorted, distinct keys) in
+/// pre-order and returns the index of its root.
+fn push_subtree(nodes: &mut Vec<SlabNode>, leaves: &[LeafNode]) -> u32 {
+    let index = nodes.len() as u32;
+    let first = leaves[0].key;
+    let last = leaves[leaves.len() - 1].key;
+    if leaves.len() == 1 {
+        nodes.push(SlabNode::LeafNode(leaves[0].clone()));
+        return index;
+    }
+
+    let prefix_len = (first ^ last).leading_zeros();
+    let critbit_mask = 1u128 << (127 - prefix_len);
+    let split = leaves.partition_point(|leaf| leaf.key & critbit_mask == 0);
+
+    nodes.push(SlabNode::InnerNode {
+        prefix_len,
+        key: first,
+        children: [0, 0],
+    });
+    let left = push_subtree(nodes, &leaves[..split]);
+    let right = push_subtree(nodes, &leaves[split..]);
+    if let SlabNode::InnerNode { children, .. } = &mut nodes[index as usize] {
+        *children = [left, right];
+    }
+    index
+}
+
+impl SlabNode {
+    /// Encodes the node into a 72-byte slot, the inverse of [`SlabNode::decode`].
+    pub fn encode(&self, dst: &mut [u8]) {
+        dst[..SLAB_NODE_LEN].fill(0);
+        match self {
+            SlabNode::Uninitialized => {}
+            SlabNode::InnerNode {
+                prefix_len,
+                key,
+                children,
+            } => {
+                write_u32(dst, 0, 1);
+                write_u32(dst, 4, *prefix_len);
+                dst[8..24].copy_from_slice(&key.to_le_bytes());
+                write_u32(dst, 24, children[0]);
+                write_u32(dst, 28, children[1]);
+            }
+            SlabNode::LeafNode(leaf) => {
+                write_u32(dst, 0, 2);
+                dst[4] = leaf.owner_slot;
+                dst[5] = leaf.fee_tier;
+                dst[8..24].copy_from_slice(&leaf.key.to_le_bytes());
+                dst[24..56].copy_from_slice(&leaf.owner.to_bytes());
+                dst[56..64].copy_from_slice(&leaf.quantity.to_le_bytes());
+                dst[64..72].copy_from_slice(&leaf.client_order_id.to_le_bytes());
+            }
+            SlabNode::FreeNode { next } => {
+                write_u32(dst, 0, 3);
+                write_u32(dst, 4, *next);
+            }
+            SlabNode::LastFreeNode => write_u32(dst, 0, 4),
+        }
+    }
+
+    /// Decodes a single 72-byte node: a `u32` tag followed by the variant body.
+    pub fn decode(data: &[u8]) -> Result<SlabNode, DecodeError> {
+        if data.len() != SLAB_NODE_LEN {
+            return Err(DecodeError::InvalidLength {
+                expected: SLAB_NODE_LEN,
+                actual: data.len(),
+            });
+        }
+
+        match read_u32(data, 0) {
+            0 => Ok(SlabNode::Uninitialized),
+            1 => Ok(SlabNode::InnerNode {
+                prefix_len: read_u32(data, 4),
+                key: read_u128(data, 8),
+                children: [read_u32(data, 24), read_u32(data, 28)],
+            }),
+            2 => Ok(SlabNode::LeafNode(LeafNode {
+                owner_slot: data[4],
+                fee_tier: data[5],
+                key: read_u128(data, 8),
+                owner: Pubkey::new_from_array(data[24..56].try_into().unwrap()),
+                quantity: read_u64(data, 56),
+                client_order_id: read_u64(data, 64),
+            })),
+            3 => Ok(SlabNode::FreeNode {
+                next: read_u32(data, 4),
+            }),
+            4 => Ok(SlabNode::LastFreeNode),
+            tag => Err(DecodeError::InvalidNodeTag { tag, offset: 0 }),
+        }
+    }
+}
+
+fn write_u32(dst: &mut [u8], offset: usize, value: u32) {
+    dst[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+pub(crate) fn read_u128(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
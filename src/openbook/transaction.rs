@@ -0,0 +1,323 @@
+use solana_sdk::{
+    message::{v0::LoadedAddresses, VersionedMessage},
+    pubkey::Pubkey,
+    transaction::VersionedTransaction,
+};
+
+use super::error::DecodeError;
+use super::instruction::Instruction;
+use super::registry::ProgramRegistry;
+
+/// What an account is to the DEX instruction that lists it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountRole {
+    Market,
+    OpenOrders,
+    RequestQueue,
+    EventQueue,
+    Bids,
+    Asks,
+    BaseVault,
+    QuoteVault,
+    BaseMint,
+    QuoteMint,
+    Owner,
+    Payer,
+    BaseWallet,
+    QuoteWallet,
+    VaultSigner,
+    BaseFeeReceivable,
+    QuoteFeeReceivable,
+    ReferrerQuoteWallet,
+    FeeDiscount,
+    Destination,
+    Authority,
+    PruneAuthority,
+    CrankAuthority,
+    MarketAuthority,
+    OpenOrdersOwner,
+    TokenProgram,
+    Rent,
+    /// Accounts past the ones the instruction reads.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabeledAccount {
+    pub role: AccountRole,
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// The data of a DEX instruction found in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexInstruction {
+    Known(Instruction),
+    /// Data the SDK does not decode: instructions it does not build, such as
+    /// DisableMarket or SweepFees, tags added after it, or malformed data.
+    /// `tag` is `None` when the data is too short to hold one.
+    Unknown {
+        tag: Option<u32>,
+        data: Vec<u8>,
+    },
+}
+
+impl DexInstruction {
+    fn unpack(data: &[u8]) -> Self {
+        match Instruction::unpack_from_slice(data) {
+            Ok(instruction) => DexInstruction::Known(instruction),
+            Err(_) => DexInstruction::Unknown {
+                tag: data
+                    .get(1..5)
+                    .map(|tag| u32::from_le_bytes(tag.try_into().unwrap())),
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    pub fn known(&self) -> Option<&Instruction> {
+        match self {
+            DexInstruction::Known(instruction) => Some(instruction),
+            DexInstruction::Unknown { .. } => None,
+        }
+    }
+}
+
+/// A DEX instruction found in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// Position among the transaction's top-level instructions.
+    pub index: usize,
+    pub program_id: Pubkey,
+    pub instruction: DexInstruction,
+    pub accounts: Vec<LabeledAccount>,
+}
+
+impl DecodedInstruction {
+    /// The first account holding `role`.
+    pub fn account(&self, role: AccountRole) -> Option<&Pubkey> {
+        self.accounts
+            .iter()
+            .find(|account| account.role == role)
+            .map(|account| &account.pubkey)
+    }
+}
+
+/// Decodes every top-level instruction of `transaction` addressed to a known
+/// DEX program. Version 0 transactions that use address lookup tables need
+/// the `loaded_addresses` resolved for them, as found in the transaction
+/// status meta. Instructions reached through CPI are not included, and ones
+/// whose data does not decode are kept as `DexInstruction::Unknown`.
+pub fn decode_transaction(
+    transaction: &VersionedTransaction,
+    loaded_addresses: Option<&LoadedAddresses>,
+) -> Result<Vec<DecodedInstruction>, DecodeError> {
+    decode_message(&transaction.message, loaded_addresses)
+}
+
+pub fn decode_message(
+    message: &VersionedMessage,
+    loaded_addresses: Option<&LoadedAddresses>,
+) -> Result<Vec<DecodedInstruction>, DecodeError> {
+    let looked_up = message
+        .address_table_lookups()
+        .unwrap_or_default()
+        .iter()
+        .map(|lookup| lookup.writable_indexes.len() + lookup.readonly_indexes.len())
+        .sum::<usize>();
+    let loaded = loaded_addresses.map_or(0, LoadedAddresses::len);
+    if looked_up != loaded {
+        return Err(DecodeError::LoadedAddressesMismatch {
+            expected: looked_up,
+            actual: loaded,
+        });
+    }
+
+    // Static keys first, then the writable and readonly loaded keys, the
+    // order compiled instructions index into.
+    let account_keys: Vec<Pubkey> = message
+        .static_account_keys()
+        .iter()
+        .chain(
+            loaded_addresses
+                .into_iter()
+                .flat_map(|loaded| loaded.writable.iter().chain(loaded.readonly.iter())),
+        )
+        .copied()
+        .collect();
+    let key = |index: u8| {
+        account_keys
+            .get(index as usize)
+            .copied()
+            .ok_or(DecodeError::AccountIndexOutOfRange {
+                index,
+                len: account_keys.len(),
+            })
+    };
+
+    let mut decoded = Vec::new();
+    for (index, compiled) in message.instructions().iter().enumerate() {
+        let program_id = key(compiled.program_id_index)?;
+        if !ProgramRegistry::global().is_known(&program_id) {
+            continue;
+        }
+        let instruction = DexInstruction::unpack(&compiled.data);
+        let roles = match instruction.known() {
+            Some(known) => account_roles(known, compiled.accounts.len()),
+            None => vec![AccountRole::Unknown; compiled.accounts.len()],
+        };
+        let accounts = compiled
+            .accounts
+            .iter()
+            .zip(roles)
+            .map(|(&account_index, role)| {
+                Ok(LabeledAccount {
+                    role,
+                    pubkey: key(account_index)?,
+                    is_signer: message.is_signer(account_index as usize),
+                    is_writable: message.is_maybe_writable(account_index as usize),
+                })
+            })
+            .collect::<Result<Vec<_>, DecodeError>>()?;
+        decoded.push(DecodedInstruction {
+            index,
+            program_id,
+            instruction,
+            accounts,
+        });
+    }
+    Ok(decoded)
+}
+
+/// Roles of the `len` accounts passed to `instruction`, in the order
+/// `DexInstructions` builds them. Optional trailing accounts are labeled when
+/// present and anything past them is `Unknown`.
+pub fn account_roles(instruction: &Instruction, len: usize) -> Vec<AccountRole> {
+    use AccountRole::*;
+
+    let roles: &[AccountRole] = match instruction {
+        Instruction::ConsumeEvents(_) => {
+            return crank_roles(
+                &[Market, EventQueue, BaseFeeReceivable, QuoteFeeReceivable],
+                len,
+            );
+        }
+        Instruction::ConsumeEventsPermissioned(_) => {
+            return crank_roles(&[Market, EventQueue, CrankAuthority], len);
+        }
+        Instruction::InitializeMarket(_) => &[
+            Market,
+            RequestQueue,
+            EventQueue,
+            Bids,
+            Asks,
+            BaseVault,
+            QuoteVault,
+            BaseMint,
+            QuoteMint,
+            Rent,
+            Authority,
+            PruneAuthority,
+            CrankAuthority,
+        ],
+        Instruction::NewOrder(_) => &[
+            Market,
+            OpenOrders,
+            RequestQueue,
+            Payer,
+            Owner,
+            BaseVault,
+            QuoteVault,
+            TokenProgram,
+            Rent,
+            FeeDiscount,
+        ],
+        Instruction::MatchOrders(_) => &[
+            Market,
+            RequestQueue,
+            EventQueue,
+            Bids,
+            Asks,
+            BaseFeeReceivable,
+            QuoteFeeReceivable,
+        ],
+        Instruction::CancelOrder(_) | Instruction::CancelOrderByClientId(_) => {
+            &[Market, OpenOrders, RequestQueue, Owner]
+        }
+        Instruction::SettleFunds(_) => &[
+            Market,
+            OpenOrders,
+            Owner,
+            BaseVault,
+            QuoteVault,
+            BaseWallet,
+            QuoteWallet,
+            VaultSigner,
+            TokenProgram,
+            ReferrerQuoteWallet,
+        ],
+        Instruction::NewOrderV3(_)
+        | Instruction::ReplaceOrderByClientId(_)
+        | Instruction::ReplaceOrdersByClientIds(_) => &[
+            Market,
+            OpenOrders,
+            RequestQueue,
+            EventQueue,
+            Bids,
+            Asks,
+            Payer,
+            Owner,
+            BaseVault,
+            QuoteVault,
+            TokenProgram,
+            Rent,
+            FeeDiscount,
+        ],
+        Instruction::CancelOrderV2(_)
+        | Instruction::CancelOrderByClientIdV2(_)
+        | Instruction::CancelOrdersByClientIds(_) => {
+            &[Market, Bids, Asks, OpenOrders, Owner, EventQueue]
+        }
+        Instruction::SendTake(_) => &[
+            Market,
+            RequestQueue,
+            EventQueue,
+            Bids,
+            Asks,
+            BaseWallet,
+            QuoteWallet,
+            Owner,
+            BaseVault,
+            QuoteVault,
+            TokenProgram,
+            VaultSigner,
+            FeeDiscount,
+        ],
+        Instruction::CloseOpenOrders(_) => &[OpenOrders, Owner, Destination, Market],
+        Instruction::InitOpenOrders(_) => &[OpenOrders, Owner, Market, Rent, MarketAuthority],
+        Instruction::Prune(_) => &[
+            Market,
+            Bids,
+            Asks,
+            PruneAuthority,
+            OpenOrders,
+            OpenOrdersOwner,
+            EventQueue,
+        ],
+    };
+    roles
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(AccountRole::Unknown))
+        .take(len)
+        .collect()
+}
+
+/// The crank instructions take any number of open orders accounts ahead of
+/// their fixed accounts.
+fn crank_roles(fixed: &[AccountRole], len: usize) -> Vec<AccountRole> {
+    let mut roles = vec![AccountRole::OpenOrders; len.saturating_sub(fixed.len())];
+    roles.extend(fixed.iter().copied().take(len - roles.len()));
+    roles
+}
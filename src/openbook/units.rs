@@ -0,0 +1,98 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+
+/// Defines a `u64` quantity in one unit. Quantities of the same unit can be
+/// added and compared; converting between units goes through
+/// `LotConversion`, which knows the market's lot sizes and decimals.
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(u64);
+
+        impl $name {
+            pub const ZERO: Self = $name(0);
+
+            pub const fn new(value: u64) -> Self {
+                $name(value)
+            }
+
+            pub fn get(self) -> u64 {
+                self.0
+            }
+
+            pub fn is_zero(self) -> bool {
+                self.0 == 0
+            }
+
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map($name)
+            }
+
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map($name)
+            }
+
+            pub fn saturating_sub(self, other: Self) -> Self {
+                $name(self.0.saturating_sub(other.0))
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: Self) {
+                self.0 += other.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold($name::ZERO, Add::add)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+unit!(
+    /// A price in quote lots per base lot, as stored in order ids and sent as
+    /// `limit_price`.
+    PriceLots
+);
+unit!(
+    /// A base quantity in multiples of the market's `base_lot_size`.
+    BaseLots
+);
+unit!(
+    /// A quote quantity in multiples of the market's `quote_lot_size`.
+    QuoteLots
+);
+unit!(
+    /// A base amount in the smallest unit of the base mint.
+    NativeBase
+);
+unit!(
+    /// A quote amount in the smallest unit of the quote mint.
+    NativeQuote
+);
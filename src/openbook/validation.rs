@@ -0,0 +1,174 @@
+use rust_decimal::Decimal;
+
+use super::conversion::{LotConversion, Rounding};
+use super::error::SdkError;
+use super::fees::get_fee_rates;
+use super::order::Side;
+use super::units::{BaseLots, NativeQuote, PriceLots};
+
+/// Fee rates are applied in parts per million. The DEX's rates are whole
+/// hundredths of a basis point, so none of them is rounded.
+const FEE_RATE_SCALE: u64 = 1_000_000;
+
+/// How a price or size that is not a whole number of ticks or lots is
+/// brought onto one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    /// Never beyond what the user asked for: bid prices round down, ask
+    /// prices up and sizes down.
+    Conservative,
+    Round(Rounding),
+    /// Rejects values that are not already on a tick or lot.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundingPolicy {
+    pub price: Snap,
+    pub size: Snap,
+}
+
+impl Default for RoundingPolicy {
+    fn default() -> Self {
+        RoundingPolicy {
+            price: Snap::Conservative,
+            size: Snap::Conservative,
+        }
+    }
+}
+
+/// An order on the market's ticks and lots, with the quote it may lock up.
+/// Its fields are what `NewOrderV3Params` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatedOrder {
+    pub side: Side,
+    pub limit_price: PriceLots,
+    pub max_base_quantity: BaseLots,
+    /// Native quote for the whole size at the limit price, plus the taker
+    /// fee, so a bid that crosses is not cut short by the fee.
+    pub max_quote_quantity: NativeQuote,
+    /// The snapped limit price, in quote tokens per base token.
+    pub price: Decimal,
+    /// The snapped size, in base tokens.
+    pub size: Decimal,
+}
+
+/// Checks order prices and sizes against a market's tick and lot sizes
+/// before anything is sent, so orders the program would reject or truncate
+/// fail early.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderValidator {
+    conversion: LotConversion,
+    taker_fee: u64,
+    policy: RoundingPolicy,
+}
+
+impl OrderValidator {
+    /// A validator charging the taker fee of `fee_tier`, as returned by
+    /// `get_fee_tier`. Tier 0 has the highest fee, so it is safe to use when
+    /// the owner's tier is not known yet.
+    pub fn new(conversion: LotConversion, fee_tier: u8) -> Self {
+        let (taker_fee, _) = get_fee_rates(fee_tier);
+        OrderValidator {
+            conversion,
+            taker_fee: (taker_fee * FEE_RATE_SCALE as f64).round() as u64,
+            policy: RoundingPolicy::default(),
+        }
+    }
+
+    pub fn with_policy(self, policy: RoundingPolicy) -> Self {
+        OrderValidator { policy, ..self }
+    }
+
+    pub fn validate(
+        &self,
+        side: Side,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<ValidatedOrder, SdkError> {
+        let conversion = &self.conversion;
+        let limit_price = PriceLots::new(snap(
+            self.policy.price,
+            Rounding::for_limit_price(side),
+            |rounding| {
+                conversion
+                    .price_number_to_lots(price, rounding)
+                    .map(PriceLots::get)
+            },
+            "price must be positive and fit in u64",
+            "price is not a multiple of the tick size",
+        )?);
+        let max_base_quantity = BaseLots::new(snap(
+            self.policy.size,
+            Rounding::Floor,
+            |rounding| {
+                conversion
+                    .base_size_number_to_lots(size, rounding)
+                    .map(BaseLots::get)
+            },
+            "size must be positive and fit in u64",
+            "size is not a multiple of the minimum order size",
+        )?);
+
+        if limit_price.is_zero() {
+            return Err(SdkError::InvalidOrder("price is below one tick"));
+        }
+        if max_base_quantity.is_zero() {
+            return Err(SdkError::InvalidOrder(
+                "size is below the minimum order size",
+            ));
+        }
+
+        let max_quote_quantity = self
+            .max_native_quote_qty_including_fees(limit_price, max_base_quantity)
+            .ok_or(SdkError::InvalidOrder("order value does not fit in u64"))?;
+        let overflow = || SdkError::InvalidOrder("order value does not fit in a decimal");
+        Ok(ValidatedOrder {
+            side,
+            limit_price,
+            max_base_quantity,
+            max_quote_quantity,
+            price: conversion
+                .price_lots_to_number(limit_price, Rounding::Nearest)
+                .ok_or_else(overflow)?,
+            size: conversion
+                .base_size_lots_to_number(max_base_quantity, Rounding::Nearest)
+                .ok_or_else(overflow)?,
+        })
+    }
+
+    /// Native quote for `size` at `price` plus the taker fee, rounded up.
+    pub fn max_native_quote_qty_including_fees(
+        &self,
+        price: PriceLots,
+        size: BaseLots,
+    ) -> Option<NativeQuote> {
+        let native = self.conversion.native_quote_for(price, size)?;
+        let fee = Rounding::Ceil.div(
+            u128::from(native.get()) * u128::from(self.taker_fee),
+            FEE_RATE_SCALE.into(),
+        )?;
+        native.checked_add(NativeQuote::new(u64::try_from(fee).ok()?))
+    }
+}
+
+/// Converts with the rounding `policy` asks for, where `conservative` is
+/// the rounding that stays within the user's request.
+fn snap(
+    policy: Snap,
+    conservative: Rounding,
+    convert: impl Fn(Rounding) -> Option<u64>,
+    invalid: &'static str,
+    off_grid: &'static str,
+) -> Result<u64, SdkError> {
+    let rounding = match policy {
+        Snap::Conservative => conservative,
+        Snap::Round(rounding) => rounding,
+        Snap::Reject => Rounding::Floor,
+    };
+    let value = convert(rounding).ok_or(SdkError::InvalidOrder(invalid))?;
+    if policy == Snap::Reject && convert(Rounding::Ceil) != Some(value) {
+        return Err(SdkError::InvalidOrder(off_grid));
+    }
+    Ok(value)
+}
@@ -0,0 +1,85 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::account::{decode_any, DexAccount};
+use crate::openbook::error::DecodeError;
+use crate::openbook::market::AccountFlags;
+use crate::openbook::slab::{SlabBuilder, SlabOrder};
+
+fn account(flags: AccountFlags, len: usize) -> Vec<u8> {
+    let mut data = vec![0u8; len];
+    data[..5].copy_from_slice(b"serum");
+    data[5..13].copy_from_slice(&flags.bits().to_le_bytes());
+    data[len - 7..].copy_from_slice(b"padding");
+    data
+}
+
+#[test]
+fn accounts_are_dispatched_on_their_flags_and_length() {
+    let program_id = Pubkey::new_unique();
+    let market = AccountFlags::INITIALIZED | AccountFlags::MARKET;
+
+    match decode_any(&program_id, &account(market, 388)).unwrap() {
+        DexAccount::Market(state) => assert_eq!(state.authority, None),
+        other => panic!("expected a market, got {:?}", other),
+    }
+    match decode_any(
+        &program_id,
+        &account(market | AccountFlags::PERMISSIONED, 1476),
+    )
+    .unwrap()
+    {
+        DexAccount::Market(state) => assert_eq!(state.authority, Some(Pubkey::default())),
+        other => panic!("expected a permissioned market, got {:?}", other),
+    }
+
+    let open_orders = account(AccountFlags::INITIALIZED | AccountFlags::OPEN_ORDERS, 3228);
+    assert!(matches!(
+        decode_any(&program_id, &open_orders),
+        Ok(DexAccount::OpenOrders(state)) if state.referrer_rebates_accrued == Some(0)
+    ));
+
+    let event_queue = account(
+        AccountFlags::INITIALIZED | AccountFlags::EVENT_QUEUE,
+        37 + 88 * 2 + 7,
+    );
+    assert!(matches!(
+        decode_any(&program_id, &event_queue),
+        Ok(DexAccount::EventQueue { events, .. }) if events.is_empty()
+    ));
+
+    let bids = SlabBuilder::new(true)
+        .order(SlabOrder {
+            price_lots: 10,
+            seq_num: 1,
+            owner: Pubkey::new_unique(),
+            owner_slot: 0,
+            fee_tier: 0,
+            quantity: 5,
+            client_order_id: 0,
+        })
+        .build()
+        .unwrap();
+    assert!(matches!(
+        decode_any(&program_id, &bids),
+        Ok(DexAccount::Bids(slab)) if slab.iter().count() == 1
+    ));
+}
+
+#[test]
+fn unrecognized_accounts_are_rejected() {
+    let program_id = Pubkey::new_unique();
+    assert_eq!(
+        decode_any(&program_id, &account(AccountFlags::INITIALIZED, 64)),
+        Err(DecodeError::UnknownAccountType(AccountFlags::INITIALIZED))
+    );
+    assert!(matches!(
+        decode_any(
+            &program_id,
+            &account(AccountFlags::INITIALIZED | AccountFlags::MARKET, 400)
+        ),
+        Err(DecodeError::InvalidLength {
+            expected: 388,
+            actual: 400
+        })
+    ));
+}
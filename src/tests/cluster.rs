@@ -0,0 +1,47 @@
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::cluster::{Cluster, SRM_MINT};
+use crate::openbook::registry::ProgramRegistry;
+
+#[test]
+fn presets_list_their_programs_and_markets() {
+    let mainnet = Cluster::mainnet();
+    let openbook = Pubkey::from_str("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX").unwrap();
+    let sol_usdc = Pubkey::from_str("8BnEgHoWFysVcuFFX7QztDmzuH8r5ZFvyP3sYwn1XTh6").unwrap();
+
+    assert_eq!(mainnet.program_id(), openbook);
+    assert_eq!(mainnet.programs[0].layout_version, 3);
+    assert_eq!(mainnet.srm_mint, Some(SRM_MINT));
+    assert_eq!(
+        mainnet.market("SOL/USDC").map(|m| m.address),
+        Some(sol_usdc)
+    );
+    assert_eq!(
+        mainnet.market_by_address(&sol_usdc).unwrap().name,
+        "SOL/USDC"
+    );
+    assert_eq!(
+        mainnet.program_id_for_market(&Pubkey::new_unique()),
+        openbook
+    );
+
+    let devnet = Cluster::devnet();
+    assert!(devnet.srm_mint.is_none() && devnet.msrm_mint.is_none());
+    assert_eq!(devnet.wrapped_sol_mint, mainnet.wrapped_sol_mint);
+    assert!(devnet.market("SOL/USDC").is_none());
+}
+
+#[test]
+fn localnet_programs_can_be_registered() {
+    let program_id = Pubkey::new_unique();
+    let localnet = Cluster::localnet(program_id);
+    let registry = ProgramRegistry::new();
+
+    assert_eq!(localnet.rpc_url, "http://127.0.0.1:8899");
+    assert!(!registry.is_known(&program_id));
+    localnet.register_programs(&registry);
+    assert_eq!(registry.layout_version(&program_id), 3);
+    assert!(!registry.uses_request_queue(&program_id));
+}
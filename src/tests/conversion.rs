@@ -0,0 +1,166 @@
+use rust_decimal::Decimal;
+
+use crate::openbook::conversion::{LotConversion, Rounding};
+use crate::openbook::order::Side;
+use crate::openbook::units::{BaseLots, NativeBase, NativeQuote, PriceLots, QuoteLots};
+
+use super::{dec, sol_usdc};
+
+#[test]
+fn limit_prices_round_towards_the_users_side() {
+    let conversion = sol_usdc();
+    let price = dec("23.416");
+
+    assert_eq!(Rounding::for_limit_price(Side::Buy), Rounding::Floor);
+    assert_eq!(
+        conversion.price_number_to_lots(price, Rounding::for_limit_price(Side::Buy)),
+        Some(PriceLots::new(2341))
+    );
+    assert_eq!(
+        conversion.price_number_to_lots(price, Rounding::for_limit_price(Side::Sell)),
+        Some(PriceLots::new(2342))
+    );
+    assert_eq!(
+        conversion.price_number_to_lots(dec("23.415"), Rounding::Nearest),
+        Some(PriceLots::new(2342))
+    );
+    assert_eq!(
+        conversion.price_number_to_lots(dec("23.41"), Rounding::Ceil),
+        Some(PriceLots::new(2341))
+    );
+    assert_eq!(
+        conversion.price_lots_to_number(PriceLots::new(2341), Rounding::Floor),
+        Some(dec("23.41"))
+    );
+
+    assert_eq!(
+        conversion.base_size_number_to_lots(dec("12.55"), Rounding::Floor),
+        Some(BaseLots::new(125))
+    );
+    assert_eq!(
+        conversion.base_size_number_to_lots(dec("12.55"), Rounding::Ceil),
+        Some(BaseLots::new(126))
+    );
+    assert_eq!(
+        conversion.base_size_lots_to_number(BaseLots::new(125), Rounding::Floor),
+        Some(dec("12.5"))
+    );
+    assert_eq!(
+        conversion.quote_size_number_to_lots(dec("0.0015"), Rounding::Nearest),
+        Some(QuoteLots::new(2))
+    );
+    assert_eq!(
+        conversion.quote_size_lots_to_number(QuoteLots::new(2), Rounding::Floor),
+        Some(dec("0.002"))
+    );
+    assert_eq!(
+        conversion.quote_size_number_to_spl_size(dec("22.5"), Rounding::Floor),
+        Some(NativeQuote::new(22_500_000))
+    );
+    assert_eq!(
+        conversion.native_price_to_number(
+            NativeQuote::new(23_410_000),
+            NativeBase::new(1_000_000_000),
+            Rounding::Nearest
+        ),
+        Some(dec("23.41"))
+    );
+}
+
+#[test]
+fn lots_convert_to_native_amounts() {
+    let conversion = sol_usdc();
+
+    assert_eq!(
+        conversion.base_lots_to_native(BaseLots::new(125)),
+        Some(NativeBase::new(12_500_000_000))
+    );
+    assert_eq!(
+        conversion.native_base_to_lots(NativeBase::new(12_550_000_000), Rounding::Floor),
+        Some(BaseLots::new(125))
+    );
+    assert_eq!(
+        conversion.quote_lots_to_native(QuoteLots::new(3)),
+        Some(NativeQuote::new(3_000))
+    );
+    assert_eq!(
+        conversion.native_quote_to_lots(NativeQuote::new(3_001), Rounding::Ceil),
+        Some(QuoteLots::new(4))
+    );
+    // 12.5 SOL at 23.41 USDC.
+    assert_eq!(
+        conversion.quote_lots_for(PriceLots::new(2341), BaseLots::new(125)),
+        Some(QuoteLots::new(292_625))
+    );
+    assert_eq!(
+        conversion.native_quote_for(PriceLots::new(2341), BaseLots::new(125)),
+        Some(NativeQuote::new(292_625_000))
+    );
+    assert_eq!(
+        conversion.native_quote_for(PriceLots::new(u64::MAX), BaseLots::new(2)),
+        None
+    );
+
+    let sizes = [BaseLots::new(1), BaseLots::new(2), BaseLots::new(3)];
+    assert_eq!(sizes.iter().copied().sum::<BaseLots>(), BaseLots::new(6));
+    assert_eq!(BaseLots::new(1).checked_sub(BaseLots::new(2)), None);
+    assert_eq!(
+        BaseLots::new(1).saturating_sub(BaseLots::new(2)),
+        BaseLots::ZERO
+    );
+}
+
+#[test]
+fn high_decimal_tokens_convert_exactly() {
+    // 18 decimal base token with single unit lots, which f64 cannot represent.
+    let conversion = LotConversion::new(1, 1, 18, 6);
+    let size = dec("0.123456789123456789");
+
+    assert_eq!(
+        conversion.base_size_number_to_spl_size(size, Rounding::Floor),
+        Some(NativeBase::new(123_456_789_123_456_789))
+    );
+    assert_eq!(
+        conversion
+            .base_spl_size_to_number(NativeBase::new(123_456_789_123_456_789), Rounding::Floor),
+        Some(size)
+    );
+    assert_eq!(
+        conversion.base_size_number_to_spl_size(dec("0.1234567891234567895"), Rounding::Nearest),
+        Some(NativeBase::new(123_456_789_123_456_790))
+    );
+
+    // Ratios that do not terminate are rounded in the last of 28 digits.
+    let thirds = LotConversion::new(3, 1, 0, 0);
+    assert_eq!(
+        thirds.price_lots_to_number(PriceLots::new(1), Rounding::Floor),
+        Some(dec("0.3333333333333333333333333333"))
+    );
+    assert_eq!(
+        thirds.price_lots_to_number(PriceLots::new(1), Rounding::Ceil),
+        Some(dec("0.3333333333333333333333333334"))
+    );
+}
+
+#[test]
+fn invalid_inputs_do_not_convert() {
+    let conversion = sol_usdc();
+
+    assert_eq!(
+        conversion.price_number_to_lots(dec("-1"), Rounding::Floor),
+        None
+    );
+    assert_eq!(
+        conversion.price_number_to_lots(dec("-0"), Rounding::Floor),
+        Some(PriceLots::ZERO)
+    );
+    assert_eq!(
+        conversion.base_size_number_to_lots(Decimal::MAX, Rounding::Floor),
+        None
+    );
+    assert_eq!(
+        LotConversion::new(0, 1_000, 9, 6).price_lots_to_number(PriceLots::new(1), Rounding::Floor),
+        None
+    );
+    assert_eq!(Rounding::Nearest.div(5, 0), None);
+}
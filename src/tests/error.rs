@@ -0,0 +1,261 @@
+use std::str::FromStr;
+
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_request::{RpcError, RpcResponseErrorData},
+    rpc_response::RpcSimulateTransactionResult,
+};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    message::Message,
+    pubkey::Pubkey,
+    system_instruction::{self, SystemError},
+    transaction::{Transaction, TransactionError, VersionedTransaction},
+};
+use spl_token::error::TokenError;
+
+use crate::openbook::error::{
+    parse_instruction_error, DecodeError, DexError, DexErrorCode, ErrorClass, InstructionFailure,
+    ProgramFailure, SdkError,
+};
+use crate::openbook::instruction::TOKEN_PROGRAM_ID;
+
+fn dex_program_id() -> Pubkey {
+    Pubkey::from_str("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX").unwrap()
+}
+
+fn transaction(payer: &Pubkey) -> VersionedTransaction {
+    let instruction = |program_id| {
+        Instruction::new_with_bytes(program_id, &[], vec![AccountMeta::new(*payer, true)])
+    };
+    let message = Message::new(
+        &[
+            system_instruction::transfer(payer, &Pubkey::new_unique(), 1),
+            instruction(TOKEN_PROGRAM_ID),
+            instruction(dex_program_id()),
+            instruction(Pubkey::new_unique()),
+        ],
+        Some(payer),
+    );
+    Transaction::new_unsigned(message).into()
+}
+
+fn failure(
+    transaction: &VersionedTransaction,
+    index: u8,
+    error: InstructionError,
+) -> ProgramFailure {
+    let error = TransactionError::InstructionError(index, error);
+    parse_instruction_error(transaction, &error)
+        .unwrap()
+        .failure
+}
+
+#[test]
+fn dex_codes_carry_the_source_line_in_the_upper_bits() {
+    assert_eq!(
+        DexErrorCode::from_custom((1234 << 16) | 61),
+        DexErrorCode {
+            code: (1234 << 16) | 61,
+            error: DexError::WouldSelfTrade,
+            line: Some(1234),
+            file_id: None,
+        }
+    );
+    assert_eq!(
+        DexErrorCode::from_custom(50).error,
+        DexError::RequestQueueFull
+    );
+    assert_eq!(DexErrorCode::from_custom(50).line, None);
+    assert_eq!(
+        DexErrorCode::from_custom(62).error,
+        DexError::InvalidOpenOrdersAuthority
+    );
+    assert_eq!(
+        DexErrorCode::from_custom(63).error,
+        DexError::OrderMaxTimestampExceeded
+    );
+    assert_eq!(DexErrorCode::from_custom(999).error, DexError::Unknown);
+}
+
+#[test]
+fn dex_assertion_codes_carry_the_source_file_and_line() {
+    assert_eq!(
+        DexErrorCode::from_custom(0x0100_01a9),
+        DexErrorCode {
+            code: 0x0100_01a9,
+            error: DexError::AssertionError,
+            line: Some(425),
+            file_id: Some(1),
+        }
+    );
+    // A low code that is also a valid error is still an assertion once a
+    // file id is set.
+    assert_eq!(
+        DexErrorCode::from_custom((2 << 24) | 34).error,
+        DexError::AssertionError
+    );
+    assert_eq!(DexErrorCode::from_custom((2 << 24) | 34).line, Some(34));
+}
+
+#[test]
+fn failures_are_decoded_for_the_program_that_raised_them() {
+    let payer = Pubkey::new_unique();
+    let transaction = transaction(&payer);
+
+    let error = TransactionError::InstructionError(2, InstructionError::Custom((87 << 16) | 34));
+    assert_eq!(
+        parse_instruction_error(&transaction, &error),
+        Some(InstructionFailure {
+            index: 2,
+            program_id: dex_program_id(),
+            failure: ProgramFailure::Dex(DexErrorCode {
+                code: (87 << 16) | 34,
+                error: DexError::InsufficientFunds,
+                line: Some(87),
+                file_id: None,
+            }),
+            log_errors: Vec::new(),
+        })
+    );
+    assert_eq!(
+        failure(&transaction, 0, InstructionError::Custom(1)),
+        ProgramFailure::System(SystemError::ResultWithNegativeLamports)
+    );
+    assert_eq!(
+        failure(&transaction, 1, InstructionError::Custom(1)),
+        ProgramFailure::Token(TokenError::InsufficientFunds)
+    );
+    assert_eq!(
+        failure(&transaction, 1, InstructionError::Custom(500)),
+        ProgramFailure::Custom(500)
+    );
+    assert_eq!(
+        failure(&transaction, 3, InstructionError::Custom(7)),
+        ProgramFailure::Custom(7)
+    );
+    assert_eq!(
+        failure(&transaction, 2, InstructionError::MissingRequiredSignature),
+        ProgramFailure::Instruction(InstructionError::MissingRequiredSignature)
+    );
+}
+
+#[test]
+fn errors_outside_the_instructions_are_not_parsed() {
+    let transaction = transaction(&Pubkey::new_unique());
+    assert_eq!(
+        parse_instruction_error(&transaction, &TransactionError::BlockhashNotFound),
+        None
+    );
+    let error = TransactionError::InstructionError(4, InstructionError::Custom(0));
+    assert_eq!(parse_instruction_error(&transaction, &error), None);
+}
+
+#[test]
+fn sdk_errors_expose_the_dex_error_and_the_failing_account() {
+    let transaction = transaction(&Pubkey::new_unique());
+
+    let error = SdkError::from_transaction_error(
+        &transaction,
+        TransactionError::InstructionError(2, InstructionError::Custom(51)),
+    );
+    assert_eq!(error.dex_error(), Some(DexError::EventQueueFull));
+    let error = SdkError::from_transaction_error(
+        &transaction,
+        TransactionError::InstructionError(1, InstructionError::Custom(1)),
+    );
+    assert_eq!(error.dex_error(), None);
+    assert!(matches!(
+        SdkError::from_transaction_error(&transaction, TransactionError::BlockhashNotFound),
+        SdkError::Transaction(TransactionError::BlockhashNotFound)
+    ));
+
+    let account = Pubkey::new_unique();
+    let error = SdkError::decode(account, DecodeError::InvalidHeadPadding);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "failed to decode account {}: account head padding is not \"serum\"",
+            account
+        )
+    );
+}
+
+fn preflight_failure(error: TransactionError, logs: &[&str]) -> ClientError {
+    ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        code: -32002,
+        message: "Transaction simulation failed".to_string(),
+        data: RpcResponseErrorData::SendTransactionPreflightFailure(RpcSimulateTransactionResult {
+            err: Some(error),
+            logs: Some(logs.iter().map(|line| line.to_string()).collect()),
+            accounts: None,
+            units_consumed: None,
+            return_data: None,
+            inner_instructions: None,
+        }),
+    })
+    .into()
+}
+
+#[test]
+fn errors_are_classified_for_retries() {
+    let transaction = transaction(&Pubkey::new_unique());
+    let dex_failure = |code| {
+        SdkError::from_transaction_error(
+            &transaction,
+            TransactionError::InstructionError(2, InstructionError::Custom(code)),
+        )
+    };
+
+    assert!(dex_failure(DexError::WrongSigner as u32).is_fatal());
+    assert!(dex_failure(DexError::OrderNotYours as u32).is_fatal());
+    assert!(dex_failure(DexError::InvalidMarketFlags as u32).is_fatal());
+    let queue_full = dex_failure(DexError::RequestQueueFull as u32);
+    assert!(queue_full.needs_crank() && !queue_full.is_retryable());
+    assert_eq!(
+        dex_failure(DexError::EventQueueFull as u32).class(),
+        ErrorClass::RetryAfterCrank
+    );
+    assert_eq!(
+        dex_failure(DexError::InsufficientFunds as u32).class(),
+        ErrorClass::Unclassified
+    );
+
+    assert!(SdkError::Transaction(TransactionError::BlockhashNotFound).is_retryable());
+    let node_behind: ClientError = ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        code: -32005,
+        message: "Node is behind by 42 slots".to_string(),
+        data: RpcResponseErrorData::NodeUnhealthy {
+            num_slots_behind: Some(42),
+        },
+    })
+    .into();
+    assert!(SdkError::from(node_behind).is_retryable());
+    let timeout: ClientError = ClientErrorKind::Io(std::io::ErrorKind::TimedOut.into()).into();
+    assert!(SdkError::from(timeout).is_retryable());
+    assert!(SdkError::decode(Pubkey::new_unique(), DecodeError::InvalidTailPadding).is_fatal());
+
+    let unconfirmed = SdkError::Unconfirmed("signature".to_string());
+    assert!(unconfirmed.needs_status_check() && !unconfirmed.is_retryable());
+}
+
+#[test]
+fn preflight_failures_are_decoded_with_their_logs() {
+    let transaction = transaction(&Pubkey::new_unique());
+    let error = preflight_failure(
+        TransactionError::InstructionError(2, InstructionError::Custom(50)),
+        &[
+            "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX invoke [1]",
+            "Program log: Error: RequestQueueFull",
+            "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX failed: custom program error: 0x32",
+        ],
+    );
+    let error = SdkError::from_send_error(&transaction, error);
+    assert_eq!(error.dex_error(), Some(DexError::RequestQueueFull));
+    assert!(error.needs_crank());
+
+    let error = preflight_failure(TransactionError::BlockhashNotFound, &[]);
+    let error = SdkError::from_send_error(&transaction, error);
+    assert!(matches!(error, SdkError::Rpc(_)));
+    assert!(error.is_retryable());
+}
@@ -0,0 +1,129 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::explain::Explainer;
+use crate::openbook::instruction::{
+    CancelOrderV2, CancelOrdersByClientIds, ConsumeEvents, Instruction, NewOrderV3, SendTake,
+};
+use crate::openbook::market::{AccountFlags, Market, MarketOptions, MarketState};
+use crate::openbook::order::{OrderType, SelfTradeBehavior, Side};
+use crate::openbook::transaction::{
+    AccountRole, DecodedInstruction, DexInstruction, LabeledAccount,
+};
+
+/// SOL/USDC: 0.1 SOL base lots, 0.001 USDC quote lots, so a price lot is
+/// 0.01 USDC per SOL.
+fn sol_usdc() -> Market {
+    let decoded = MarketState {
+        account_flags: AccountFlags::INITIALIZED | AccountFlags::MARKET,
+        own_address: Pubkey::new_unique(),
+        vault_signer_nonce: 0,
+        base_mint: Pubkey::new_unique(),
+        quote_mint: Pubkey::new_unique(),
+        base_vault: Pubkey::new_unique(),
+        base_deposits_total: 0,
+        base_fees_accrued: 0,
+        quote_vault: Pubkey::new_unique(),
+        quote_deposits_total: 0,
+        quote_fees_accrued: 0,
+        quote_dust_threshold: 100,
+        request_queue: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        base_lot_size: 100_000_000,
+        quote_lot_size: 1_000,
+        fee_rate_bps: 0,
+        referrer_rebates_accrued: Some(0),
+        authority: None,
+        prune_authority: None,
+        consume_events_authority: None,
+    };
+    let options = MarketOptions {
+        skip_preflight: None,
+        commitment: None,
+    };
+    Market::new(decoded, 9, 6, options, Pubkey::new_unique()).unwrap()
+}
+
+fn post_only_buy() -> NewOrderV3 {
+    NewOrderV3 {
+        side: Side::Buy,
+        limit_price: 2341,
+        max_base_quantity: 125,
+        max_quote_quantity: 292_625_000,
+        self_trade_behavior: SelfTradeBehavior::CancelProvide,
+        order_type: OrderType::PostOnly,
+        client_order_id: 42,
+        limit: 10,
+        max_ts: i64::MAX,
+    }
+}
+
+#[test]
+fn orders_are_explained_in_market_units() {
+    let market = sol_usdc();
+    let explainer = Explainer::new(&market, "SOL", "USDC");
+
+    assert_eq!(
+        explainer.explain(&Instruction::NewOrderV3(post_only_buy())),
+        "NewOrderV3 BUY 12.5 SOL @ 23.41 USDC, PostOnly, client_id=42, STP=CancelProvide"
+    );
+    assert_eq!(
+        explainer.explain(&Instruction::SendTake(SendTake {
+            side: Side::Sell,
+            limit_price: 2300,
+            max_base_quantity: 10,
+            max_quote_quantity: 0,
+            min_base_quantity: 10,
+            min_quote_quantity: 22_500_000,
+            limit: 5,
+        })),
+        "SendTake SELL 1 SOL @ 23 USDC, max_quote=0 USDC, min_base=1 SOL, min_quote=22.5 USDC, limit=5"
+    );
+    assert_eq!(
+        explainer.explain(&Instruction::CancelOrderV2(CancelOrderV2 {
+            side: Side::Sell,
+            order_id: (2341u128 << 64) | 7,
+        })),
+        "CancelOrderV2 SELL @ 23.41 USDC, order_id=0x9250000000000000007"
+    );
+
+    let mut client_ids = [0; 8];
+    client_ids[..2].copy_from_slice(&[3, 4]);
+    assert_eq!(
+        explainer.explain(&Instruction::CancelOrdersByClientIds(
+            CancelOrdersByClientIds { client_ids }
+        )),
+        "CancelOrdersByClientIds client_ids=[3, 4]"
+    );
+    assert_eq!(
+        explainer.explain(&Instruction::ConsumeEvents(ConsumeEvents { limit: 8 })),
+        "ConsumeEvents limit=8"
+    );
+}
+
+#[test]
+fn only_instructions_for_the_market_are_explained() {
+    let market = sol_usdc();
+    let explainer = Explainer::new(&market, "SOL", "USDC");
+    let decoded = |index, market| DecodedInstruction {
+        index,
+        program_id: Pubkey::new_unique(),
+        instruction: DexInstruction::Known(Instruction::NewOrderV3(post_only_buy())),
+        accounts: vec![LabeledAccount {
+            role: AccountRole::Market,
+            pubkey: market,
+            is_signer: false,
+            is_writable: true,
+        }],
+    };
+
+    let explained = explainer.explain_transaction(&[
+        decoded(0, Pubkey::new_unique()),
+        decoded(1, market.address()),
+    ]);
+    assert_eq!(
+        explained,
+        vec!["NewOrderV3 BUY 12.5 SOL @ 23.41 USDC, PostOnly, client_id=42, STP=CancelProvide"]
+    );
+}
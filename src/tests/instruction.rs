@@ -0,0 +1,371 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::error::{DecodeError, EncodeError};
+use crate::openbook::instruction::{
+    CancelOrder, CancelOrderByClientId, CancelOrderByClientIdV2, CancelOrderV2,
+    CancelOrdersByClientIds, CancelOrdersByClientIdsParams, CloseOpenOrders, ConsumeEvents,
+    ConsumeEventsPermissioned, DexInstructions, InitOpenOrders, InitializeMarket, Instruction,
+    MatchOrders, NewOrder, NewOrderParams, NewOrderV3, NewOrderV3Params, Prune,
+    ReplaceOrderByClientId, ReplaceOrdersByClientIds, SendTake, SettleFunds, SettleFundsParams,
+    DEFAULT_MATCH_LIMIT, NEW_ORDER_OPEN_ORDERS_INDEX, NEW_ORDER_OWNER_INDEX,
+    NEW_ORDER_V3_OPEN_ORDERS_INDEX, NEW_ORDER_V3_OWNER_INDEX, SETTLE_FUNDS_BASE_WALLET_INDEX,
+    SETTLE_FUNDS_QUOTE_WALLET_INDEX, TOKEN_PROGRAM_ID,
+};
+use crate::openbook::order::{OrderType, SelfTradeBehavior, Side};
+use crate::openbook::units::{BaseLots, NativeQuote, PriceLots};
+
+fn new_order_v3_params(
+    replace_if_exists: bool,
+    fee_discount_pubkey: Option<Pubkey>,
+) -> NewOrderV3Params {
+    NewOrderV3Params {
+        market: Pubkey::new_unique(),
+        open_orders: Pubkey::new_unique(),
+        request_queue: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        payer: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        base_vault: Pubkey::new_unique(),
+        quote_vault: Pubkey::new_unique(),
+        side: Side::Buy,
+        limit_price: PriceLots::new(2341),
+        max_base_quantity: BaseLots::new(125),
+        max_quote_quantity: NativeQuote::new(292_625),
+        self_trade_behavior: SelfTradeBehavior::CancelProvide,
+        order_type: OrderType::PostOnly,
+        client_id: 42,
+        limit: DEFAULT_MATCH_LIMIT,
+        max_ts: i64::MAX,
+        replace_if_exists,
+        fee_discount_pubkey,
+        program_id: Pubkey::new_unique(),
+    }
+}
+
+#[test]
+fn new_order_v3_accounts_follow_the_index_constants() {
+    let params = new_order_v3_params(false, None);
+    let (open_orders, owner, program_id) = (params.open_orders, params.owner, params.program_id);
+    let instruction = DexInstructions::new_order_v3(params);
+
+    assert_eq!(instruction.program_id, program_id);
+    assert_eq!(instruction.accounts.len(), 12);
+    let open_orders_meta = &instruction.accounts[NEW_ORDER_V3_OPEN_ORDERS_INDEX as usize];
+    assert_eq!(open_orders_meta.pubkey, open_orders);
+    assert!(open_orders_meta.is_writable && !open_orders_meta.is_signer);
+    let owner_meta = &instruction.accounts[NEW_ORDER_V3_OWNER_INDEX as usize];
+    assert_eq!(owner_meta.pubkey, owner);
+    assert!(owner_meta.is_signer && !owner_meta.is_writable);
+    assert_eq!(instruction.accounts[10].pubkey, TOKEN_PROGRAM_ID);
+    assert_eq!(
+        instruction
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_signer)
+            .count(),
+        1
+    );
+    assert_eq!(&instruction.data[..5], &[0, 10, 0, 0, 0]);
+}
+
+#[test]
+fn new_order_accounts_follow_the_index_constants() {
+    let open_orders = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let request_queue = Pubkey::new_unique();
+    let instruction = DexInstructions::new_order(NewOrderParams {
+        market: Pubkey::new_unique(),
+        open_orders,
+        request_queue,
+        payer: Pubkey::new_unique(),
+        owner,
+        base_vault: Pubkey::new_unique(),
+        quote_vault: Pubkey::new_unique(),
+        side: Side::Buy,
+        limit_price: PriceLots::new(100),
+        max_quantity: BaseLots::new(5),
+        order_type: OrderType::Limit,
+        client_id: 7,
+        fee_discount_pubkey: None,
+        program_id: Pubkey::new_unique(),
+    });
+
+    assert_eq!(instruction.accounts.len(), 9);
+    assert_eq!(
+        instruction.accounts[NEW_ORDER_OPEN_ORDERS_INDEX as usize].pubkey,
+        open_orders
+    );
+    let owner_meta = &instruction.accounts[NEW_ORDER_OWNER_INDEX as usize];
+    assert_eq!(owner_meta.pubkey, owner);
+    assert!(owner_meta.is_signer && !owner_meta.is_writable);
+    assert_eq!(instruction.accounts[2].pubkey, request_queue);
+    assert_eq!(instruction.accounts[7].pubkey, TOKEN_PROGRAM_ID);
+}
+
+#[test]
+fn replace_if_exists_switches_to_replace_order_by_client_id() {
+    let fee_discount = Pubkey::new_unique();
+    let instruction = DexInstructions::new_order_v3(new_order_v3_params(true, Some(fee_discount)));
+
+    assert_eq!(&instruction.data[..5], &[0, 19, 0, 0, 0]);
+    assert_eq!(instruction.accounts.len(), 13);
+    let fee_discount_meta = instruction.accounts.last().unwrap();
+    assert_eq!(fee_discount_meta.pubkey, fee_discount);
+    assert!(!fee_discount_meta.is_writable && !fee_discount_meta.is_signer);
+}
+
+#[test]
+fn settle_funds_places_wallets_and_optional_referrer() {
+    let base_wallet = Pubkey::new_unique();
+    let quote_wallet = Pubkey::new_unique();
+    let referrer = Pubkey::new_unique();
+    let instruction = DexInstructions::settle_funds(SettleFundsParams {
+        market: Pubkey::new_unique(),
+        open_orders: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        base_vault: Pubkey::new_unique(),
+        quote_vault: Pubkey::new_unique(),
+        base_wallet,
+        quote_wallet,
+        vault_signer: Pubkey::new_unique(),
+        referrer_quote_wallet: Some(referrer),
+        program_id: Pubkey::new_unique(),
+    });
+
+    assert_eq!(
+        instruction.accounts[SETTLE_FUNDS_BASE_WALLET_INDEX as usize].pubkey,
+        base_wallet
+    );
+    assert_eq!(
+        instruction.accounts[SETTLE_FUNDS_QUOTE_WALLET_INDEX as usize].pubkey,
+        quote_wallet
+    );
+    assert!(instruction.accounts[2].is_signer);
+    assert_eq!(instruction.accounts[9].pubkey, referrer);
+    assert!(instruction.accounts[9].is_writable);
+    assert_eq!(instruction.data, vec![0, 5, 0, 0, 0]);
+}
+
+fn cancel_by_client_ids_params(client_ids: Vec<u64>) -> CancelOrdersByClientIdsParams {
+    CancelOrdersByClientIdsParams {
+        market: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        open_orders: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        client_ids,
+        program_id: Pubkey::new_unique(),
+    }
+}
+
+#[test]
+fn cancel_orders_by_client_ids_pads_to_eight_ids() {
+    let instruction =
+        DexInstructions::cancel_orders_by_client_ids(cancel_by_client_ids_params(vec![7, 8]))
+            .unwrap();
+
+    assert_eq!(instruction.data.len(), 5 + 8 * 8);
+    assert_eq!(&instruction.data[..5], &[0, 18, 0, 0, 0]);
+    assert_eq!(instruction.data[5], 7);
+    assert_eq!(instruction.data[13], 8);
+    assert!(instruction.data[21..].iter().all(|byte| *byte == 0));
+    assert!(instruction.accounts[4].is_signer);
+}
+
+#[test]
+fn cancel_orders_by_client_ids_rejects_more_than_eight_ids() {
+    let params = cancel_by_client_ids_params((1..=9).collect());
+    assert_eq!(
+        DexInstructions::cancel_orders_by_client_ids(params).unwrap_err(),
+        EncodeError::TooManyClientIds { count: 9, max: 8 }
+    );
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn new_order_v3() -> NewOrderV3 {
+    NewOrderV3 {
+        side: Side::Buy,
+        limit_price: 2341,
+        max_base_quantity: 125,
+        max_quote_quantity: 292_625,
+        self_trade_behavior: SelfTradeBehavior::CancelProvide,
+        order_type: OrderType::PostOnly,
+        client_order_id: 42,
+        limit: DEFAULT_MATCH_LIMIT,
+        max_ts: i64::MAX,
+    }
+}
+
+#[test]
+fn every_variant_round_trips() {
+    let instructions = vec![
+        Instruction::InitializeMarket(InitializeMarket {
+            base_lot_size: 100,
+            quote_lot_size: 10,
+            fee_rate_bps: 22,
+            vault_signer_nonce: 3,
+            quote_dust_threshold: 5,
+        }),
+        Instruction::NewOrder(NewOrder {
+            side: Side::Sell,
+            limit_price: 99,
+            max_quantity: 1,
+            order_type: OrderType::Ioc,
+            client_id: 7,
+        }),
+        Instruction::MatchOrders(MatchOrders { limit: 5 }),
+        Instruction::ConsumeEvents(ConsumeEvents { limit: 10 }),
+        Instruction::CancelOrder(CancelOrder {
+            side: Side::Buy,
+            order_id: u128::MAX - 1,
+            owner: Pubkey::new_unique(),
+            owner_slot: 127,
+        }),
+        Instruction::SettleFunds(SettleFunds),
+        Instruction::CancelOrderByClientId(CancelOrderByClientId { client_id: 1 }),
+        Instruction::NewOrderV3(new_order_v3()),
+        Instruction::CancelOrderV2(CancelOrderV2 {
+            side: Side::Sell,
+            order_id: 1 << 70,
+        }),
+        Instruction::CancelOrderByClientIdV2(CancelOrderByClientIdV2 {
+            client_id: u64::MAX,
+        }),
+        Instruction::SendTake(SendTake {
+            side: Side::Buy,
+            limit_price: 1,
+            max_base_quantity: 2,
+            max_quote_quantity: 3,
+            min_base_quantity: 4,
+            min_quote_quantity: 5,
+            limit: 6,
+        }),
+        Instruction::CloseOpenOrders(CloseOpenOrders),
+        Instruction::InitOpenOrders(InitOpenOrders),
+        Instruction::Prune(Prune { limit: 3 }),
+        Instruction::ConsumeEventsPermissioned(ConsumeEventsPermissioned { limit: 4 }),
+        Instruction::CancelOrdersByClientIds(CancelOrdersByClientIds {
+            client_ids: [1, 2, 3, 4, 5, 6, 7, 8],
+        }),
+        Instruction::ReplaceOrderByClientId(ReplaceOrderByClientId {
+            order: new_order_v3(),
+        }),
+        Instruction::ReplaceOrdersByClientIds(ReplaceOrdersByClientIds {
+            orders: vec![
+                new_order_v3(),
+                NewOrderV3 {
+                    side: Side::Sell,
+                    ..new_order_v3()
+                },
+            ],
+        }),
+    ];
+
+    let mut tags: Vec<u32> = instructions.iter().map(Instruction::tag).collect();
+    tags.dedup();
+    assert_eq!(tags.len(), instructions.len());
+
+    for instruction in instructions {
+        let data = instruction.pack();
+        assert_eq!(data[0], 0);
+        assert_eq!(&data[1..5], &instruction.tag().to_le_bytes());
+        assert_eq!(Instruction::unpack_from_slice(&data), Ok(instruction));
+    }
+}
+
+#[test]
+fn packed_data_matches_the_deployed_program() {
+    assert_eq!(
+        Instruction::NewOrderV3(new_order_v3()).pack(),
+        hex(concat!(
+            "00",
+            "0a000000",
+            "00000000",
+            "2509000000000000",
+            "7d00000000000000",
+            "1177040000000000",
+            "01000000",
+            "02000000",
+            "2a00000000000000",
+            "ffff",
+            "ffffffffffffff7f",
+        ))
+    );
+    assert_eq!(
+        Instruction::CancelOrderV2(CancelOrderV2 {
+            side: Side::Sell,
+            order_id: (2341 << 64) | 7,
+        })
+        .pack(),
+        hex("000b0000000100000007000000000000002509000000000000")
+    );
+    assert_eq!(
+        Instruction::InitializeMarket(InitializeMarket {
+            base_lot_size: 100,
+            quote_lot_size: 10,
+            fee_rate_bps: 22,
+            vault_signer_nonce: 3,
+            quote_dust_threshold: 5,
+        })
+        .pack(),
+        hex("000000000064000000000000000a00000000000000160003000000000000000500000000000000")
+    );
+    assert_eq!(
+        Instruction::MatchOrders(MatchOrders { limit: 5 }).pack(),
+        hex("00020000000500")
+    );
+}
+
+#[test]
+fn new_order_v3_without_max_ts_never_expires() {
+    let mut data = Instruction::NewOrderV3(NewOrderV3 {
+        max_ts: 1_700_000_000,
+        ..new_order_v3()
+    })
+    .pack();
+    data.truncate(data.len() - 8);
+    assert_eq!(
+        Instruction::unpack_from_slice(&data),
+        Ok(Instruction::NewOrderV3(new_order_v3()))
+    );
+}
+
+#[test]
+fn malformed_instruction_data_is_rejected() {
+    assert_eq!(
+        Instruction::unpack_from_slice(&hex("01020000000500")),
+        Err(DecodeError::UnsupportedInstructionVersion(1))
+    );
+    assert_eq!(
+        Instruction::unpack_from_slice(&hex("0007000000")),
+        Err(DecodeError::UnknownInstructionTag(7))
+    );
+    assert_eq!(
+        Instruction::unpack_from_slice(&hex("0005000000ff")),
+        Err(DecodeError::InvalidInstructionLength { tag: 5, actual: 1 })
+    );
+    assert_eq!(
+        Instruction::unpack_from_slice(&hex(concat!(
+            "000b000000",
+            "02000000",
+            "00000000000000000000000000000000"
+        ))),
+        Err(DecodeError::InvalidInstructionField {
+            field: "side",
+            value: 2
+        })
+    );
+    assert!(matches!(
+        Instruction::unpack_from_slice(&hex("00020000")),
+        Err(DecodeError::InvalidLength { .. })
+    ));
+}
@@ -0,0 +1,104 @@
+use crate::openbook::l2::{aggregate_l2, L2Level, L2Options};
+use crate::openbook::order::Side;
+use crate::openbook::units::{BaseLots, PriceLots};
+
+use super::{dec, sol_usdc};
+
+fn orders(levels: &[(u64, u64)]) -> Vec<(PriceLots, BaseLots)> {
+    levels
+        .iter()
+        .map(|&(price, size)| (PriceLots::new(price), BaseLots::new(size)))
+        .collect()
+}
+
+fn summary(levels: &[L2Level]) -> Vec<(u64, u64, usize)> {
+    levels
+        .iter()
+        .map(|level| {
+            (
+                level.price_lots.get(),
+                level.size_lots.get(),
+                level.order_count,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn levels_merge_orders_at_the_same_price() {
+    let bids = orders(&[(2341, 10), (2341, 5), (2339, 20), (2338, 1)]);
+
+    let levels = aggregate_l2(&sol_usdc(), Side::Buy, bids.clone(), &L2Options::default());
+    assert_eq!(
+        summary(&levels),
+        vec![(2341, 15, 2), (2339, 20, 1), (2338, 1, 1)]
+    );
+    assert_eq!(
+        levels[0],
+        L2Level {
+            price: dec("23.41"),
+            size: dec("1.5"),
+            price_lots: PriceLots::new(2341),
+            size_lots: BaseLots::new(15),
+            order_count: 2,
+        }
+    );
+
+    let options = L2Options {
+        depth: Some(2),
+        cumulative: true,
+        ..L2Options::default()
+    };
+    let levels = aggregate_l2(&sol_usdc(), Side::Buy, bids, &options);
+    assert_eq!(summary(&levels), vec![(2341, 15, 2), (2339, 35, 3)]);
+    assert_eq!(levels[1].size, dec("3.5"));
+}
+
+#[test]
+fn grouped_levels_round_away_from_the_spread() {
+    let conversion = sol_usdc();
+    let options = L2Options::default()
+        .group_by(&conversion, dec("0.1"))
+        .unwrap();
+    assert_eq!(options.group, Some(PriceLots::new(10)));
+
+    let bids = orders(&[(2345, 1), (2340, 2), (2339, 3), (2301, 4)]);
+    let levels = aggregate_l2(&conversion, Side::Buy, bids, &options);
+    assert_eq!(
+        summary(&levels),
+        vec![(2340, 3, 2), (2330, 3, 1), (2300, 4, 1)]
+    );
+    assert_eq!(levels[0].price, dec("23.4"));
+
+    let asks = orders(&[(2341, 1), (2350, 2), (2351, 3)]);
+    let levels = aggregate_l2(&conversion, Side::Sell, asks.clone(), &options);
+    assert_eq!(summary(&levels), vec![(2350, 3, 2), (2360, 3, 1)]);
+
+    let options = L2Options {
+        depth: Some(1),
+        ..L2Options::default()
+    }
+    .group_by(&conversion, dec("1"))
+    .unwrap();
+    let levels = aggregate_l2(&conversion, Side::Sell, asks, &options);
+    assert_eq!(summary(&levels), vec![(2400, 6, 3)]);
+
+    // Widths below a tick become one tick, and empty widths are rejected.
+    assert_eq!(
+        L2Options::default()
+            .group_by(&conversion, dec("0.005"))
+            .unwrap()
+            .group,
+        Some(PriceLots::new(1))
+    );
+    assert_eq!(L2Options::default().group_by(&conversion, dec("0")), None);
+    assert_eq!(L2Options::default().group_by(&conversion, dec("-1")), None);
+
+    let top = orders(&[(u64::MAX - 1, 1)]);
+    let options = L2Options {
+        group: Some(PriceLots::new(10)),
+        ..L2Options::default()
+    };
+    let levels = aggregate_l2(&conversion, Side::Sell, top, &options);
+    assert_eq!(summary(&levels), vec![(u64::MAX, 1, 1)]);
+}
@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use solana_sdk::{instruction::InstructionError, pubkey::Pubkey, transaction::TransactionError};
+
+use crate::openbook::error::{parse_instruction_error, DexError, ProgramFailure};
+use crate::openbook::logs::{parse_logs, DexLogError, ProgramLog, SourceLocation};
+
+const DEX: &str = "srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX";
+const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const SYSTEM: &str = "11111111111111111111111111111111";
+
+fn logs(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn log_lines_are_mapped_to_their_instruction_and_depth() {
+    let logs = logs(&[
+        "Program 11111111111111111111111111111111 invoke [1]",
+        "Program 11111111111111111111111111111111 success",
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX invoke [1]",
+        "Program log: settling",
+        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+        "Program log: Instruction: Transfer",
+        "Program log: Error: insufficient funds",
+        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 2712 of 189250 compute units",
+        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1",
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX consumed 12000 of 200000 compute units",
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX failed: custom program error: 0x1",
+    ]);
+
+    let instructions = parse_logs(&logs);
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(
+        instructions[0].program_id,
+        Pubkey::from_str(SYSTEM).unwrap()
+    );
+    assert!(instructions[0].messages.is_empty());
+    assert_eq!(instructions[0].failure, None);
+
+    let settle = &instructions[1];
+    assert_eq!(settle.index, 1);
+    assert_eq!(
+        settle.messages[1],
+        ProgramLog {
+            depth: 2,
+            program_id: Pubkey::from_str(TOKEN).unwrap(),
+            message: "Instruction: Transfer".to_string(),
+        }
+    );
+    assert_eq!(settle.messages[0].depth, 1);
+    assert_eq!(settle.custom_error_code(), Some(1));
+    // The error line was written by the token program, not the DEX.
+    assert!(settle.dex_errors().is_empty());
+}
+
+#[test]
+fn dex_panics_and_error_lines_are_extracted() {
+    let logs = logs(&[
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX invoke [1]",
+        "Program log: Error: WouldSelfTrade",
+        "Program log: panicked at 'attempt to subtract with overflow', dex/src/state.rs:2419:13",
+        "Program log: panicked at dex/src/matching.rs:512:9:\nassertion failed: free_slot_bits != 0",
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX failed: Program failed to complete",
+    ]);
+
+    let instructions = parse_logs(&logs);
+    assert_eq!(instructions[0].custom_error_code(), None);
+    assert_eq!(
+        instructions[0].dex_errors(),
+        vec![
+            DexLogError::Error("WouldSelfTrade".to_string()),
+            DexLogError::Panic {
+                message: "attempt to subtract with overflow".to_string(),
+                location: Some(SourceLocation {
+                    file: "dex/src/state.rs".to_string(),
+                    line: 2419,
+                    column: 13,
+                }),
+            },
+            DexLogError::Panic {
+                message: "assertion failed: free_slot_bits != 0".to_string(),
+                location: Some(SourceLocation {
+                    file: "dex/src/matching.rs".to_string(),
+                    line: 512,
+                    column: 9,
+                }),
+            },
+        ]
+    );
+}
+
+#[test]
+fn truncated_logs_keep_the_instructions_seen() {
+    let logs = logs(&[
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX invoke [1]",
+        "Program log: Error: InsufficientFunds",
+        "Log truncated",
+        "Program log: Error: ignored",
+    ]);
+
+    let instructions = parse_logs(&logs);
+    assert_eq!(instructions.len(), 1);
+    assert_eq!(instructions[0].program_id, Pubkey::from_str(DEX).unwrap());
+    assert_eq!(
+        instructions[0].dex_errors(),
+        vec![DexLogError::Error("InsufficientFunds".to_string())]
+    );
+}
+
+#[test]
+fn logged_errors_are_attached_to_the_failure() {
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        transaction::{Transaction, VersionedTransaction},
+    };
+
+    let payer = Pubkey::new_unique();
+    let dex = Pubkey::from_str(DEX).unwrap();
+    let instruction = Instruction::new_with_bytes(dex, &[], vec![AccountMeta::new(payer, true)]);
+    let transaction: VersionedTransaction =
+        Transaction::new_unsigned(Message::new(&[instruction], Some(&payer))).into();
+    let logs = logs(&[
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX invoke [1]",
+        "Program log: Error: InsufficientFunds",
+        "Program srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX failed: custom program error: 0x22",
+    ]);
+
+    let error = TransactionError::InstructionError(0, InstructionError::Custom(0x22));
+    let failure = parse_instruction_error(&transaction, &error)
+        .unwrap()
+        .with_logs(&parse_logs(&logs));
+    assert!(matches!(
+        failure.failure,
+        ProgramFailure::Dex(code) if code.error == DexError::InsufficientFunds
+    ));
+    assert_eq!(
+        failure.log_errors,
+        vec![DexLogError::Error("InsufficientFunds".to_string())]
+    );
+}
@@ -0,0 +1,209 @@
+use bytemuck::Zeroable;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+
+use crate::openbook::error::DecodeError;
+use crate::openbook::market::{
+    AccountFlags, LayoutExt, Market, MarketLayout, MarketOptions, MarketState, MarketStateBody,
+    MarketStateLayoutV1, MarketStateLayoutV2, MarketStateLayoutV3,
+};
+use crate::openbook::order::OpenOrdersLayout;
+
+fn body(flags: AccountFlags, own_address: Pubkey) -> MarketStateBody {
+    let mut body = MarketStateBody::zeroed();
+    body.account_flags = flags.bits();
+    body.own_address = own_address.to_bytes();
+    body.base_mint = Pubkey::new_from_array([1; 32]).to_bytes();
+    body.base_lot_size = 100_000_000;
+    body.quote_lot_size = 1_000;
+    body
+}
+
+#[test]
+fn account_flags_match_exactly_one_account_type() {
+    let market = AccountFlags::INITIALIZED | AccountFlags::MARKET;
+    assert_eq!(market.expect_market(), Ok(()));
+    assert_eq!(
+        (market | AccountFlags::PERMISSIONED | AccountFlags::CRANK_AUTHORITY_REQUIRED)
+            .expect_market(),
+        Ok(())
+    );
+    assert_eq!(
+        market.expect_bids(),
+        Err(DecodeError::WrongAccountType {
+            expected: AccountFlags::BIDS,
+            actual: market,
+        })
+    );
+
+    let asks = AccountFlags::INITIALIZED | AccountFlags::ASKS;
+    assert_eq!(asks.expect_orderbook(), Ok(false));
+    assert!(AccountFlags::ASKS.expect_asks().is_err());
+    assert!((asks | AccountFlags::BIDS).expect_orderbook().is_err());
+    assert!((asks | AccountFlags::DISABLED).expect_asks().is_err());
+    assert!(
+        (AccountFlags::INITIALIZED | AccountFlags::OPEN_ORDERS | AccountFlags::CLOSED)
+            .expect_open_orders()
+            .is_err()
+    );
+}
+
+#[test]
+fn undefined_account_flag_bits_are_rejected() {
+    assert_eq!(
+        AccountFlags::from_raw(0b11),
+        Ok(AccountFlags::INITIALIZED | AccountFlags::MARKET)
+    );
+    assert_eq!(
+        AccountFlags::from_raw(1 << 11),
+        Err(DecodeError::UnknownAccountFlags(1 << 11))
+    );
+}
+
+#[test]
+fn market_states_decode_from_every_layout() {
+    let address = Pubkey::new_unique();
+    let market = AccountFlags::INITIALIZED | AccountFlags::MARKET;
+
+    let mut v1 = MarketStateLayoutV1::zeroed();
+    v1.head_padding = *b"serum";
+    v1.body = body(market, address);
+    v1.tail_padding = *b"padding";
+    let state = MarketState::decode(bytemuck::bytes_of(&v1), MarketLayout::V1).unwrap();
+    assert_eq!(state.own_address, address);
+    assert_eq!(state.base_mint, Pubkey::new_from_array([1; 32]));
+    assert_eq!(
+        (state.base_lot_size, state.quote_lot_size),
+        (100_000_000, 1_000)
+    );
+    assert_eq!(state.referrer_rebates_accrued, None);
+
+    let mut v2 = MarketStateLayoutV2::zeroed();
+    v2.head_padding = *b"serum";
+    v2.body = body(market, address);
+    v2.referrer_rebates_accrued = 42;
+    v2.tail_padding = *b"padding";
+    let state = MarketState::decode(bytemuck::bytes_of(&v2), MarketLayout::V2).unwrap();
+    assert_eq!(state.referrer_rebates_accrued, Some(42));
+    assert_eq!(state.authority, None);
+
+    let authority = Pubkey::new_unique();
+    let mut v3 = MarketStateLayoutV3::zeroed();
+    v3.head_padding = *b"serum";
+    let permissioned = market | AccountFlags::PERMISSIONED | AccountFlags::CRANK_AUTHORITY_REQUIRED;
+    v3.body = body(permissioned, address);
+    v3.referrer_rebates_accrued = 7;
+    v3.authority = authority.to_bytes();
+    v3.consume_events_authority = authority.to_bytes();
+    v3.tail_padding = *b"padding";
+    let data = bytemuck::bytes_of(&v3);
+    let state = MarketState::decode(data, MarketLayout::V3).unwrap();
+    assert_eq!(state.account_flags, permissioned);
+    assert_eq!(state.authority, Some(authority));
+    assert_eq!(state.prune_authority, Some(Pubkey::default()));
+    assert_eq!(state.consume_events_authority, Some(authority));
+
+    assert_eq!(
+        MarketState::decode(data, MarketLayout::V2),
+        Err(DecodeError::InvalidLength {
+            expected: 388,
+            actual: 1476
+        })
+    );
+}
+
+#[test]
+fn v3_markets_without_a_crank_authority_have_no_permissioned_crank() {
+    let mut v3 = MarketStateLayoutV3::zeroed();
+    v3.head_padding = *b"serum";
+    v3.body = body(
+        AccountFlags::INITIALIZED | AccountFlags::MARKET | AccountFlags::PERMISSIONED,
+        Pubkey::new_unique(),
+    );
+    v3.authority = Pubkey::new_unique().to_bytes();
+    v3.tail_padding = *b"padding";
+    let state = MarketState::decode(bytemuck::bytes_of(&v3), MarketLayout::V3).unwrap();
+    assert_eq!(state.consume_events_authority, None);
+
+    let market = Market::new(state, 9, 6, MarketOptions::default(), Pubkey::new_unique()).unwrap();
+    assert_eq!(
+        market.make_consume_events_permissioned_instruction(vec![Pubkey::new_unique()], 10),
+        None
+    );
+}
+
+#[test]
+fn markets_use_confirmed_commitment_unless_given_one() {
+    let mut v1 = MarketStateLayoutV1::zeroed();
+    v1.head_padding = *b"serum";
+    v1.body = body(
+        AccountFlags::INITIALIZED | AccountFlags::MARKET,
+        Pubkey::new_unique(),
+    );
+    v1.tail_padding = *b"padding";
+    let state = MarketState::decode(bytemuck::bytes_of(&v1), MarketLayout::V1).unwrap();
+
+    let market = Market::new(state, 9, 6, MarketOptions::default(), Pubkey::new_unique()).unwrap();
+    assert_eq!(market.commitment(), CommitmentConfig::confirmed());
+
+    let options = MarketOptions {
+        commitment: Some(CommitmentConfig::finalized()),
+        ..MarketOptions::default()
+    };
+    let market = Market::new(state, 9, 6, options, Pubkey::new_unique()).unwrap();
+    assert_eq!(market.commitment(), CommitmentConfig::finalized());
+}
+
+#[test]
+fn market_layouts_follow_program_version_and_length() {
+    assert_eq!(MarketLayout::for_version(1), MarketLayout::V1);
+    assert_eq!(MarketLayout::for_version(3), MarketLayout::V2);
+    assert_eq!(MarketLayout::for_account(1, 1476), MarketLayout::V1);
+    assert_eq!(MarketLayout::for_account(2, 388), MarketLayout::V2);
+    assert_eq!(MarketLayout::for_account(3, 1476), MarketLayout::V3);
+}
+
+#[test]
+fn layout_offsets_match_the_serum_program() {
+    for layout in [MarketLayout::V1, MarketLayout::V2, MarketLayout::V3] {
+        assert_eq!(layout.offset_of("account_flags"), Ok(5));
+        assert_eq!(layout.offset_of("own_address"), Ok(13));
+        assert_eq!(layout.offset_of("base_mint"), Ok(53));
+        assert_eq!(layout.offset_of("quote_mint"), Ok(85));
+        assert_eq!(layout.offset_of("request_queue"), Ok(221));
+        assert_eq!(layout.offset_of("bids"), Ok(285));
+        assert_eq!(layout.offset_of("fee_rate_bps"), Ok(365));
+    }
+    assert_eq!(
+        MarketLayout::V2.offset_of("referrer_rebates_accrued"),
+        Ok(373)
+    );
+    assert_eq!(MarketLayout::V3.offset_of("authority"), Ok(381));
+    assert_eq!(
+        MarketLayout::V3.offset_of("consume_events_authority"),
+        Ok(445)
+    );
+    assert_eq!(
+        MarketLayout::V1.offset_of("referrer_rebates_accrued"),
+        Err(DecodeError::UnknownField(
+            "referrer_rebates_accrued".to_string()
+        ))
+    );
+
+    for layout in [OpenOrdersLayout::V1, OpenOrdersLayout::V2] {
+        assert_eq!(layout.offset_of("head_padding"), Ok(0));
+        assert_eq!(layout.offset_of("market"), Ok(13));
+        assert_eq!(layout.offset_of("owner"), Ok(45));
+        assert_eq!(layout.offset_of("orders"), Ok(141));
+        assert_eq!(layout.offset_of("client_ids"), Ok(2189));
+    }
+    assert_eq!(OpenOrdersLayout::V1.offset_of("tail_padding"), Ok(3213));
+    assert_eq!(
+        OpenOrdersLayout::V2.offset_of("referrer_rebates_accrued"),
+        Ok(3213)
+    );
+    assert_eq!(OpenOrdersLayout::V2.offset_of("tail_padding"), Ok(3221));
+    assert_eq!(
+        (OpenOrdersLayout::V1.span(), OpenOrdersLayout::V2.span()),
+        (3220, 3228)
+    );
+}
@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::error::SdkError;
+use crate::openbook::market_list::MarketList;
+
+const MARKETS_JSON: &str = r#"[
+  {
+    "address": "9wFFyRfZBsuAha4YcuxcXLKwMxJR43S7fPfQLusDBzvT",
+    "deprecated": true,
+    "name": "SOL/USDC",
+    "programId": "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin"
+  },
+  {
+    "address": "8BnEgHoWFysVcuFFX7QztDmzuH8r5ZFvyP3sYwn1XTh6",
+    "deprecated": false,
+    "name": "SOL/USDC",
+    "programId": "srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX"
+  },
+  {
+    "address": "ByRys5tuUWDgL73G8JBAEfkdFf8JWBzPBDHsBVQ5vbQA",
+    "name": "SRM/USDC",
+    "programId": "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin"
+  }
+]"#;
+
+#[test]
+fn markets_are_found_by_name_and_address() {
+    let list = MarketList::from_json(MARKETS_JSON).unwrap();
+    let old = Pubkey::from_str("9wFFyRfZBsuAha4YcuxcXLKwMxJR43S7fPfQLusDBzvT").unwrap();
+    let openbook = Pubkey::from_str("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX").unwrap();
+
+    assert_eq!(list.len(), 3);
+    assert_eq!(list.get("SOL/USDC").unwrap().program_id, openbook);
+    assert!(list.get_by_address(&old).unwrap().deprecated);
+    assert!(!list.get("SRM/USDC").unwrap().deprecated);
+    assert!(list.get("BTC/USDC").is_none());
+    assert_eq!(
+        list.active()
+            .map(|market| market.name.as_str())
+            .collect::<Vec<_>>(),
+        ["SOL/USDC", "SRM/USDC"]
+    );
+}
+
+#[test]
+fn malformed_lists_are_rejected() {
+    assert!(matches!(
+        MarketList::from_json(r#"[{"name": "SOL/USDC"}]"#),
+        Err(SdkError::MarketList(_))
+    ));
+    assert!(matches!(
+        MarketList::from_json(
+            r#"[{"name": "SOL/USDC", "address": "not a key", "programId": "11111111111111111111111111111111"}]"#
+        ),
+        Err(SdkError::InvalidPubkey(_))
+    ));
+}
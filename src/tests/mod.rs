@@ -0,0 +1,32 @@
+mod account;
+mod cluster;
+mod conversion;
+mod error;
+mod explain;
+mod instruction;
+mod l2;
+mod logs;
+mod market;
+mod market_list;
+mod order_id;
+mod queue;
+mod registry;
+mod slab;
+mod transaction;
+mod validation;
+
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::openbook::conversion::LotConversion;
+
+fn dec(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
+
+/// SOL/USDC: 0.1 SOL base lots and 0.001 USDC quote lots, so a price lot
+/// (one tick) is 0.01 USDC per SOL.
+fn sol_usdc() -> LotConversion {
+    LotConversion::new(100_000_000, 1_000, 9, 6)
+}
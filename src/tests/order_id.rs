@@ -0,0 +1,59 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::order::Side;
+use crate::openbook::order_id::OrderId;
+use crate::openbook::slab::{SlabBuilder, SlabOrder};
+use crate::openbook::units::PriceLots;
+
+fn order(price_lots: u64, seq_num: u64) -> SlabOrder {
+    SlabOrder {
+        price_lots,
+        seq_num,
+        owner: Pubkey::new_unique(),
+        owner_slot: 0,
+        fee_tier: 0,
+        quantity: 1,
+        client_order_id: seq_num,
+    }
+}
+
+#[test]
+fn order_ids_split_into_price_and_sequence_number() {
+    let ask = OrderId::new(Side::Sell, PriceLots::new(2341), 7);
+    assert_eq!(ask, OrderId((2341u128 << 64) | 7));
+    assert_eq!(ask.price_lots(), PriceLots::new(2341));
+    assert_eq!(ask.seq_num(Side::Sell), 7);
+    assert_eq!(ask.side_hint(), Side::Sell);
+    assert_eq!(format!("{:#x}", ask), "0x9250000000000000007");
+
+    let bid = OrderId::new(Side::Buy, PriceLots::new(2341), 7);
+    assert_eq!(bid, OrderId((2341u128 << 64) | u128::from(!7u64)));
+    assert_eq!(bid.price_lots(), PriceLots::new(2341));
+    assert_eq!(bid.seq_num(Side::Buy), 7);
+    assert_eq!(bid.side_hint(), Side::Buy);
+
+    // Earlier bids at the same price sort first when walking bids downwards.
+    assert!(OrderId::new(Side::Buy, PriceLots::new(2341), 6) > bid);
+    assert!(OrderId::new(Side::Buy, PriceLots::new(2342), 8) > bid);
+}
+
+#[test]
+fn order_ids_find_resting_orders() {
+    for side in [Side::Buy, Side::Sell] {
+        let slab = SlabBuilder::new(side == Side::Buy)
+            .orders(vec![order(101, 1), order(103, 2), order(101, 3)])
+            .build_slab()
+            .unwrap();
+
+        let leaf = slab
+            .get(OrderId::new(side, PriceLots::new(101), 3).get())
+            .unwrap();
+        assert_eq!(leaf.client_order_id, 3);
+        assert_eq!(leaf.order_id().price_lots(), PriceLots::new(101));
+        assert_eq!(leaf.order_id().seq_num(side), 3);
+        assert_eq!(leaf.order_id().side_hint(), side);
+        assert!(slab
+            .get(OrderId::new(side, PriceLots::new(103), 1).get())
+            .is_none());
+    }
+}
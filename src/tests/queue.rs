@@ -0,0 +1,174 @@
+use crate::openbook::error::DecodeError;
+use crate::openbook::order::{OrderType, Side};
+use crate::openbook::order_id::OrderId;
+use crate::openbook::queue::{
+    decode_event_queue, decode_request_queue, EventFlags, RequestFlags, RequestKind,
+};
+
+fn event_queue(head: u32, count: u32, seq_num: u32, alloc_len: usize) -> Vec<u8> {
+    let mut data = b"serum".to_vec();
+    data.extend_from_slice(&0x11u64.to_le_bytes());
+    for value in [head, count, seq_num] {
+        data.extend_from_slice(&value.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+    }
+    for slot in 0..alloc_len {
+        let mut event = [0u8; 88];
+        event[0] = if slot % 2 == 0 { 0b0101 } else { 0b0010 };
+        event[1] = slot as u8;
+        event[8..16].copy_from_slice(&(slot as u64 * 100).to_le_bytes());
+        data.extend_from_slice(&event);
+    }
+    data.extend_from_slice(b"padding");
+    data
+}
+
+fn slots_and_seq_nums(data: &[u8], history: Option<usize>) -> Vec<(u8, u32)> {
+    decode_event_queue(data, history)
+        .unwrap()
+        .iter()
+        .map(|event| (event.open_orders_slot, event.seq_num))
+        .collect()
+}
+
+#[test]
+fn pending_events_wrap_around_the_ring() {
+    let data = event_queue(3, 2, 10, 4);
+    assert_eq!(slots_and_seq_nums(&data, None), vec![(3, 8), (0, 9)]);
+
+    let events = decode_event_queue(&data, None).unwrap();
+    assert_eq!(events[0].event_flags, EventFlags::OUT);
+    assert_eq!(events[1].event_flags, EventFlags::FILL | EventFlags::BID);
+    assert_eq!(events[0].native_quantity_released, 300);
+}
+
+#[test]
+fn history_walks_back_from_the_newest_event() {
+    let data = event_queue(3, 2, 10, 4);
+    assert_eq!(
+        slots_and_seq_nums(&data, Some(3)),
+        vec![(0, 9), (3, 8), (2, 7)]
+    );
+    assert_eq!(slots_and_seq_nums(&data, Some(100)).len(), 4);
+}
+
+#[test]
+fn history_of_a_fresh_queue_stops_at_the_first_event() {
+    // Three events pushed into slots 0 to 2, two of them consumed.
+    let data = event_queue(2, 1, 3, 8);
+    assert_eq!(
+        slots_and_seq_nums(&data, Some(5)),
+        vec![(2, 2), (1, 1), (0, 0)]
+    );
+    assert_eq!(
+        slots_and_seq_nums(&event_queue(0, 0, 0, 8), Some(5)),
+        vec![]
+    );
+}
+
+#[test]
+fn malformed_event_queues_are_rejected() {
+    assert!(matches!(
+        decode_event_queue(&event_queue(0, 5, 5, 4), None),
+        Err(DecodeError::QueueOverflow {
+            count: 5,
+            capacity: 4,
+            ..
+        })
+    ));
+    assert!(matches!(
+        decode_event_queue(&event_queue(3, 2, 10, 4)[..40], None),
+        Err(DecodeError::InvalidLength { .. })
+    ));
+}
+
+fn request_queue(requests: &[(u8, u64)], next_seq_num: u32) -> Vec<u8> {
+    let mut data = b"serum".to_vec();
+    data.extend_from_slice(&0x09u64.to_le_bytes());
+    for value in [0, requests.len() as u32, next_seq_num] {
+        data.extend_from_slice(&value.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+    }
+    for (flags, max_base_size_or_cancel_id) in requests {
+        let mut request = [0u8; 80];
+        request[0] = *flags;
+        request[8..16].copy_from_slice(&max_base_size_or_cancel_id.to_le_bytes());
+        request[24..40].copy_from_slice(&(42u128 << 64).to_le_bytes());
+        data.extend_from_slice(&request);
+    }
+    data.extend_from_slice(b"padding");
+    data
+}
+
+#[test]
+fn requests_decode_into_new_order_and_cancel_kinds() {
+    let data = request_queue(&[(0b0_1101, 5), (0b0_0010, 77)], 3);
+    let requests = decode_request_queue(&data, None).unwrap();
+
+    assert_eq!(requests[0].seq_num, 1);
+    assert_eq!(
+        requests[0].flags,
+        RequestFlags::NEW_ORDER | RequestFlags::BID | RequestFlags::POST_ONLY
+    );
+    assert_eq!(
+        requests[0].kind,
+        RequestKind::NewOrder {
+            side: Side::Buy,
+            order_type: OrderType::PostOnly,
+            max_base_size: 5,
+            native_quote_quantity_locked: 0,
+        }
+    );
+    assert_eq!(requests[0].order_id, OrderId(42u128 << 64));
+    assert_eq!(
+        requests[1].kind,
+        RequestKind::CancelOrder {
+            side: Side::Sell,
+            cancel_id: 77
+        }
+    );
+
+    assert_eq!(
+        decode_request_queue(&request_queue(&[(0b0_0011, 0)], 1), None).unwrap_err(),
+        DecodeError::InvalidRequestFlags(0b0_0011)
+    );
+}
+
+#[test]
+fn request_history_skips_slots_that_do_not_decode() {
+    // Two requests pushed and consumed, then a third pending one, in a queue
+    // whose fourth slot was never written.
+    let mut data = request_queue(&[(0b0_0010, 1), (0b0_0001, 2), (0b0_0010, 3), (0, 0)], 3);
+    data[13..17].copy_from_slice(&2u32.to_le_bytes());
+    data[21..25].copy_from_slice(&1u32.to_le_bytes());
+
+    let pending = decode_request_queue(&data, None).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].max_base_size_or_cancel_id, 3);
+
+    // A corrupt slot in history is skipped rather than failing the rest.
+    data[37 + 80] = 0b0_0011;
+    let history = decode_request_queue(&data, Some(10)).unwrap();
+    let seq_nums: Vec<u32> = history.iter().map(|request| request.seq_num).collect();
+    assert_eq!(seq_nums, vec![2, 0]);
+    assert!(decode_request_queue(&data, None).is_ok());
+}
+
+#[test]
+fn queues_reject_the_other_queue_type() {
+    assert!(matches!(
+        decode_request_queue(&event_queue(0, 1, 1, 2), None),
+        Err(DecodeError::WrongAccountType { .. })
+    ));
+    assert!(matches!(
+        decode_event_queue(&request_queue(&[(0b0001, 1)], 1), None),
+        Err(DecodeError::WrongAccountType { .. })
+    ));
+
+    let mut data = event_queue(0, 1, 1, 2);
+    data[5 + 6] = 0x80;
+    assert_eq!(
+        decode_event_queue(&data, None).unwrap_err(),
+        DecodeError::UnknownAccountFlags(0x0080_0000_0000_0011)
+    );
+}
@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::account::{decode_any, DexAccount};
+use crate::openbook::market::AccountFlags;
+use crate::openbook::registry::{ProgramInfo, ProgramRegistry, DEFAULT_LAYOUT_VERSION};
+
+#[test]
+fn known_programs_carry_their_features() {
+    let registry = ProgramRegistry::with_known_programs();
+    let v1 = Pubkey::from_str("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn").unwrap();
+    let v2 = Pubkey::from_str("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o").unwrap();
+    let openbook = Pubkey::from_str("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX").unwrap();
+
+    assert_eq!(registry.program_ids().len(), 4);
+    assert_eq!(
+        registry.get(&v1),
+        Some(ProgramInfo {
+            program_id: v1,
+            layout_version: 1,
+            supports_srm_fee_discounts: false,
+            supports_referral_fees: false,
+            uses_request_queue: true,
+        })
+    );
+    assert!(registry.supports_referral_fees(&v2) && registry.uses_request_queue(&v2));
+    assert_eq!(registry.layout_version(&openbook), 3);
+    assert!(!registry.uses_request_queue(&openbook));
+
+    let unknown = Pubkey::new_unique();
+    assert!(!registry.is_known(&unknown));
+    assert_eq!(registry.layout_version(&unknown), DEFAULT_LAYOUT_VERSION);
+    assert!(ProgramRegistry::new().program_ids().is_empty());
+}
+
+#[test]
+fn forks_registered_at_runtime_are_used_by_the_decoders() {
+    let fork = Pubkey::new_unique();
+    let mut data = vec![0u8; 3220];
+    data[..5].copy_from_slice(b"serum");
+    let flags = AccountFlags::INITIALIZED | AccountFlags::OPEN_ORDERS;
+    data[5..13].copy_from_slice(&flags.bits().to_le_bytes());
+    data[3213..].copy_from_slice(b"padding");
+
+    // Unregistered programs get the V2 open orders layout, which is longer.
+    assert!(decode_any(&fork, &data).is_err());
+
+    let info = ProgramInfo {
+        supports_referral_fees: true,
+        ..ProgramInfo::new(fork, 1)
+    };
+    ProgramRegistry::global().register(info);
+    assert_eq!(ProgramRegistry::global().get(&fork), Some(info));
+    assert!(matches!(
+        decode_any(&fork, &data),
+        Ok(DexAccount::OpenOrders(state)) if state.referrer_rebates_accrued.is_none()
+    ));
+}
@@ -0,0 +1,109 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::openbook::error::EncodeError;
+use crate::openbook::market::AccountFlags;
+use crate::openbook::slab::{Slab, SlabBuilder, SlabNode, SlabOrder};
+
+fn order(price_lots: u64, seq_num: u64, quantity: u64) -> SlabOrder {
+    SlabOrder {
+        price_lots,
+        seq_num,
+        owner: Pubkey::new_unique(),
+        owner_slot: (seq_num % 128) as u8,
+        fee_tier: 0,
+        quantity,
+        client_order_id: seq_num + 1000,
+    }
+}
+
+fn prices(slab: &Slab, descending: bool) -> Vec<(u64, u64)> {
+    slab.items(descending)
+        .map(|leaf| ((leaf.key >> 64) as u64, leaf.quantity))
+        .collect()
+}
+
+#[test]
+fn asks_round_trip_through_decoder() {
+    let builder = SlabBuilder::new(false)
+        .orders(vec![
+            order(105, 1, 10),
+            order(101, 2, 20),
+            order(103, 3, 30),
+            order(101, 4, 40),
+        ])
+        .capacity(16);
+    let data = builder.build().unwrap();
+
+    let (account_flags, slab) = Slab::decode_account(&data).unwrap();
+    assert_eq!(
+        account_flags,
+        AccountFlags::INITIALIZED | AccountFlags::ASKS
+    );
+    assert_eq!(slab.header, builder.build_slab().unwrap().header);
+    assert_eq!(slab.nodes, builder.build_slab().unwrap().nodes);
+    assert_eq!(slab.header.leaf_count, 4);
+    assert_eq!(
+        prices(&slab, false),
+        vec![(101, 20), (101, 40), (103, 30), (105, 10)]
+    );
+    assert_eq!((slab.find_min().unwrap().key >> 64) as u64, 101);
+}
+
+#[test]
+fn bids_keep_time_priority_within_a_price() {
+    let data = SlabBuilder::new(true)
+        .orders(vec![order(99, 7, 1), order(100, 8, 2), order(99, 5, 3)])
+        .build()
+        .unwrap();
+
+    let (account_flags, slab) = Slab::decode_account(&data).unwrap();
+    assert!(account_flags.contains(AccountFlags::BIDS));
+    assert_eq!(prices(&slab, true), vec![(100, 2), (99, 3), (99, 1)]);
+    assert_eq!(slab.find_max().unwrap().quantity, 2);
+
+    let key = ((99u128) << 64) | !5u64 as u128;
+    assert_eq!(slab.get(key).unwrap().quantity, 3);
+    assert!(slab.get(((99u128) << 64) | 5).is_none());
+}
+
+#[test]
+fn free_list_is_chained_after_tree_nodes() {
+    let slab = SlabBuilder::new(false)
+        .orders(vec![order(1, 1, 1), order(2, 2, 1)])
+        .free_nodes(3)
+        .build_slab()
+        .unwrap();
+
+    assert_eq!(slab.header.bump_index, 6);
+    assert_eq!(slab.header.free_list_len, 3);
+    assert_eq!(slab.header.free_list_head, 5);
+    assert_eq!(slab.nodes[5], SlabNode::FreeNode { next: 4 });
+    assert_eq!(slab.nodes[4], SlabNode::FreeNode { next: 3 });
+    assert_eq!(slab.nodes[3], SlabNode::LastFreeNode);
+    assert_eq!(slab.items(false).count(), 2);
+}
+
+#[test]
+fn empty_book_and_builder_errors() {
+    let data = SlabBuilder::new(false).capacity(4).build().unwrap();
+    let (_, slab) = Slab::decode_account(&data).unwrap();
+    assert_eq!(slab.items(false).count(), 0);
+    assert!(slab.find_min().is_none());
+
+    let duplicate = SlabBuilder::new(false).orders(vec![order(1, 1, 1), order(1, 1, 2)]);
+    assert_eq!(
+        duplicate.build().unwrap_err(),
+        EncodeError::DuplicateKey(1u128 << 64 | 1)
+    );
+
+    let too_small = SlabBuilder::new(false)
+        .orders(vec![order(1, 1, 1), order(2, 2, 1)])
+        .capacity(2);
+    assert_eq!(
+        too_small.build().unwrap_err(),
+        EncodeError::CapacityExceeded {
+            required: 3,
+            capacity: 2
+        }
+    );
+}
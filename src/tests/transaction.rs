@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+use solana_sdk::{
+    address_lookup_table::AddressLookupTableAccount,
+    hash::Hash,
+    instruction::AccountMeta,
+    message::{v0, v0::LoadedAddresses, Message, VersionedMessage},
+    pubkey::Pubkey,
+    system_instruction,
+};
+
+use crate::openbook::error::DecodeError;
+use crate::openbook::instruction::{
+    CancelOrderByClientIdV2Params, ConsumeEventsParams, DexInstructions, Instruction, SettleFunds,
+};
+use crate::openbook::transaction::{account_roles, decode_message, AccountRole, DexInstruction};
+
+fn dex_program_id() -> Pubkey {
+    Pubkey::from_str("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX").unwrap()
+}
+
+fn cancel_by_client_id(owner: Pubkey, market: Pubkey) -> solana_sdk::instruction::Instruction {
+    DexInstructions::cancel_order_by_client_id_v2(CancelOrderByClientIdV2Params {
+        market,
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        open_orders: Pubkey::new_unique(),
+        owner,
+        event_queue: Pubkey::new_unique(),
+        client_id: 42,
+        program_id: dex_program_id(),
+    })
+}
+
+#[test]
+fn legacy_transactions_skip_other_programs_and_label_accounts() {
+    let owner = Pubkey::new_unique();
+    let market = Pubkey::new_unique();
+    let message = Message::new(
+        &[
+            system_instruction::transfer(&owner, &Pubkey::new_unique(), 1),
+            cancel_by_client_id(owner, market),
+        ],
+        Some(&owner),
+    );
+
+    let decoded = decode_message(&VersionedMessage::Legacy(message), None).unwrap();
+    assert_eq!(decoded.len(), 1);
+    let cancel = &decoded[0];
+    assert_eq!(cancel.index, 1);
+    assert_eq!(cancel.program_id, dex_program_id());
+    assert!(matches!(
+        cancel.instruction,
+        DexInstruction::Known(Instruction::CancelOrderByClientIdV2(ref inner))
+            if inner.client_id == 42
+    ));
+    assert_eq!(cancel.account(AccountRole::Market), Some(&market));
+    let owner_account = cancel.accounts[4];
+    assert_eq!(
+        (
+            owner_account.role,
+            owner_account.pubkey,
+            owner_account.is_signer
+        ),
+        (AccountRole::Owner, owner, true)
+    );
+    assert!(cancel.accounts[0].is_writable);
+}
+
+#[test]
+fn lookup_table_accounts_are_resolved_from_loaded_addresses() {
+    let owner = Pubkey::new_unique();
+    let market = Pubkey::new_unique();
+    let instruction = cancel_by_client_id(owner, market);
+    let table = AddressLookupTableAccount {
+        key: Pubkey::new_unique(),
+        addresses: instruction
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .filter(|key| *key != owner)
+            .collect(),
+    };
+    let message = v0::Message::try_compile(
+        &owner,
+        &[instruction],
+        std::slice::from_ref(&table),
+        Hash::default(),
+    )
+    .unwrap();
+    let lookup = &message.address_table_lookups[0];
+    let loaded = LoadedAddresses {
+        writable: lookup
+            .writable_indexes
+            .iter()
+            .map(|i| table.addresses[*i as usize])
+            .collect(),
+        readonly: lookup
+            .readonly_indexes
+            .iter()
+            .map(|i| table.addresses[*i as usize])
+            .collect(),
+    };
+    let message = VersionedMessage::V0(message);
+
+    let decoded = decode_message(&message, Some(&loaded)).unwrap();
+    assert_eq!(decoded[0].account(AccountRole::Market), Some(&market));
+    assert_eq!(decoded[0].account(AccountRole::Owner), Some(&owner));
+    // The owner pays the fees, which makes every account writable.
+    assert!(decoded[0]
+        .accounts
+        .iter()
+        .all(|account| account.is_writable));
+
+    assert_eq!(
+        decode_message(&message, None),
+        Err(DecodeError::LoadedAddressesMismatch {
+            expected: loaded.len(),
+            actual: 0
+        })
+    );
+}
+
+#[test]
+fn undecodable_instructions_are_kept_and_decoding_goes_on() {
+    let owner = Pubkey::new_unique();
+    let market = Pubkey::new_unique();
+    // DisableMarket, which the SDK does not decode.
+    let disable_market = solana_sdk::instruction::Instruction::new_with_bytes(
+        dex_program_id(),
+        &[0, 8, 0, 0, 0],
+        vec![AccountMeta::new(market, false)],
+    );
+    let truncated =
+        solana_sdk::instruction::Instruction::new_with_bytes(dex_program_id(), &[0, 8], vec![]);
+    let message = Message::new(
+        &[
+            disable_market,
+            truncated,
+            cancel_by_client_id(owner, market),
+        ],
+        Some(&owner),
+    );
+
+    let decoded = decode_message(&VersionedMessage::Legacy(message), None).unwrap();
+    assert_eq!(decoded.len(), 3);
+    assert_eq!(
+        decoded[0].instruction,
+        DexInstruction::Unknown {
+            tag: Some(8),
+            data: vec![0, 8, 0, 0, 0]
+        }
+    );
+    assert_eq!(decoded[0].accounts[0].role, AccountRole::Unknown);
+    assert_eq!(decoded[0].accounts[0].pubkey, market);
+    assert_eq!(
+        decoded[1].instruction,
+        DexInstruction::Unknown {
+            tag: None,
+            data: vec![0, 8]
+        }
+    );
+    assert_eq!(decoded[2].index, 2);
+    assert!(matches!(
+        decoded[2].instruction.known(),
+        Some(Instruction::CancelOrderByClientIdV2(_))
+    ));
+    assert_eq!(decoded[2].account(AccountRole::Market), Some(&market));
+}
+
+#[test]
+fn crank_roles_count_open_orders_from_the_front() {
+    let instruction = DexInstructions::consume_events(ConsumeEventsParams {
+        market: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        coin_fee: Pubkey::new_unique(),
+        pc_fee: Pubkey::new_unique(),
+        open_orders_accounts: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        limit: 10,
+        program_id: dex_program_id(),
+    });
+    let instruction = Instruction::unpack_from_slice(&instruction.data).unwrap();
+
+    assert_eq!(
+        account_roles(&instruction, 6),
+        vec![
+            AccountRole::OpenOrders,
+            AccountRole::OpenOrders,
+            AccountRole::Market,
+            AccountRole::EventQueue,
+            AccountRole::BaseFeeReceivable,
+            AccountRole::QuoteFeeReceivable,
+        ]
+    );
+    assert_eq!(
+        account_roles(&Instruction::SettleFunds(SettleFunds), 11)[9..],
+        [AccountRole::ReferrerQuoteWallet, AccountRole::Unknown]
+    );
+}
@@ -0,0 +1,91 @@
+use crate::openbook::conversion::Rounding;
+use crate::openbook::error::SdkError;
+use crate::openbook::order::Side;
+use crate::openbook::units::{BaseLots, NativeQuote, PriceLots};
+use crate::openbook::validation::{OrderValidator, RoundingPolicy, Snap};
+
+use super::{dec, sol_usdc};
+
+#[test]
+fn orders_snap_to_ticks_and_lots_within_the_limit() {
+    let validator = OrderValidator::new(sol_usdc(), 0);
+
+    let bid = validator
+        .validate(Side::Buy, dec("23.416"), dec("12.57"))
+        .unwrap();
+    assert_eq!(bid.limit_price, PriceLots::new(2341));
+    assert_eq!(bid.max_base_quantity, BaseLots::new(125));
+    assert_eq!((bid.price, bid.size), (dec("23.41"), dec("12.5")));
+    // 292.625 USDC plus the 0.22% taker fee of tier 0, rounded up.
+    assert_eq!(bid.max_quote_quantity, NativeQuote::new(293_268_775));
+
+    let ask = validator
+        .validate(Side::Sell, dec("23.411"), dec("0.1"))
+        .unwrap();
+    assert_eq!(ask.limit_price, PriceLots::new(2342));
+    assert_eq!(ask.max_base_quantity, BaseLots::new(1));
+
+    // Tier 6 pays 0.1%.
+    assert_eq!(
+        OrderValidator::new(sol_usdc(), 6)
+            .max_native_quote_qty_including_fees(PriceLots::new(2341), BaseLots::new(125)),
+        Some(NativeQuote::new(292_917_625))
+    );
+}
+
+#[test]
+fn rounding_policies_are_applied() {
+    let nearest = OrderValidator::new(sol_usdc(), 0).with_policy(RoundingPolicy {
+        price: Snap::Round(Rounding::Nearest),
+        size: Snap::Round(Rounding::Ceil),
+    });
+    let bid = nearest
+        .validate(Side::Buy, dec("23.416"), dec("12.51"))
+        .unwrap();
+    assert_eq!(bid.limit_price, PriceLots::new(2342));
+    assert_eq!(bid.max_base_quantity, BaseLots::new(126));
+
+    let strict = OrderValidator::new(sol_usdc(), 0).with_policy(RoundingPolicy {
+        price: Snap::Reject,
+        size: Snap::Reject,
+    });
+    assert!(strict
+        .validate(Side::Buy, dec("23.41"), dec("12.5"))
+        .is_ok());
+    assert!(matches!(
+        strict.validate(Side::Buy, dec("23.416"), dec("12.5")),
+        Err(SdkError::InvalidOrder(
+            "price is not a multiple of the tick size"
+        ))
+    ));
+    assert!(matches!(
+        strict.validate(Side::Sell, dec("23.41"), dec("12.55")),
+        Err(SdkError::InvalidOrder(
+            "size is not a multiple of the minimum order size"
+        ))
+    ));
+}
+
+#[test]
+fn zero_lot_orders_are_rejected() {
+    let validator = OrderValidator::new(sol_usdc(), 0);
+
+    assert!(matches!(
+        validator.validate(Side::Buy, dec("0.009"), dec("1")),
+        Err(SdkError::InvalidOrder("price is below one tick"))
+    ));
+    assert!(matches!(
+        validator.validate(Side::Sell, dec("23"), dec("0.09")),
+        Err(SdkError::InvalidOrder(
+            "size is below the minimum order size"
+        ))
+    ));
+    assert!(matches!(
+        validator.validate(Side::Buy, dec("-23"), dec("1")),
+        Err(SdkError::InvalidOrder(_))
+    ));
+    assert!(validator
+        .validate(Side::Buy, dec("23"), dec("0.09"))
+        .unwrap_err()
+        .is_fatal());
+}